@@ -0,0 +1,41 @@
+//! Helpers for sets of squares stored as 64-bit masks, bit `n` being square `n`.
+
+pub const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+/// Iterates over the squares set in `bitboard`, from a1 to h8.
+pub fn squares(mut bitboard: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+
+        let square = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some(square)
+    })
+}
+
+pub fn file_mask(file: usize) -> u64 {
+    FILE_A << file
+}
+
+pub fn rank_mask(rank: usize) -> u64 {
+    0xFF << (rank * 8)
+}
+
+pub fn adjacent_files(file: usize) -> u64 {
+    let west = if file > 0 { file_mask(file - 1) } else { 0 };
+    let east = if file < 7 { file_mask(file + 1) } else { 0 };
+
+    west | east
+}
+
+/// The squares a king on `square` moves to.
+pub fn king_attacks(square: usize) -> u64 {
+    let (file, rank) = (square % 8, square / 8);
+    let files = file_mask(file) | adjacent_files(file);
+    let ranks =
+        (rank.saturating_sub(1)..=(rank + 1).min(7)).fold(0, |ranks, rank| ranks | rank_mask(rank));
+
+    files & ranks & !(1 << square)
+}
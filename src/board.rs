@@ -1,12 +1,17 @@
 use lazy_static::lazy_static;
-use std::cmp::min;
+use std::{cmp::min, sync::Arc};
 
 use crate::{
+    bitboard::squares,
     castling_rights::CastlingRights,
     colour::Colour,
     errors::{FenError, UndoMoveError},
+    evaluation,
     moves::{Move, MoveRecord},
+    nnue::{Network, NnueState},
     piece::Piece,
+    utils::{square_to_string, string_to_square},
+    zobrist::ZOBRIST,
     Result,
 };
 
@@ -42,7 +47,9 @@ lazy_static! {
 
 const NORTH: usize = 0;
 const SOUTH: usize = 1;
+#[allow(dead_code)]
 const WEST: usize = 2;
+#[allow(dead_code)]
 const EAST: usize = 3;
 const NORTH_WEST: usize = 4;
 const SOUTH_EAST: usize = 5;
@@ -61,62 +68,76 @@ const KNIGHTS_OFFSETS: [(i8, i8); 8] = [
 ];
 const KING_SQUARE: (usize, usize) = (4, 60);
 
+#[derive(Clone)]
 pub struct Board {
     pieces: [u8; 64],
     colour_to_move: bool,
     castling_rights: u8,
     en_passant_square: Option<u8>,
+    halfmove_clock: u16,
+    fullmove_number: u16,
     move_history: Vec<MoveRecord>,
+    /// Zobrist key of the pawns alone, kept in step by `set_piece`.
+    pawn_key: u64,
+    /// Accumulators of the network evaluating the board, if there is one.
+    nnue: Option<NnueState>,
 }
 
 impl Board {
+    /// The evaluation in centipawns from white's point of view, by the
+    /// network set with `set_network` or by the hand-crafted evaluation.
     pub fn evaluate(&self) -> i32 {
-        let white_evaluation = self.evaluate_colour(Colour::White);
-        let black_evaluation = self.evaluate_colour(Colour::Black);
-
-        white_evaluation - black_evaluation
+        match &self.nnue {
+            Some(nnue) => match nnue.evaluate(self.colour_to_move) {
+                score if self.colour_to_move == Colour::White => score,
+                score => -score,
+            },
+            None => evaluation::evaluate(self),
+        }
     }
 
-    fn evaluate_colour(&self, colour: bool) -> i32 {
-        let mut evaluation = 0;
-
-        let mut material = 0;
-        for piece in self.pieces {
-            if !Piece::is_colour_bool(piece, colour) {
-                continue;
-            }
+    /// Evaluates with `network` from now on, or with the hand-crafted
+    /// evaluation for `None`. The accumulators are only rebuilt when the
+    /// network changes.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = match (network, self.nnue.take()) {
+            (Some(network), Some(nnue)) if Arc::ptr_eq(&network, nnue.network()) => Some(nnue),
+            (Some(network), _) => Some(NnueState::new(network, &self.pieces)),
+            (None, _) => None,
+        };
+    }
 
-            material += match Piece::piece_type(piece) {
-                Piece::Pawn => Piece::PawnValue,
-                Piece::Knight => Piece::KnightValue,
-                Piece::Bishop => Piece::BishopValue,
-                Piece::Rook => Piece::RookValue,
-                Piece::Queen => Piece::QueenValue,
-                Piece::King => continue,
-                _ => unreachable!(),
-            };
+    /// A position with `pieces` on the board, `colour_to_move` to move and
+    /// neither castling nor en passant possible.
+    pub fn from_pieces(pieces: [u8; 64], colour_to_move: bool) -> Self {
+        Self {
+            pieces,
+            colour_to_move,
+            castling_rights: CastlingRights::WhiteCanNotCastle | CastlingRights::BlackCanNotCastle,
+            pawn_key: pawn_key(&pieces),
+            ..Self::default()
         }
-
-        evaluation += material;
-
-        evaluation
     }
 
-    pub fn from_fen(fen: &'static str) -> Result<Self> {
+    pub fn from_fen(fen: &str) -> Result<Self> {
         let splited_fen = fen.split_whitespace().collect::<Vec<_>>();
 
         use FenError::NotEnoughParts as FENotEnoughParts;
-        let board_seg = splited_fen.get(0).copied().ok_or(FENotEnoughParts)?;
+        let board_seg = splited_fen.first().copied().ok_or(FENotEnoughParts)?;
         let colour_to_move_seg = splited_fen.get(1).copied().ok_or(FENotEnoughParts)?;
         let castling_rights_seg = splited_fen.get(2).copied().ok_or(FENotEnoughParts)?;
-        let en_passant_squage_seg = splited_fen.get(3).copied().ok_or(FENotEnoughParts)?;
+        let en_passant_square_seg = splited_fen.get(3).copied().ok_or(FENotEnoughParts)?;
         let halfmove_clock_seg = splited_fen.get(4).copied().ok_or(FENotEnoughParts)?;
         let fullmove_number_seg = splited_fen.get(5).copied().ok_or(FENotEnoughParts)?;
 
         let colour_to_move = match colour_to_move_seg {
             "w" => Colour::White,
             "b" => Colour::Black,
-            _ => return Err(Box::new(FenError::NoSuchSide(colour_to_move_seg))),
+            _ => {
+                return Err(Box::new(FenError::NoSuchSide(
+                    colour_to_move_seg.to_string(),
+                )))
+            }
         };
 
         use CastlingRights as CR;
@@ -129,15 +150,39 @@ impl Board {
                     'k' => Ok(sum | CR::BlackCanShortCastle),
                     'q' => Ok(sum | CR::BlackCanLongCastle),
                     '-' => Ok(sum),
-                    _ => Err(FenError::BadCastlingCharacter(castling_rights_seg)),
+                    _ => Err(FenError::BadCastlingCharacter(
+                        castling_rights_seg.to_string(),
+                    )),
                 })?;
 
+        let en_passant_square = match en_passant_square_seg {
+            "-" => None,
+            square => Some(
+                string_to_square(square)
+                    .map_err(|_| FenError::BadEnPassant(en_passant_square_seg.to_string()))?,
+            ),
+        };
+
+        let halfmove_clock = halfmove_clock_seg
+            .parse()
+            .map_err(|_| FenError::BadHalfmove(halfmove_clock_seg.to_string()))?;
+
+        let fullmove_number = fullmove_number_seg
+            .parse()
+            .map_err(|_| FenError::BadFullmove(fullmove_number_seg.to_string()))?;
+
+        let bad_placement = || FenError::BadPlacement(board_seg.to_string());
+
+        if board_seg.split('/').count() != 8 {
+            return Err(Box::new(bad_placement()));
+        }
+
         let mut pieces = [0u8; 64];
         for (rank, mut rank_chars) in board_seg.split('/').map(|rank| rank.chars()).enumerate() {
             let mut file = 0usize;
 
             while file < 8 {
-                let c = rank_chars.next().unwrap();
+                let c = rank_chars.next().ok_or_else(bad_placement)?;
 
                 if let Some(n) = c.to_digit(10) {
                     file += n as usize;
@@ -172,6 +217,10 @@ impl Board {
             pieces,
             colour_to_move,
             castling_rights,
+            en_passant_square,
+            halfmove_clock,
+            fullmove_number,
+            pawn_key: pawn_key(&pieces),
             ..Default::default()
         };
 
@@ -318,8 +367,7 @@ impl Board {
                             }
                         }
                     } else if Piece::is_colour_bool(piece_on_target_square, !self.colour_to_move) {
-                        let m_move = Move::new(start_square as u16, target_square as u16);
-                        moves.push(m_move);
+                        push_pawn_move(&mut moves, start_square as u16, target_square as u16);
                         continue;
                     }
                 }
@@ -337,14 +385,13 @@ impl Board {
                     continue;
                 }
 
-                let m_move = Move::new(start_square as u16, target_square as u16);
-                moves.push(m_move);
+                push_pawn_move(&mut moves, start_square as u16, target_square as u16);
 
                 // Double forward move
 
                 let second_rank = match self.colour_to_move {
                     Colour::White => (8, 15),
-                    Colour::Black => (47, 55),
+                    Colour::Black => (48, 55),
                 };
 
                 if !(second_rank.0..=second_rank.1).contains(&start_square) {
@@ -372,150 +419,430 @@ impl Board {
         };
 
         let castling_rights = CastlingRights::rights(self.castling_rights, self.colour_to_move);
+        let opponent = !self.colour_to_move;
 
-        if CastlingRights::can_long_castle(castling_rights)
-            && self.pieces[castling_square - 2] == Piece::None
-            && self.pieces[castling_square - 3] == Piece::None
+        // The king may not castle out of, through or into check
+        if castling_rights != CastlingRights::CanNotCastle
+            && !self.is_square_attacked(castling_square as u8, opponent)
         {
-            let v_move = Move::LongCastle;
+            if CastlingRights::can_long_castle(castling_rights)
+                && self.pieces[castling_square - 1] == Piece::None
+                && self.pieces[castling_square - 2] == Piece::None
+                && self.pieces[castling_square - 3] == Piece::None
+                && !self.is_square_attacked(castling_square as u8 - 1, opponent)
+                && !self.is_square_attacked(castling_square as u8 - 2, opponent)
+            {
+                let v_move = Move::LongCastle;
+
+                moves.push(v_move);
+            }
+
+            if CastlingRights::can_short_castle(castling_rights)
+                && self.pieces[castling_square + 1] == Piece::None
+                && self.pieces[castling_square + 2] == Piece::None
+                && !self.is_square_attacked(castling_square as u8 + 1, opponent)
+                && !self.is_square_attacked(castling_square as u8 + 2, opponent)
+            {
+                let v_move = Move::ShortCastle;
 
-            moves.push(v_move);
+                moves.push(v_move);
+            }
         }
 
-        if CastlingRights::can_short_castle(castling_rights)
-            && self.pieces[castling_square + 1] == Piece::None
-            && self.pieces[castling_square + 2] == Piece::None
-        {
-            let v_move = Move::ShortCastle;
+        moves
+    }
 
-            moves.push(v_move);
-        }
+    /// Pseudo-legal moves that do not leave the mover's own king in check.
+    pub fn generate_legal_moves(&mut self) -> Vec<u16> {
+        let colour = self.colour_to_move;
+        let mut moves = self.generate_moves();
+
+        moves.retain(|&v_move| {
+            self.make_move(v_move);
+            let legal = !self.is_colour_in_check(colour);
+            let _ = self.undo_move();
+
+            legal
+        });
 
         moves
     }
 
-    pub fn make_move(&mut self, v_move: u16) {
-        let departure_square = Move::departure_square(v_move) as usize;
-        let target_square = Move::target_square(v_move) as usize;
-        let promotion = Move::is_promotion(v_move);
-        let castling = Move::is_castling(v_move);
-        let special_one = Move::special_one(v_move);
-        let special_two = Move::special_two(v_move);
+    /// Moves the side that just moved could have played to reach this
+    /// position, each going from where the piece now stands back to where it
+    /// came from. Captures, promotions and castling are left out, and nothing
+    /// is checked for legality.
+    pub fn generate_unmoves(&self) -> Vec<u16> {
+        let colour = !self.colour_to_move;
+        let mut unmoves = Vec::new();
 
-        if promotion {
-            let promotion_type = Move::promotion_type(v_move);
-            let piece_to_promote_to = match promotion_type {
-                Move::PromoteToKnight => Piece::Knight,
-                Move::PromoteToBishop => Piece::Bishop,
-                Move::PromoteToRook => Piece::Rook,
-                Move::PromoteToQueen => Piece::Queen,
-                _ => unreachable!(),
+        for (square, &piece) in self.pieces.iter().enumerate() {
+            if piece == Piece::None || Piece::colour_bool(piece) != colour {
+                continue;
+            }
+
+            let origins = match Piece::piece_type(piece) {
+                Piece::Pawn => {
+                    let (back, second_rank) = match colour {
+                        Colour::White => (DIRECTION_OFFSETS[SOUTH], 1),
+                        Colour::Black => (DIRECTION_OFFSETS[NORTH], 6),
+                    };
+                    let origin = (square as i8 + back) as usize;
+                    let double_push_origin = (origin as i8 + back) as usize;
+
+                    // A pawn never stands on its first rank
+                    match origin / 8 {
+                        0 | 7 => 0,
+                        _ if double_push_origin / 8 == second_rank
+                            && self.pieces[origin] == Piece::None =>
+                        {
+                            1 << origin | 1 << double_push_origin
+                        }
+                        _ => 1 << origin,
+                    }
+                }
+                _ => self.attacks_from(square as u8),
             };
-            let piece_colour = Piece::colour(self.pieces[departure_square]);
 
-            self.pieces[target_square] = piece_colour | piece_to_promote_to;
-            self.pieces[departure_square] = Piece::None;
+            for origin in squares(origins) {
+                if self.pieces[origin] == Piece::None {
+                    unmoves.push(Move::new(square as u16, origin as u16));
+                }
+            }
+        }
 
-            return;
+        unmoves
+    }
+
+    /// Takes back an un-move from `generate_unmoves`, leaving the history
+    /// untouched.
+    pub fn make_unmove(&mut self, unmove: u16) {
+        let square = Move::departure_square(unmove) as usize;
+        let origin = Move::target_square(unmove) as usize;
+
+        self.set_piece(origin, self.pieces[square]);
+        self.set_piece(square, Piece::None);
+        self.colour_to_move = !self.colour_to_move;
+    }
+
+    pub fn is_in_check(&self) -> bool {
+        self.is_colour_in_check(self.colour_to_move)
+    }
+
+    pub fn is_colour_in_check(&self, colour: bool) -> bool {
+        match self.king_square(colour) {
+            Some(square) => self.is_square_attacked(square, !colour),
+            None => false,
         }
+    }
 
-        if castling {
-            let castling_rights = CastlingRights::rights(self.castling_rights, self.colour_to_move);
+    pub fn king_square(&self, colour: bool) -> Option<u8> {
+        self.pieces
+            .iter()
+            .position(|&piece| {
+                Piece::is_type(piece, Piece::King) && Piece::is_colour_bool(piece, colour)
+            })
+            .map(|square| square as u8)
+    }
 
-            if castling_rights == CastlingRights::CanNotCastle {
-                return;
+    pub fn is_square_attacked(&self, square: u8, by_colour: bool) -> bool {
+        let square = square as usize;
+        let attacker = |piece: u8, kinds: &[u8]| {
+            Piece::is_colour_bool(piece, by_colour) && kinds.contains(&Piece::piece_type(piece))
+        };
+
+        // Pawns attack diagonally forward, so look diagonally backward from the square
+        let pawn_directions = match by_colour {
+            Colour::White => [SOUTH_EAST, SOUTH_WEST],
+            Colour::Black => [NORTH_WEST, NORTH_EAST],
+        };
+        for dir_index in pawn_directions {
+            if NUM_SQUARES_TO_EDGE[square][dir_index] == 0 {
+                continue;
             }
 
-            let king_index = match self.colour_to_move {
-                Colour::White => KING_SQUARE.0,
-                Colour::Black => KING_SQUARE.1,
-            };
+            let origin = (square as i8 + DIRECTION_OFFSETS[dir_index]) as usize;
+            if attacker(self.pieces[origin], &[Piece::Pawn]) {
+                return true;
+            }
+        }
+
+        let (file, rank) = square_to_coods(square as u16);
+        for n in KNIGHTS_OFFSETS {
+            let origin_file = file as i8 + n.0;
+            let origin_rank = rank as i8 + n.1;
+
+            if !(0..=7).contains(&origin_file) || !(0..=7).contains(&origin_rank) {
+                continue;
+            }
+
+            let origin = (origin_rank * 8 + origin_file) as usize;
+            if attacker(self.pieces[origin], &[Piece::Knight]) {
+                return true;
+            }
+        }
 
-            let move_record = MoveRecord {
-                v_move,
-                piece_on_target_square: 0,
+        for dir_index in 0..8 {
+            let sliders: &[u8] = if dir_index < 4 {
+                &[Piece::Rook, Piece::Queen]
+            } else {
+                &[Piece::Bishop, Piece::Queen]
             };
-            self.move_history.push(move_record);
 
-            if special_one && CastlingRights::can_short_castle(castling_rights) {
-                self.castling_rights = match self.colour_to_move {
-                    Colour::White => {
-                        self.castling_rights >> 4 << 4 | CastlingRights::WhiteCanNotCastle
-                    }
-                    Colour::Black => {
-                        self.castling_rights << 4 >> 4 | CastlingRights::BlackCanNotCastle
-                    }
+            for n in 0..NUM_SQUARES_TO_EDGE[square][dir_index] {
+                let origin = (square as i8 + DIRECTION_OFFSETS[dir_index] * (n as i8 + 1)) as usize;
+                let piece = self.pieces[origin];
+
+                if piece == Piece::None {
+                    continue;
+                }
+
+                if attacker(piece, sliders) || (n == 0 && attacker(piece, &[Piece::King])) {
+                    return true;
+                }
+
+                break;
+            }
+        }
+
+        false
+    }
+
+    /// The squares attacked by the piece on `square`, one bit per square.
+    ///
+    /// Sliders stop at the first occupied square, which is included whoever
+    /// it belongs to.
+    pub fn attacks_from(&self, square: u8) -> u64 {
+        let square = square as usize;
+        let piece = self.pieces[square];
+        let mut attacks = 0;
+
+        let step = |dir_index: usize| (square as i8 + DIRECTION_OFFSETS[dir_index]) as usize;
+
+        match Piece::piece_type(piece) {
+            Piece::Pawn => {
+                let directions = match Piece::colour_bool(piece) {
+                    Colour::White => [NORTH_WEST, NORTH_EAST],
+                    Colour::Black => [SOUTH_EAST, SOUTH_WEST],
                 };
 
-                let king = self.pieces[king_index];
-                let rook = self.pieces[king_index + 3];
-                self.pieces[king_index] = Piece::None;
-                self.pieces[king_index + 3] = Piece::None;
-                self.pieces[king_index + 2] = king;
-                self.pieces[king_index + 1] = rook;
+                for dir_index in directions {
+                    if NUM_SQUARES_TO_EDGE[square][dir_index] != 0 {
+                        attacks |= 1 << step(dir_index);
+                    }
+                }
+            }
 
-                self.colour_to_move = !self.colour_to_move;
+            Piece::Knight => {
+                let (file, rank) = square_to_coods(square as u16);
+                for n in KNIGHTS_OFFSETS {
+                    let target_file = file as i8 + n.0;
+                    let target_rank = rank as i8 + n.1;
+
+                    if (0..=7).contains(&target_file) && (0..=7).contains(&target_rank) {
+                        attacks |= 1 << (target_rank * 8 + target_file);
+                    }
+                }
             }
 
-            if special_two && CastlingRights::can_long_castle(castling_rights) {
-                self.castling_rights = match self.colour_to_move {
-                    Colour::White => {
-                        self.castling_rights >> 4 << 4 | CastlingRights::WhiteCanNotCastle
+            Piece::King => {
+                for dir_index in 0..8 {
+                    if NUM_SQUARES_TO_EDGE[square][dir_index] != 0 {
+                        attacks |= 1 << step(dir_index);
                     }
-                    Colour::Black => {
-                        self.castling_rights << 4 >> 4 | CastlingRights::BlackCanNotCastle
+                }
+            }
+
+            piece_type @ (Piece::Bishop | Piece::Rook | Piece::Queen) => {
+                let start_dir_index = if piece_type == Piece::Bishop { 4 } else { 0 };
+                let end_dir_index = if piece_type == Piece::Rook { 4 } else { 8 };
+
+                for dir_index in start_dir_index..end_dir_index {
+                    for n in 0..NUM_SQUARES_TO_EDGE[square][dir_index] {
+                        let target =
+                            (square as i8 + DIRECTION_OFFSETS[dir_index] * (n as i8 + 1)) as usize;
+                        attacks |= 1 << target;
+
+                        if self.pieces[target] != Piece::None {
+                            break;
+                        }
                     }
-                };
+                }
+            }
 
-                let king = self.pieces[king_index];
-                let rook = self.pieces[king_index - 4];
-                self.pieces[king_index] = Piece::None;
-                self.pieces[king_index - 4] = Piece::None;
-                self.pieces[king_index - 2] = king;
-                self.pieces[king_index - 1] = rook;
+            _ => {}
+        }
+
+        attacks
+    }
 
-                self.colour_to_move = !self.colour_to_move;
+    /// Whether neither side has enough material left to mate: only kings,
+    /// or a single knight or bishop besides them.
+    pub fn is_insufficient_material(&self) -> bool {
+        let mut minor_pieces = 0;
+
+        for &piece in self.pieces.iter() {
+            match Piece::piece_type(piece) {
+                Piece::Pawn | Piece::Rook | Piece::Queen => return false,
+                Piece::Knight | Piece::Bishop => minor_pieces += 1,
+                _ => {}
             }
+        }
 
-            return;
+        minor_pieces <= 1
+    }
+
+    pub fn is_capture(&self, v_move: u16) -> bool {
+        !Move::is_castling(v_move)
+            && (Move::is_en_passant(v_move)
+                || self.pieces[Move::target_square(v_move) as usize] != Piece::None)
+    }
+
+    pub fn hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for (square, &piece) in self.pieces.iter().enumerate() {
+            if piece != Piece::None {
+                hash ^= ZOBRIST.piece(piece, square);
+            }
+        }
+
+        if self.colour_to_move == Colour::Black {
+            hash ^= ZOBRIST.black_to_move;
+        }
+
+        hash ^= ZOBRIST.castling(self.castling_rights);
+
+        if let Some(square) = self.en_passant_square {
+            hash ^= ZOBRIST.en_passant[square as usize % 8];
         }
 
+        hash
+    }
+
+    /// A hash of the pawns alone, which key the pawn structure cache.
+    pub fn pawn_hash(&self) -> u64 {
+        self.pawn_key
+    }
+
+    pub fn make_move(&mut self, v_move: u16) {
+        let departure_square = Move::departure_square(v_move) as usize;
+        let target_square = Move::target_square(v_move) as usize;
+        let promotion = Move::is_promotion(v_move);
+        let castling = Move::is_castling(v_move);
+
         let move_record = MoveRecord {
             v_move,
-            piece_on_target_square: self.pieces[target_square],
+            piece_on_target_square: if castling {
+                Piece::None
+            } else {
+                self.pieces[target_square]
+            },
+            castling_rights: self.castling_rights,
+            en_passant_square: self.en_passant_square,
+            halfmove_clock: self.halfmove_clock,
+            pawn_key: self.pawn_key,
         };
         self.move_history.push(move_record);
 
-        let piece_to_move_type = Piece::piece_type(self.pieces[departure_square]);
-        if piece_to_move_type == Piece::King || piece_to_move_type == Piece::Rook {
-            self.castling_rights = match self.colour_to_move {
-                Colour::White => self.castling_rights >> 4 << 4 | CastlingRights::WhiteCanNotCastle,
-                Colour::Black => self.castling_rights << 4 >> 4 | CastlingRights::BlackCanNotCastle,
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push();
+        }
+
+        self.en_passant_square = None;
+        self.halfmove_clock += 1;
+        if self.colour_to_move == Colour::Black {
+            self.fullmove_number += 1;
+        }
+
+        if castling {
+            let king_index = match self.colour_to_move {
+                Colour::White => KING_SQUARE.0,
+                Colour::Black => KING_SQUARE.1,
             };
+
+            self.castling_rights &= !castling_rights_lost(king_index);
+
+            let king = self.pieces[king_index];
+            self.set_piece(king_index, Piece::None);
+
+            if Move::is_short_castling(v_move) {
+                let rook = self.pieces[king_index + 3];
+                self.set_piece(king_index + 3, Piece::None);
+                self.set_piece(king_index + 2, king);
+                self.set_piece(king_index + 1, rook);
+            } else {
+                let rook = self.pieces[king_index - 4];
+                self.set_piece(king_index - 4, Piece::None);
+                self.set_piece(king_index - 2, king);
+                self.set_piece(king_index - 1, rook);
+            }
+
+            self.colour_to_move = !self.colour_to_move;
+
+            return;
         }
 
+        let piece_to_move = self.pieces[departure_square];
+        if Piece::is_type(piece_to_move, Piece::Pawn) || self.is_capture(v_move) {
+            self.halfmove_clock = 0;
+        }
+
+        // Moving the king or a rook, or capturing a rook, loses the matching rights
+        self.castling_rights &=
+            !(castling_rights_lost(departure_square) | castling_rights_lost(target_square));
+
         let colour_index = match self.colour_to_move {
             Colour::White => DIRECTION_OFFSETS[SOUTH],
             Colour::Black => DIRECTION_OFFSETS[NORTH],
         };
 
         let is_two_rank_movement = ((target_square as i8 - departure_square as i8) / 8).abs() == 2;
-        self.en_passant_square =
-            if Piece::is_type(self.pieces[departure_square], Piece::Pawn) && is_two_rank_movement {
-                Some((target_square as i8 + colour_index) as u8)
-            } else {
-                None
+        if Piece::is_type(piece_to_move, Piece::Pawn) && is_two_rank_movement {
+            self.en_passant_square = Some((target_square as i8 + colour_index) as u8);
+        }
+
+        if promotion {
+            let piece_to_promote_to = match Move::promotion_type(v_move) {
+                Move::PromoteToKnight => Piece::Knight,
+                Move::PromoteToBishop => Piece::Bishop,
+                Move::PromoteToRook => Piece::Rook,
+                Move::PromoteToQueen => Piece::Queen,
+                _ => unreachable!(),
             };
 
-        self.pieces[target_square] = self.pieces[departure_square];
+            self.set_piece(
+                target_square,
+                Piece::colour(piece_to_move) | piece_to_promote_to,
+            );
+        } else {
+            self.set_piece(target_square, piece_to_move);
+        }
         if Move::is_en_passant(v_move) {
-            self.pieces[(target_square as i8 + colour_index) as usize] = Piece::None;
+            self.set_piece((target_square as i8 + colour_index) as usize, Piece::None);
         }
-        self.pieces[departure_square] = Piece::None;
+        self.set_piece(departure_square, Piece::None);
 
         self.colour_to_move = !self.colour_to_move;
     }
 
+    /// Puts `piece` on `square`, keeping the pawn key and the network
+    /// accumulators in step. `undo_move` writes squares directly as it
+    /// restores both from the move's record and saved accumulators.
+    fn set_piece(&mut self, square: usize, piece: u8) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.update(square, self.pieces[square], piece);
+        }
+
+        for changed in [self.pieces[square], piece] {
+            if Piece::is_type(changed, Piece::Pawn) {
+                self.pawn_key ^= ZOBRIST.piece(changed, square);
+            }
+        }
+
+        self.pieces[square] = piece;
+    }
+
     #[allow(dead_code)]
     pub fn new() -> Self {
         let mut board = Self::default();
@@ -543,10 +870,86 @@ impl Board {
         for x in 0..8 {
             board.pieces[48 + x] = Piece::Black | Piece::Pawn;
         }
+        board.pawn_key = pawn_key(&board.pieces);
 
         board
     }
 
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+
+            for file in 0..8 {
+                let piece = self.pieces[rank * 8 + file];
+                if piece == Piece::None {
+                    empty += 1;
+                    continue;
+                }
+
+                if empty > 0 {
+                    placement.push_str(&empty.to_string());
+                    empty = 0;
+                }
+
+                let c = match Piece::piece_type(piece) {
+                    Piece::Pawn => 'p',
+                    Piece::Knight => 'n',
+                    Piece::Bishop => 'b',
+                    Piece::Rook => 'r',
+                    Piece::Queen => 'q',
+                    _ => 'k',
+                };
+                placement.push(match Piece::colour_bool(piece) {
+                    Colour::White => c.to_ascii_uppercase(),
+                    Colour::Black => c,
+                });
+            }
+
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let colour_to_move = match self.colour_to_move {
+            Colour::White => "w",
+            Colour::Black => "b",
+        };
+
+        use CastlingRights as CR;
+        let mut castling_rights = String::new();
+        for (right, c) in [
+            (CR::WhiteCanShortCastle, 'K'),
+            (CR::WhiteCanLongCastle, 'Q'),
+            (CR::BlackCanShortCastle, 'k'),
+            (CR::BlackCanLongCastle, 'q'),
+        ] {
+            if self.castling_rights & right != 0 {
+                castling_rights.push(c);
+            }
+        }
+        if castling_rights.is_empty() {
+            castling_rights.push('-');
+        }
+
+        let en_passant_square = self
+            .en_passant_square
+            .map_or("-".to_string(), square_to_string);
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            colour_to_move,
+            castling_rights,
+            en_passant_square,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
     pub fn stringify(&self, perspective: bool) -> String {
         let mut string = String::new();
 
@@ -595,19 +998,35 @@ impl Board {
             Some(v) => v,
             None => return Err(Box::new(UndoMoveError::EmptyStack)),
         };
+
+        self.restore_move(move_record);
+
+        // Moves made before the network was set have no saved accumulators
+        if let Some(nnue) = &mut self.nnue {
+            if !nnue.pop() {
+                *nnue = NnueState::new(Arc::clone(nnue.network()), &self.pieces);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Puts the pieces back as they were before the move in `move_record`.
+    fn restore_move(&mut self, move_record: MoveRecord) {
         let v_move = move_record.v_move;
 
         let departure_square = Move::departure_square(v_move) as usize;
         let target_square = Move::target_square(v_move) as usize;
         let promotion = Move::is_promotion(v_move);
         let castling = Move::is_castling(v_move);
-        let special_one = Move::special_one(v_move);
-        let special_two = Move::special_two(v_move);
 
         self.colour_to_move = !self.colour_to_move;
-
-        if promotion {
-            return Ok(());
+        self.castling_rights = move_record.castling_rights;
+        self.en_passant_square = move_record.en_passant_square;
+        self.halfmove_clock = move_record.halfmove_clock;
+        self.pawn_key = move_record.pawn_key;
+        if self.colour_to_move == Colour::Black {
+            self.fullmove_number -= 1;
         }
 
         if castling {
@@ -621,46 +1040,37 @@ impl Board {
                 Colour::Black => Piece::Black,
             };
 
-            if special_one {
-                self.castling_rights = match self.colour_to_move {
-                    Colour::White => {
-                        self.castling_rights >> 4 << 4 | CastlingRights::WhiteCanNotCastle
-                    }
-                    Colour::Black => {
-                        self.castling_rights << 4 >> 4 | CastlingRights::BlackCanNotCastle
-                    }
-                };
-
+            if Move::is_short_castling(v_move) {
                 self.pieces[castling_square + 1] = Piece::None;
                 self.pieces[castling_square + 2] = Piece::None;
                 self.pieces[castling_square] = Piece::King | piece_colour;
                 self.pieces[castling_square + 3] = Piece::Rook | piece_colour;
-            }
-
-            if special_two {
-                self.castling_rights = match self.colour_to_move {
-                    Colour::White => {
-                        self.castling_rights >> 4 << 4 | CastlingRights::WhiteCanCastle
-                    }
-                    Colour::Black => {
-                        self.castling_rights << 4 >> 4 | CastlingRights::BlackCanCastle
-                    }
-                };
-
+            } else {
                 self.pieces[castling_square - 1] = Piece::None;
                 self.pieces[castling_square - 2] = Piece::None;
-                self.pieces[castling_square - 3] = Piece::None;
                 self.pieces[castling_square] = Piece::King | piece_colour;
                 self.pieces[castling_square - 4] = Piece::Rook | piece_colour;
             }
 
-            return Ok(());
+            return;
         }
 
-        self.pieces[departure_square] = self.pieces[target_square];
+        let moved_piece = self.pieces[target_square];
+        self.pieces[departure_square] = if promotion {
+            Piece::colour(moved_piece) | Piece::Pawn
+        } else {
+            moved_piece
+        };
         self.pieces[target_square] = move_record.piece_on_target_square;
 
-        Ok(())
+        if Move::is_en_passant(v_move) {
+            let (captured_square, captured_colour) = match self.colour_to_move {
+                Colour::White => (target_square as i8 + DIRECTION_OFFSETS[SOUTH], Piece::Black),
+                Colour::Black => (target_square as i8 + DIRECTION_OFFSETS[NORTH], Piece::White),
+            };
+
+            self.pieces[captured_square as usize] = captured_colour | Piece::Pawn;
+        }
     }
 
     pub fn get_colour_to_move(&self) -> bool {
@@ -674,6 +1084,30 @@ impl Board {
     pub fn get_castling_rights(&self) -> u8 {
         self.castling_rights
     }
+
+    pub fn get_halfmove_clock(&self) -> u16 {
+        self.halfmove_clock
+    }
+
+    pub fn get_fullmove_number(&self) -> u16 {
+        self.fullmove_number
+    }
+
+    /// The moves played on the board since it was set up, oldest first.
+    pub fn played_moves(&self) -> Vec<u16> {
+        self.move_history
+            .iter()
+            .map(|move_record| move_record.v_move)
+            .collect()
+    }
+
+    pub fn pieces(&self) -> &[u8; 64] {
+        &self.pieces
+    }
+
+    pub fn piece_at(&self, square: u8) -> u8 {
+        self.pieces[square as usize]
+    }
 }
 
 impl Default for Board {
@@ -683,14 +1117,146 @@ impl Default for Board {
             colour_to_move: Colour::White,
             castling_rights: CastlingRights::WhiteCanCastle | CastlingRights::BlackCanCastle,
             en_passant_square: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
             move_history: Vec::new(),
+            pawn_key: 0,
+            nnue: None,
         }
     }
 }
 
+/// The Zobrist key of the pawns among `pieces`, computed from scratch.
+fn pawn_key(pieces: &[u8; 64]) -> u64 {
+    pieces
+        .iter()
+        .enumerate()
+        .filter(|(_, &piece)| Piece::is_type(piece, Piece::Pawn))
+        .fold(0, |key, (square, &piece)| {
+            key ^ ZOBRIST.piece(piece, square)
+        })
+}
+
+/// Pushes a pawn move, expanding it into the four promotions on the last rank.
+fn push_pawn_move(moves: &mut Vec<u16>, departure_square: u16, target_square: u16) {
+    let v_move = Move::new(departure_square, target_square);
+
+    if !(8..56).contains(&target_square) {
+        for promotion in [
+            Move::PromoteToQueen,
+            Move::PromoteToRook,
+            Move::PromoteToBishop,
+            Move::PromoteToKnight,
+        ] {
+            moves.push(v_move | promotion);
+        }
+
+        return;
+    }
+
+    moves.push(v_move);
+}
+
+/// The castling rights lost when a piece leaves or lands on `square`.
+fn castling_rights_lost(square: usize) -> u8 {
+    use CastlingRights as CR;
+
+    match square {
+        0 => CR::WhiteCanLongCastle,
+        4 => CR::WhiteCanCastle,
+        7 => CR::WhiteCanShortCastle,
+        56 => CR::BlackCanLongCastle,
+        60 => CR::BlackCanCastle,
+        63 => CR::BlackCanShortCastle,
+        _ => CR::CanNotCastle,
+    }
+}
+
 fn square_to_coods(square: u16) -> (u16, u16) {
     let rank = (square as f32 / 8.).floor();
     let file = square as f32 - rank * 8.;
 
     (file as u16, rank as u16)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+
+    /// Everything a position is made of, to compare it before and after a
+    /// move is undone.
+    fn snapshot(board: &Board) -> (String, bool, Option<u8>, u8, u16) {
+        (
+            board.stringify(Colour::White),
+            board.get_colour_to_move(),
+            board.get_en_passant_square(),
+            board.get_castling_rights(),
+            board.get_halfmove_clock(),
+        )
+    }
+
+    /// The number of legal move sequences `depth` plies long from `board`,
+    /// checking that undoing every move restores the position.
+    fn perft(board: &mut Board, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let mut nodes = 0;
+        for v_move in board.generate_legal_moves() {
+            let position = snapshot(board);
+            let hash = board.hash();
+
+            board.make_move(v_move);
+            assert_eq!(board.pawn_hash(), pawn_key(board.pieces()));
+            nodes += perft(board, depth - 1);
+            board.undo_move().unwrap();
+
+            assert_eq!(snapshot(board), position);
+            assert_eq!(board.hash(), hash);
+            assert_eq!(board.pawn_hash(), pawn_key(board.pieces()));
+        }
+
+        nodes
+    }
+
+    fn assert_perft(mut board: Board, expected: &[u64]) {
+        for (depth, &nodes) in (1..).zip(expected) {
+            assert_eq!(perft(&mut board, depth), nodes, "depth {depth}");
+        }
+    }
+
+    fn assert_perft_from_fen(fen: &'static str, expected: &[u64]) {
+        assert_perft(Board::from_fen(fen).unwrap(), expected);
+    }
+
+    #[test]
+    fn perft_start_position() {
+        assert_perft(Board::new(), &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        assert_perft_from_fen(KIWIPETE, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn perft_position_3() {
+        assert_perft_from_fen(POSITION_3, &[14, 191, 2812, 43238]);
+    }
+
+    #[test]
+    fn perft_position_4() {
+        assert_perft_from_fen(POSITION_4, &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn perft_position_5() {
+        assert_perft_from_fen(POSITION_5, &[44, 1486, 62379]);
+    }
+}
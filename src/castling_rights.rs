@@ -2,6 +2,7 @@ use crate::colour::Colour;
 
 pub struct CastlingRights;
 
+#[allow(non_upper_case_globals, dead_code)]
 impl CastlingRights {
     pub const WhiteCanCastle: u8 = 0b00000011;
     pub const WhiteCanNotCastle: u8 = 0b00000000;
@@ -33,7 +34,6 @@ impl CastlingRights {
         match colour {
             Colour::White => Self::white_rights(rights),
             Colour::Black => Self::black_rights(rights),
-            _ => unreachable!(),
         }
     }
 
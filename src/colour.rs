@@ -1,6 +1,7 @@
 #[non_exhaustive]
 pub struct Colour;
 
+#[allow(non_upper_case_globals)]
 impl Colour {
     pub const White: bool = true;
     pub const Black: bool = false;
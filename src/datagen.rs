@@ -0,0 +1,195 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_print::cprintln;
+
+use crate::{
+    board::Board,
+    colour::Colour,
+    moves::Move,
+    search::{SearchLimits, Searcher, MATE_BOUND},
+    utils::Rng,
+    Result,
+};
+
+pub const DEFAULT_NODES: u64 = 5000;
+
+/// Random moves played from the starting position before the engine takes
+/// over, plus up to one more so that either side may be the first to think.
+const RANDOM_PLIES: usize = 8;
+/// Openings the engine already judges lost for one side make poor games.
+const MAX_OPENING_SCORE: i32 = 400;
+/// Games still going after this many plies are scored as draws.
+const MAX_GAME_PLIES: usize = 400;
+const TT_SIZE_MB: usize = 16;
+const PROGRESS_INTERVAL: usize = 10;
+
+/// Plays `games` self-play games split over `threads` threads, searching
+/// `nodes` nodes per move, and appends the positions to `output_path`.
+///
+/// Each position is written on its own line as its FEN, the game result
+/// from white's point of view as `[1.0]`, `[0.5]` or `[0.0]`, and the
+/// search score in centipawns from white's point of view, the format the
+/// tuner reads. Positions in check, or where the best move is a capture or
+/// a promotion, are left out as their static evaluation is unreliable.
+pub fn run(output_path: &str, games: usize, nodes: u64, threads: usize) -> Result<()> {
+    let output = Mutex::new(BufWriter::new(
+        File::options()
+            .create(true)
+            .append(true)
+            .open(output_path)?,
+    ));
+    let games_played = AtomicUsize::new(0);
+    let positions_written = AtomicUsize::new(0);
+
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(1, |time| time.as_nanos() as u64);
+    let threads = threads.clamp(1, games.max(1));
+
+    thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|id| {
+                let output = &output;
+                let games_played = &games_played;
+                let positions_written = &positions_written;
+
+                // Threads split the games evenly, the first ones taking the remainder
+                let thread_games = games / threads + usize::from(id < games % threads);
+
+                scope.spawn(move || -> io::Result<()> {
+                    let mut rng =
+                        Rng::new(seed ^ (id as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+                    let mut searcher = Searcher::new(TT_SIZE_MB);
+
+                    for _ in 0..thread_games {
+                        let lines = play_game(&mut searcher, &mut rng, nodes);
+
+                        let mut output = output.lock().unwrap();
+                        for line in &lines {
+                            writeln!(output, "{}", line)?;
+                        }
+                        output.flush()?;
+                        drop(output);
+
+                        let written = positions_written.fetch_add(lines.len(), Ordering::Relaxed);
+                        let played = games_played.fetch_add(1, Ordering::Relaxed) + 1;
+                        if played.is_multiple_of(PROGRESS_INTERVAL) || played == games {
+                            cprintln!(
+                                "<green>games:</> {}/{}  <green>positions:</> {}",
+                                played,
+                                games,
+                                written + lines.len()
+                            );
+                        }
+                    }
+
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("datagen thread panicked"))
+    })?;
+
+    Ok(())
+}
+
+/// Plays one game and returns its positions in the output format.
+fn play_game(searcher: &mut Searcher, rng: &mut Rng, nodes: u64) -> Vec<String> {
+    searcher.clear();
+
+    let limits = SearchLimits {
+        nodes: Some(nodes),
+        ..Default::default()
+    };
+
+    let mut board = random_opening(searcher, rng, limits);
+    let mut hashes = vec![board.hash()];
+    let mut positions = Vec::new();
+
+    let result = loop {
+        let moves = board.generate_legal_moves();
+        if moves.is_empty() {
+            break match board.is_in_check() {
+                true if board.get_colour_to_move() == Colour::White => 0.0,
+                true => 1.0,
+                false => 0.5,
+            };
+        }
+
+        let repetitions = hashes.iter().filter(|&&hash| hash == board.hash()).count();
+        if board.get_halfmove_clock() >= 100
+            || repetitions >= 3
+            || board.is_insufficient_material()
+            || hashes.len() > MAX_GAME_PLIES
+        {
+            break 0.5;
+        }
+
+        let result = searcher.search(&mut board, limits);
+        let Some(best_move) = result.best_move else {
+            break 0.5;
+        };
+
+        let white_score = match board.get_colour_to_move() {
+            Colour::White => result.score,
+            Colour::Black => -result.score,
+        };
+
+        // Once a mate is found the game is decided
+        if white_score.abs() >= MATE_BOUND {
+            break if white_score > 0 { 1.0 } else { 0.0 };
+        }
+
+        let noisy =
+            board.is_in_check() || board.is_capture(best_move) || Move::is_promotion(best_move);
+        if !noisy {
+            positions.push((board.to_fen(), white_score));
+        }
+
+        board.make_move(best_move);
+        hashes.push(board.hash());
+    };
+
+    positions
+        .into_iter()
+        .map(|(fen, score)| format!("{} [{:.1}] {}", fen, result, score))
+        .collect()
+}
+
+/// A position reached by random legal moves from the starting position
+/// that neither side should be winning yet.
+fn random_opening(searcher: &mut Searcher, rng: &mut Rng, limits: SearchLimits) -> Board {
+    loop {
+        let mut board = Board::new();
+        let plies = RANDOM_PLIES + rng.below(2);
+
+        let reached = (0..plies).all(|_| {
+            let moves = board.generate_legal_moves();
+            if moves.is_empty() {
+                return false;
+            }
+
+            board.make_move(moves[rng.below(moves.len())]);
+            true
+        });
+
+        if reached
+            && !board.generate_legal_moves().is_empty()
+            && searcher.search(&mut board, limits).score.abs() <= MAX_OPENING_SCORE
+        {
+            return board;
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    board::Board, colour::Colour, kpk, params::EvalParams, piece::Piece, search::MATE_BOUND,
+};
+
+/// Scale factor leaving the endgame score untouched, scale factors being in 64ths.
+pub const SCALE_NORMAL: i32 = 64;
+const SCALE_DRAW: i32 = 0;
+
+/// What the endgame module knows about a position.
+#[derive(Clone, Copy, Debug)]
+pub enum Endgame {
+    /// The evaluation in centipawns from white's point of view.
+    Score(i32),
+    /// The factor, in 64ths, to apply to the endgame part of the evaluation.
+    Scale(i32),
+}
+
+/// Pieces of each type for white and black, indexed by piece type.
+struct Material {
+    counts: [[u8; 7]; 2],
+    /// Bishops on light squares and dark squares for each side.
+    bishop_colours: [[u8; 2]; 2],
+}
+
+impl Material {
+    fn new(board: &Board) -> Self {
+        let mut counts = [[0; 7]; 2];
+        let mut bishop_colours = [[0; 2]; 2];
+
+        for (square, &piece) in board.pieces().iter().enumerate() {
+            if piece == Piece::None {
+                continue;
+            }
+
+            let side = colour_index(Piece::colour_bool(piece));
+            counts[side][Piece::piece_type(piece) as usize] += 1;
+
+            if Piece::is_type(piece, Piece::Bishop) {
+                bishop_colours[side][square_colour(square)] += 1;
+            }
+        }
+
+        Self {
+            counts,
+            bishop_colours,
+        }
+    }
+
+    fn count(&self, colour: bool, piece_type: u8) -> u8 {
+        self.counts[colour_index(colour)][piece_type as usize]
+    }
+
+    /// Pieces of `colour` other than the king.
+    fn pieces(&self, colour: bool) -> u8 {
+        (Piece::Pawn..=Piece::Queen)
+            .map(|piece_type| self.count(colour, piece_type))
+            .sum()
+    }
+
+    /// Whether `colour` has exactly the given pieces besides the king, by type
+    /// from pawn to queen.
+    fn is(&self, colour: bool, pieces: [u8; 5]) -> bool {
+        (Piece::Pawn..=Piece::Queen)
+            .zip(pieces)
+            .all(|(piece_type, count)| self.count(colour, piece_type) == count)
+    }
+
+    fn value(&self, colour: bool) -> i32 {
+        (Piece::Pawn..=Piece::Queen)
+            .map(|piece_type| self.count(colour, piece_type) as i32 * Piece::value(piece_type))
+            .sum()
+    }
+}
+
+/// Recognises the endgames the regular evaluation misjudges.
+pub fn probe(board: &Board, params: &EvalParams) -> Option<Endgame> {
+    let material = Material::new(board);
+
+    for strong in [Colour::White, Colour::Black] {
+        let weak = !strong;
+        let sign = if strong == Colour::White { 1 } else { -1 };
+
+        if material.pieces(weak) != 0 || material.pieces(strong) == 0 {
+            continue;
+        }
+
+        if material.is(strong, [1, 0, 0, 0, 0]) {
+            return Some(Endgame::Score(kpk(board, strong, params) * sign));
+        }
+
+        if material.is(strong, [0, 1, 1, 0, 0]) {
+            return Some(Endgame::Score(
+                kbnk(board, &material, strong, params) * sign,
+            ));
+        }
+
+        let bishop_pair = material.bishop_colours[colour_index(strong)]
+            .iter()
+            .all(|&count| count > 0);
+        let mating_material = material.count(strong, Piece::Queen) > 0
+            || material.count(strong, Piece::Rook) > 0
+            || bishop_pair;
+
+        if material.count(strong, Piece::Pawn) == 0 {
+            return Some(match mating_material {
+                true => Endgame::Score(kxk(board, &material, strong, params) * sign),
+                false => Endgame::Scale(SCALE_DRAW),
+            });
+        }
+    }
+
+    scale_factor(&material, params).map(Endgame::Scale)
+}
+
+/// Scale factors for endings where the side ahead has trouble converting.
+fn scale_factor(material: &Material, params: &EvalParams) -> Option<i32> {
+    let pawns = |colour| material.count(colour, Piece::Pawn);
+    let no_pawns = pawns(Colour::White) == 0 && pawns(Colour::Black) == 0;
+
+    let lone_rooks = |colour| material.is(colour, [0, 0, 0, 1, 0]);
+    if lone_rooks(Colour::White) && lone_rooks(Colour::Black) {
+        return Some(params.scale_rook_ending);
+    }
+
+    let lone_bishop = |colour| material.is(colour, [pawns(colour), 0, 1, 0, 0]);
+    let single_bishops = material.count(Colour::White, Piece::Bishop) == 1
+        && material.count(Colour::Black, Piece::Bishop) == 1;
+    let opposite_bishops =
+        single_bishops && material.bishop_colours[0] != material.bishop_colours[1];
+
+    if lone_bishop(Colour::White) && lone_bishop(Colour::Black) {
+        if no_pawns {
+            return Some(SCALE_DRAW);
+        }
+        if opposite_bishops {
+            return Some(params.scale_opposite_bishops);
+        }
+    }
+
+    if opposite_bishops {
+        return Some(params.scale_opposite_bishops_with_pieces);
+    }
+
+    // Without pawns, being up no more than a minor piece is usually not enough
+    for strong in [Colour::White, Colour::Black] {
+        let advantage = material.value(strong) - material.value(!strong);
+        if pawns(strong) == 0 && advantage > 0 && advantage <= Piece::BishopValue {
+            return Some(params.scale_no_pawns);
+        }
+    }
+
+    None
+}
+
+/// Queen, rook or bishop pair against a bare king: drive the king to the
+/// edge and bring the own king closer.
+fn kxk(board: &Board, material: &Material, strong: bool, params: &EvalParams) -> i32 {
+    let (strong_king, weak_king) = kings(board, strong);
+
+    let score = params.known_win
+        + material.value(strong)
+        + params.push_to_edge * (3 - edge_distance(weak_king))
+        + params.push_close * (7 - distance(strong_king, weak_king));
+
+    score.min(MATE_BOUND - 1)
+}
+
+/// Bishop and knight against a bare king: only the corners of the bishop's
+/// colour allow mate, so the king is driven towards those.
+fn kbnk(board: &Board, material: &Material, strong: bool, params: &EvalParams) -> i32 {
+    let (strong_king, weak_king) = kings(board, strong);
+
+    let light_bishop = material.bishop_colours[colour_index(strong)][LIGHT] > 0;
+    let corners = match light_bishop {
+        true => [7, 56],
+        false => [0, 63],
+    };
+    let corner_distance = corners
+        .iter()
+        .map(|&corner| distance(weak_king, corner))
+        .min()
+        .unwrap_or_default();
+
+    params.known_win
+        + material.value(strong)
+        + params.push_to_corner * (7 - corner_distance)
+        + params.push_close * (7 - distance(strong_king, weak_king))
+}
+
+/// King and pawn against king, exactly won or drawn according to the
+/// bitbase. Wins score higher the further the pawn has advanced.
+fn kpk(board: &Board, strong: bool, params: &EvalParams) -> i32 {
+    if kpk::probe(board) != Some(true) {
+        return 0;
+    }
+
+    let pawn = board
+        .pieces()
+        .iter()
+        .position(|&piece| Piece::is_type(piece, Piece::Pawn))
+        .unwrap_or_default();
+    let rank = match strong {
+        Colour::White => pawn / 8,
+        Colour::Black => 7 - pawn / 8,
+    };
+
+    params.known_win + Piece::PawnValue + params.push_close * rank as i32
+}
+
+fn kings(board: &Board, strong: bool) -> (usize, usize) {
+    let strong_king = board.king_square(strong).unwrap_or_default() as usize;
+    let weak_king = board.king_square(!strong).unwrap_or_default() as usize;
+
+    (strong_king, weak_king)
+}
+
+const LIGHT: usize = 1;
+
+fn square_colour(square: usize) -> usize {
+    (square % 8 + square / 8) % 2
+}
+
+/// King moves between two squares.
+fn distance(a: usize, b: usize) -> i32 {
+    let files = (a % 8).abs_diff(b % 8);
+    let ranks = (a / 8).abs_diff(b / 8);
+
+    files.max(ranks) as i32
+}
+
+/// Squares between `square` and the nearest edge.
+fn edge_distance(square: usize) -> i32 {
+    let file = square % 8;
+    let rank = square / 8;
+
+    file.min(7 - file).min(rank).min(7 - rank) as i32
+}
+
+fn colour_index(colour: bool) -> usize {
+    match colour {
+        Colour::White => 0,
+        Colour::Black => 1,
+    }
+}
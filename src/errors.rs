@@ -13,6 +13,7 @@ pub enum UndoMoveError {
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names, dead_code)]
 pub enum MoveError {
     InvalidMove,
     InvalidFile,
@@ -20,16 +21,79 @@ pub enum MoveError {
 }
 
 #[derive(Debug)]
-pub enum FenError<'a> {
+#[allow(dead_code)]
+pub enum FenError {
     NotEnoughParts,
-    TooManyPieces(&'a str),
+    TooManyPieces(String),
     UnknownPiece(char),
-    BadPlacement(&'a str),
-    NoSuchSide(&'a str),
-    BadCastlingCharacter(&'a str),
-    BadEnPassant(&'a str),
-    BadHalfmove(&'a str),
-    BadFullmove(&'a str),
+    BadPlacement(String),
+    NoSuchSide(String),
+    BadCastlingCharacter(String),
+    BadEnPassant(String),
+    BadHalfmove(String),
+    BadFullmove(String),
+}
+
+#[derive(Debug)]
+pub enum PstError {
+    UnknownTable(String),
+    ValueOutsideTable(usize),
+    BadValue(String, usize),
+    WrongSize(String, usize),
+}
+
+#[derive(Debug)]
+pub enum ParamsError {
+    UnknownParam(String),
+    BadValue(String, usize),
+    WrongCount(String, usize, usize),
+    BadJson(String),
+}
+
+#[derive(Debug)]
+pub enum TuneError {
+    NoPositions,
+    NoResult(usize),
+    BadPosition(usize, String),
+}
+
+#[derive(Debug)]
+pub enum NnueError {
+    BadMagic,
+    UnsupportedVersion(u32),
+    WrongHiddenSize(u32),
+    WrongLength(usize, usize),
+}
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    BadName(String),
+    TooManyPieces(String),
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    WrongLength(usize, usize),
+}
+
+#[derive(Debug)]
+pub enum BookError {
+    WrongLength(usize),
+    Unsorted,
+}
+
+#[derive(Debug)]
+pub enum PgnError {
+    Unexpected(String, usize, usize),
+    Unterminated(&'static str, usize, usize),
+    BadFen(String, usize, usize),
+    BadMove(String, usize, usize),
+}
+
+#[derive(Debug)]
+pub enum SanError {
+    Malformed(String),
+    NoSuchMove(String),
+    Ambiguous(String),
 }
 
 impl Display for SquareParsingError {
@@ -57,12 +121,14 @@ impl Display for UndoMoveError {
 impl Display for MoveError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            _ => write!(f, "dd"),
+            MoveError::InvalidMove => write!(f, "invalid move"),
+            MoveError::InvalidFile => write!(f, "invalid file"),
+            MoveError::InvalidRank => write!(f, "invalid rank"),
         }
     }
 }
 
-impl Display for FenError<'_> {
+impl Display for FenError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FenError::NotEnoughParts => write!(f, "not enough parts in FEN string"),
@@ -76,15 +142,154 @@ impl Display for FenError<'_> {
             FenError::BadEnPassant(a) => write!(f, "bad en passant square {} in FEN string", a),
             FenError::BadHalfmove(a) => write!(f, "bad half move counter {} in FEN string", a),
             FenError::BadFullmove(a) => write!(f, "bad full move counter {} in FEN string", a),
-            _ => write!(f, "error message lol"),
+        }
+    }
+}
+
+impl Display for PstError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PstError::UnknownTable(a) => write!(f, "unknown piece-square table {}", a),
+            PstError::ValueOutsideTable(line) => {
+                write!(f, "value outside of a piece-square table on line {}", line)
+            }
+            PstError::BadValue(a, line) => {
+                write!(f, "bad piece-square table value {} on line {}", a, line)
+            }
+            PstError::WrongSize(a, count) => {
+                write!(
+                    f,
+                    "piece-square table {} has {} values instead of 64",
+                    a, count
+                )
+            }
+        }
+    }
+}
+
+impl Display for ParamsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamsError::UnknownParam(a) => write!(f, "unknown evaluation parameter {}", a),
+            ParamsError::BadValue(a, line) => {
+                write!(f, "bad evaluation parameter value {} on line {}", a, line)
+            }
+            ParamsError::WrongCount(a, count, expected) => write!(
+                f,
+                "evaluation parameter {} has {} values instead of {}",
+                a, count, expected
+            ),
+            ParamsError::BadJson(a) => write!(f, "bad evaluation parameter JSON: {}", a),
+        }
+    }
+}
+
+impl Display for TuneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TuneError::NoPositions => write!(f, "no positions to tune on"),
+            TuneError::NoResult(line) => write!(f, "no game result on line {}", line),
+            TuneError::BadPosition(line, a) => write!(f, "bad position on line {}: {}", line, a),
+        }
+    }
+}
+
+impl Display for NnueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NnueError::BadMagic => write!(f, "not a network file"),
+            NnueError::UnsupportedVersion(a) => write!(f, "unsupported network version {}", a),
+            NnueError::WrongHiddenSize(a) => write!(f, "unsupported hidden layer size {}", a),
+            NnueError::WrongLength(a, expected) => {
+                write!(f, "network file has {} bytes instead of {}", a, expected)
+            }
+        }
+    }
+}
+
+impl Display for TablebaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TablebaseError::BadName(a) => write!(f, "bad tablebase name {}", a),
+            TablebaseError::TooManyPieces(a) => {
+                write!(f, "tablebase {} has too many or too few pieces", a)
+            }
+            TablebaseError::BadMagic => write!(f, "not a tablebase file"),
+            TablebaseError::UnsupportedVersion(a) => {
+                write!(f, "unsupported tablebase version {}", a)
+            }
+            TablebaseError::Truncated => write!(f, "tablebase file is truncated"),
+            TablebaseError::WrongLength(a, expected) => {
+                write!(f, "tablebase has {} positions instead of {}", a, expected)
+            }
+        }
+    }
+}
+
+impl Display for BookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BookError::WrongLength(a) => {
+                write!(f, "book file has {} bytes, not a multiple of 16", a)
+            }
+            BookError::Unsorted => write!(f, "book entries are not sorted by key"),
+        }
+    }
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::Unexpected(a, line, column) => {
+                write!(f, "unexpected {} at line {}, column {}", a, line, column)
+            }
+            PgnError::Unterminated(a, line, column) => {
+                write!(
+                    f,
+                    "unterminated {} from line {}, column {}",
+                    a, line, column
+                )
+            }
+            PgnError::BadFen(a, line, column) => {
+                write!(f, "{} at line {}, column {}", a, line, column)
+            }
+            PgnError::BadMove(a, line, column) => {
+                write!(f, "{} at line {}, column {}", a, line, column)
+            }
+        }
+    }
+}
+
+impl Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::Malformed(a) => write!(f, "malformed move {}", a),
+            SanError::NoSuchMove(a) => write!(f, "no legal move {}", a),
+            SanError::Ambiguous(a) => write!(f, "ambiguous move {}", a),
         }
     }
 }
 
 impl Error for SquareParsingError {}
 
+impl Error for SanError {}
+
+impl Error for PgnError {}
+
+impl Error for BookError {}
+
+impl Error for TablebaseError {}
+
+impl Error for NnueError {}
+
+impl Error for TuneError {}
+
+impl Error for ParamsError {}
+
+impl Error for PstError {}
+
 impl Error for UndoMoveError {}
 
 impl Error for MoveError {}
 
-impl Error for FenError<'_> {}
+impl Error for FenError {}
@@ -0,0 +1,335 @@
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+use crate::{
+    bitboard::{adjacent_files, file_mask, squares},
+    board::Board,
+    colour::Colour,
+    endgame::{self, Endgame, SCALE_NORMAL},
+    king_safety,
+    params::{EvalParams, EVAL_PARAMS},
+    pawns::{self, front_span, pawn_attacks, relative_rank},
+    piece::Piece,
+    pst,
+};
+
+/// A pair of middlegame and endgame values, blended by the game phase.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// Interpolates between the endgame value at phase 0 and the middlegame
+    /// value at `max_phase`.
+    pub fn taper(self, phase: i32, max_phase: i32) -> i32 {
+        let phase = phase.clamp(0, max_phase);
+
+        (self.mg * phase + self.eg * (max_phase - phase)) / max_phase
+    }
+}
+
+impl Add for Score {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Self;
+
+    fn mul(self, factor: i32) -> Self {
+        Self::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl Div<i32> for Score {
+    type Output = Self;
+
+    fn div(self, divisor: i32) -> Self {
+        Self::new(self.mg / divisor, self.eg / divisor)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+/// Names of the evaluation terms, in the order an `EvalTrace` holds them.
+pub const TERM_NAMES: [&str; TERM_COUNT] = [
+    "material",
+    "placement",
+    "pawns",
+    "king safety",
+    "mobility",
+    "pieces",
+];
+const TERM_COUNT: usize = 6;
+
+/// Every term of a position's evaluation for both sides, before tapering.
+pub struct EvalTrace {
+    /// White's and black's score for each term of `TERM_NAMES`.
+    pub terms: [[Score; 2]; TERM_COUNT],
+    pub phase: i32,
+    /// The phase of the starting position, see `EvalParams::max_phase`.
+    pub max_phase: i32,
+    /// Specialised knowledge about the material on the board, if any.
+    pub endgame: Option<Endgame>,
+}
+
+impl EvalTrace {
+    /// White's score minus black's for one term.
+    pub fn term_total(&self, index: usize) -> Score {
+        let [white, black] = self.terms[index];
+
+        white - black
+    }
+
+    pub fn total(&self) -> Score {
+        (0..TERM_COUNT).fold(Score::default(), |total, index| {
+            total + self.term_total(index)
+        })
+    }
+
+    /// The tapered evaluation in centipawns from white's point of view.
+    pub fn evaluation(&self) -> i32 {
+        let total = self.total();
+
+        match self.endgame {
+            Some(Endgame::Score(score)) => score,
+            Some(Endgame::Scale(factor)) => Score::new(total.mg, total.eg * factor / SCALE_NORMAL)
+                .taper(self.phase, self.max_phase),
+            None => total.taper(self.phase, self.max_phase),
+        }
+    }
+}
+
+/// The evaluation of `board` in centipawns from white's point of view.
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_with(board, &EVAL_PARAMS.read().unwrap())
+}
+
+/// The evaluation of `board` with `params` rather than the global parameters.
+pub fn evaluate_with(board: &Board, params: &EvalParams) -> i32 {
+    trace_with(board, params).evaluation()
+}
+
+/// The evaluation of `board` broken down into its terms.
+pub fn trace(board: &Board) -> EvalTrace {
+    trace_with(board, &EVAL_PARAMS.read().unwrap())
+}
+
+fn trace_with(board: &Board, params: &EvalParams) -> EvalTrace {
+    let white_terms = evaluate_colour(board, Colour::White, params);
+    let black_terms = evaluate_colour(board, Colour::Black, params);
+
+    EvalTrace {
+        terms: std::array::from_fn(|index| [white_terms[index], black_terms[index]]),
+        phase: game_phase(board, params),
+        max_phase: params.max_phase(),
+        endgame: endgame::probe(board, params),
+    }
+}
+
+/// How much non-pawn material is left, from 0 in a pawn ending to
+/// `EvalParams::max_phase` with all pieces on the board.
+pub fn game_phase(board: &Board, params: &EvalParams) -> i32 {
+    let phase = board
+        .pieces()
+        .iter()
+        .map(|&piece| match Piece::piece_type(piece) {
+            piece_type @ Piece::Knight..=Piece::Queen => {
+                params.phase_weight[piece_type as usize - 2]
+            }
+            _ => 0,
+        })
+        .sum::<i32>();
+
+    // Promotions can push the phase past its starting value
+    phase.min(params.max_phase())
+}
+
+fn evaluate_colour(board: &Board, colour: bool, params: &EvalParams) -> [Score; TERM_COUNT] {
+    let mut material = Score::default();
+    let mut placement = Score::default();
+    for (square, &piece) in board.pieces().iter().enumerate() {
+        if !Piece::is_colour_bool(piece, colour) {
+            continue;
+        }
+
+        placement += pst::value(params, piece, square);
+        material += material_value(piece, params);
+    }
+
+    let (mobility, pieces) = evaluate_pieces(board, colour, params);
+
+    [
+        material,
+        placement,
+        pawns::evaluate(board, colour, params),
+        king_safety::evaluate(board, colour, params),
+        mobility,
+        pieces,
+    ]
+}
+
+/// Mobility, and the terms specific to each piece type.
+fn evaluate_pieces(board: &Board, colour: bool, params: &EvalParams) -> (Score, Score) {
+    let mut own_pieces = 0u64;
+    let mut own_pawns = 0u64;
+    let mut enemy_pawns = 0u64;
+    for (square, &piece) in board.pieces().iter().enumerate() {
+        if piece == Piece::None {
+            continue;
+        }
+
+        let own = Piece::is_colour_bool(piece, colour);
+        if own {
+            own_pieces |= 1 << square;
+        }
+
+        if Piece::is_type(piece, Piece::Pawn) {
+            match own {
+                true => own_pawns |= 1 << square,
+                false => enemy_pawns |= 1 << square,
+            }
+        }
+    }
+
+    // Squares attacked by an enemy pawn are not safe for any piece
+    let enemy_pawn_attacks =
+        squares(enemy_pawns).fold(0, |attacks, square| attacks | pawn_attacks(square, !colour));
+
+    let king_square = board.king_square(colour).map(|square| square as usize);
+    let enemy_king_square = board.king_square(!colour).map(|square| square as usize);
+
+    let mut mobility_score = Score::default();
+    let mut score = Score::default();
+    let mut bishops = 0;
+
+    for square in squares(own_pieces) {
+        let piece_type = Piece::piece_type(board.piece_at(square as u8));
+        if piece_type == Piece::Pawn || piece_type == Piece::King {
+            continue;
+        }
+
+        let safe_squares = board.attacks_from(square as u8) & !own_pieces & !enemy_pawn_attacks;
+        let mobility = safe_squares.count_ones() as i32;
+        mobility_score += params.mobility[piece_type as usize - 2]
+            * (mobility - params.mobility_baseline[piece_type as usize - 2]);
+
+        let file = square % 8;
+        let rank = relative_rank(square, colour);
+
+        match piece_type {
+            Piece::Knight => {
+                let chasers = front_span(square, colour) & adjacent_files(file);
+                if (3..=5).contains(&rank) && enemy_pawns & chasers == 0 {
+                    score += params.knight_outpost;
+
+                    if own_pawns & pawn_attacks(square, !colour) != 0 {
+                        score += params.defended_knight_outpost;
+                    }
+                }
+            }
+
+            Piece::Bishop => {
+                bishops += 1;
+
+                // From the bishop's side, a7 and h7 with the pawn one step diagonally back
+                let trap = match (file, rank) {
+                    (0, 6) => Some(1),
+                    (7, 6) => Some(6),
+                    _ => None,
+                };
+                if let Some(trap_file) = trap {
+                    let trap_square = match colour {
+                        Colour::White => 40 + trap_file,
+                        Colour::Black => 16 + trap_file,
+                    };
+
+                    if enemy_pawns & 1 << trap_square != 0 {
+                        score += params.trapped_bishop;
+                    }
+                }
+            }
+
+            Piece::Rook => {
+                match (own_pawns & file_mask(file), enemy_pawns & file_mask(file)) {
+                    (0, 0) => score += params.rook_on_open_file,
+                    (0, _) => score += params.rook_on_semi_open_file,
+                    _ => {}
+                }
+
+                let enemy_king_on_eighth = enemy_king_square
+                    .is_some_and(|king_square| relative_rank(king_square, colour) == 7);
+                let enemy_pawns_on_seventh =
+                    squares(enemy_pawns).any(|pawn_square| relative_rank(pawn_square, colour) == 6);
+                if rank == 6 && (enemy_king_on_eighth || enemy_pawns_on_seventh) {
+                    score += params.rook_on_seventh;
+                }
+
+                // A king that stepped aside without castling shuts the rook in the corner,
+                // while one still on its starting file can castle it out
+                if let Some(king_square) = king_square {
+                    let king_file = king_square % 8;
+                    let cornered =
+                        (king_file > 4 && file > king_file) || (king_file < 4 && file < king_file);
+
+                    if relative_rank(king_square, colour) == 0
+                        && rank == 0
+                        && cornered
+                        && mobility <= params.trapped_rook_max_mobility
+                    {
+                        score += params.trapped_rook;
+                    }
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    if bishops >= 2 {
+        score += params.bishop_pair;
+    }
+
+    (mobility_score, score)
+}
+
+pub fn material_value(piece: u8, params: &EvalParams) -> Score {
+    match Piece::piece_type(piece) {
+        piece_type @ Piece::Pawn..=Piece::Queen => params.material[piece_type as usize - 1],
+        _ => Score::default(),
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    bitboard::{file_mask, squares},
+    board::Board,
+    evaluation::Score,
+    params::EvalParams,
+    pawns::relative_rank,
+    piece::Piece,
+};
+
+/// The king safety of `colour`, as seen from `colour`'s point of view.
+pub fn evaluate(board: &Board, colour: bool, params: &EvalParams) -> Score {
+    let Some(king_square) = board.king_square(colour) else {
+        return Score::default();
+    };
+
+    pawn_cover(board, king_square as usize, colour, params)
+        + king_attacks(board, king_square, colour, params)
+}
+
+/// Pawn shield, pawn storm and open files on the king's file and its neighbours.
+fn pawn_cover(board: &Board, king_square: usize, colour: bool, params: &EvalParams) -> Score {
+    let mut own_pawns = 0u64;
+    let mut enemy_pawns = 0u64;
+    for (square, &piece) in board.pieces().iter().enumerate() {
+        if Piece::is_type(piece, Piece::Pawn) {
+            match Piece::is_colour_bool(piece, colour) {
+                true => own_pawns |= 1 << square,
+                false => enemy_pawns |= 1 << square,
+            }
+        }
+    }
+
+    let king_file = king_square % 8;
+    let king_rank = relative_rank(king_square, colour);
+    let mut score = Score::default();
+
+    for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+        let in_front = |pawns: u64| {
+            squares(pawns & file_mask(file))
+                .map(|square| relative_rank(square, colour))
+                .filter(|&rank| rank > king_rank)
+                .min()
+        };
+
+        match in_front(own_pawns) {
+            Some(rank) => score += params.pawn_shield[rank],
+            None => score += params.missing_shield_pawn,
+        }
+
+        if let Some(rank) = in_front(enemy_pawns) {
+            score += params.pawn_storm[rank];
+        }
+
+        match (own_pawns & file_mask(file), enemy_pawns & file_mask(file)) {
+            (0, 0) => score += params.open_file_near_king,
+            (0, _) => score += params.semi_open_file_near_king,
+            _ => {}
+        }
+    }
+
+    score
+}
+
+/// Penalty from the enemy pieces bearing on the squares around the king.
+///
+/// Every attacked zone square adds units by the attacker's type, and the
+/// danger grows with the square of the total so that coordinated attacks
+/// weigh far more than the same attacks taken one at a time.
+fn king_attacks(board: &Board, king_square: u8, colour: bool, params: &EvalParams) -> Score {
+    let zone = board.attacks_from(king_square) | 1 << king_square;
+
+    let mut attackers = 0;
+    let mut units = 0;
+    let mut attacked_zone = 0u64;
+    let mut defended = 0u64;
+    for (square, &piece) in board.pieces().iter().enumerate() {
+        let piece_type = Piece::piece_type(piece);
+        if piece == Piece::None || piece_type == Piece::King {
+            continue;
+        }
+
+        let attacks = board.attacks_from(square as u8);
+        if Piece::is_colour_bool(piece, colour) {
+            defended |= attacks;
+            continue;
+        }
+
+        let attacked = attacks & zone;
+        if attacked != 0 && piece_type != Piece::Pawn {
+            attackers += 1;
+            units += params.attack_weight[piece_type as usize - 2] * attacked.count_ones() as i32;
+            attacked_zone |= attacked;
+        }
+    }
+
+    units += params.undefended_zone_square_weight * (attacked_zone & !defended).count_ones() as i32;
+
+    if attackers < params.min_king_attackers {
+        return Score::default();
+    }
+
+    let danger = params.king_danger * (units * units) / 64;
+
+    -Score::new(
+        danger.mg.min(params.max_king_danger.mg),
+        danger.eg.min(params.max_king_danger.eg),
+    )
+}
@@ -0,0 +1,230 @@
+//! King and pawn against king bitbase.
+//!
+//! Every position with the pawn on the a to d files, the others being their
+//! mirror images, is classified as a win or a draw for the side with the
+//! pawn by retrograde analysis: positions won or drawn outright are marked
+//! first, then the rest are resolved from their successors until nothing
+//! changes any more. Squares are seen from the side with the pawn, the pawn
+//! moving up the board.
+
+use lazy_static::lazy_static;
+
+use crate::{
+    bitboard::{king_attacks, squares},
+    board::Board,
+    colour::Colour,
+    pawns::pawn_attacks,
+    piece::Piece,
+};
+
+/// Side to move, defending king, attacking king and the 24 pawn squares.
+const POSITIONS: usize = 2 * 64 * 64 * 24;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+lazy_static! {
+    /// One bit per position, set for wins.
+    static ref BITBASE: Vec<u64> = generate();
+}
+
+/// Generates the bitbase now rather than on the first probe.
+pub fn init() {
+    lazy_static::initialize(&BITBASE);
+}
+
+/// Whether the side with the pawn wins, if `board` is a king and pawn
+/// against king ending.
+pub fn probe(board: &Board) -> Option<bool> {
+    let mut pawn = None;
+    let mut kings = 0;
+
+    for (square, &piece) in board.pieces().iter().enumerate() {
+        match Piece::piece_type(piece) {
+            Piece::None => {}
+            Piece::King => kings += 1,
+            Piece::Pawn if pawn.is_none() => pawn = Some((square, Piece::colour_bool(piece))),
+            _ => return None,
+        }
+    }
+
+    let (pawn, strong) = pawn?;
+    // Pawns on the first or last rank, which a FEN can set up, are not indexed
+    if kings != 2 || matches!(pawn / 8, 0 | 7) {
+        return None;
+    }
+
+    let strong_king = board.king_square(strong)? as usize;
+    let weak_king = board.king_square(!strong)? as usize;
+
+    // Black's pawn goes up the board once flipped, and e to h file pawns
+    // become a to d file pawns once mirrored
+    let ranks = if strong == Colour::White { 0 } else { 56 };
+    let files = if pawn % 8 > 3 { 7 } else { 0 };
+    let normalise = |square: usize| square ^ ranks ^ files;
+
+    let index = index(
+        board.get_colour_to_move() == strong,
+        normalise(strong_king),
+        normalise(weak_king),
+        normalise(pawn),
+    );
+
+    Some(BITBASE[index / 64] & (1 << (index % 64)) != 0)
+}
+
+fn index(strong_to_move: bool, strong_king: usize, weak_king: usize, pawn: usize) -> usize {
+    let pawn_index = pawn % 8 + 4 * (pawn / 8 - 1);
+
+    usize::from(strong_to_move) | weak_king << 1 | strong_king << 7 | pawn_index << 13
+}
+
+fn generate() -> Vec<u64> {
+    let mut results = (0..POSITIONS).map(initial_result).collect::<Vec<_>>();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for index in 0..POSITIONS {
+            if results[index] == UNKNOWN {
+                results[index] = classify(&results, index);
+                changed |= results[index] != UNKNOWN;
+            }
+        }
+    }
+
+    let mut bitbase = vec![0; POSITIONS.div_ceil(64)];
+    for (index, _) in results.iter().enumerate().filter(|&(_, &r)| r == WIN) {
+        bitbase[index / 64] |= 1 << (index % 64);
+    }
+
+    bitbase
+}
+
+/// Side to move and the attacking king, defending king and pawn squares.
+fn decode(index: usize) -> (bool, usize, usize, usize) {
+    let pawn_index = index >> 13;
+
+    (
+        index & 1 == 1,
+        (index >> 7) & 63,
+        (index >> 1) & 63,
+        8 * (pawn_index / 4 + 1) + pawn_index % 4,
+    )
+}
+
+/// Illegal positions and the ones decided on the spot.
+fn initial_result(index: usize) -> u8 {
+    let (strong_to_move, strong_king, weak_king, pawn) = decode(index);
+    let promotion_square = pawn + 8;
+
+    if distance(strong_king, weak_king) <= 1
+        || strong_king == pawn
+        || weak_king == pawn
+        || (strong_to_move && pawn_attacks(pawn, Colour::White) & (1 << weak_king) != 0)
+    {
+        return INVALID;
+    }
+
+    // The pawn promotes safely
+    if strong_to_move
+        && pawn / 8 == 6
+        && strong_king != promotion_square
+        && weak_king != promotion_square
+        && (distance(weak_king, promotion_square) > 1
+            || distance(strong_king, promotion_square) == 1)
+    {
+        return WIN;
+    }
+
+    if !strong_to_move {
+        let attacked = king_attacks(strong_king) | pawn_attacks(pawn, Colour::White);
+        let escapes = king_attacks(weak_king) & !attacked;
+
+        // Stalemate, or the pawn is lost
+        if escapes == 0 || escapes & (1 << pawn) != 0 {
+            return DRAW;
+        }
+    }
+
+    UNKNOWN
+}
+
+/// The result of a position from the results of its successors.
+fn classify(results: &[u8], index: usize) -> u8 {
+    let (strong_to_move, strong_king, weak_king, pawn) = decode(index);
+    let mut successors = 0;
+
+    if strong_to_move {
+        for square in squares(king_attacks(strong_king) & !king_attacks(weak_king)) {
+            successors |= results[self::index(false, square, weak_king, pawn)];
+        }
+
+        // Promotions were settled by the initial results
+        let push = pawn + 8;
+        if pawn / 8 < 6 && push != strong_king && push != weak_king {
+            successors |= results[self::index(false, strong_king, weak_king, push)];
+
+            let double_push = push + 8;
+            if pawn / 8 == 1 && double_push != strong_king && double_push != weak_king {
+                successors |= results[self::index(false, strong_king, weak_king, double_push)];
+            }
+        }
+    } else {
+        let attacked = king_attacks(strong_king) | pawn_attacks(pawn, Colour::White);
+        for square in squares(king_attacks(weak_king) & !attacked) {
+            successors |= results[self::index(true, strong_king, square, pawn)];
+        }
+    }
+
+    let (good, bad) = match strong_to_move {
+        true => (WIN, DRAW),
+        false => (DRAW, WIN),
+    };
+
+    if successors & good != 0 {
+        good
+    } else if successors & UNKNOWN != 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+/// King moves between two squares.
+fn distance(a: usize, b: usize) -> usize {
+    (a % 8).abs_diff(b % 8).max((a / 8).abs_diff(b / 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe_fen(fen: &str) -> Option<bool> {
+        probe(&Board::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn wins_and_draws() {
+        // The king in front of its pawn wins whoever moves
+        assert_eq!(probe_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(true));
+        assert_eq!(probe_fen("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Some(true));
+        // Further back, the defending king takes the opposition
+        assert_eq!(probe_fen("4k3/8/8/4K3/4P3/8/8/8 b - - 0 1"), Some(false));
+        // A rook pawn is a draw once the defending king reaches the corner
+        assert_eq!(probe_fen("k7/8/1K6/P7/8/8/8/8 w - - 0 1"), Some(false));
+        // The same with black's pawn
+        assert_eq!(probe_fen("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1"), Some(true));
+    }
+
+    #[test]
+    fn pawns_on_the_back_ranks_are_not_probed() {
+        assert_eq!(probe_fen("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"), None);
+        assert_eq!(probe_fen("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"), None);
+        assert_eq!(probe_fen("4k3/8/8/8/8/8/8/p3K3 b - - 0 1"), None);
+        assert_eq!(probe_fen("p3k3/8/8/8/8/8/8/4K3 b - - 0 1"), None);
+    }
+}
@@ -1,29 +1,56 @@
 use std::{
     error::Error,
-    io::{stdin, stdout, Write},
+    io::{stdin, stdout, IsTerminal, Write},
+    time::{Duration, Instant},
 };
 
-use errors::{MoveError, SquareParsingError};
+use book::Book;
+use endgame::{Endgame, SCALE_NORMAL};
+use evaluation::{EvalTrace, TERM_NAMES};
 use moves::Move;
-use utils::{get_file_number, string_to_square};
-
-use crate::{
-    board::Board,
-    castling_rights::CastlingRights,
-    colour::Colour,
-    utils::{get_file_letter, square_to_coods},
+use nnue::Network;
+use params::{set_eval_params, EvalParams, EVAL_PARAMS};
+use pgn::{PgnGame, PgnMove};
+use san::{line_to_san, parse_san, to_san};
+use search::{
+    score_to_mate, PvLine, SearchFeature, SearchInfo, SearchLimits, SearchResult, Searcher,
 };
+use tablebase::Dtm;
+use tt::TranspositionTable;
+
+use crate::{board::Board, castling_rights::CastlingRights, colour::Colour};
 
 // use colored::*;
 use color_print::cprintln;
 
+mod bitboard;
 mod board;
+mod book;
 mod castling_rights;
 mod colour;
+mod datagen;
+mod endgame;
 mod errors;
+mod evaluation;
+mod king_safety;
+mod kpk;
 mod moves;
+mod nnue;
+mod params;
+mod pawns;
+mod pgn;
 mod piece;
+mod pst;
+mod san;
+mod search;
+mod syzygy;
+mod tablebase;
+mod tt;
+mod tuner;
+mod uci;
 mod utils;
+mod xboard;
+mod zobrist;
 
 type Result<T> = core::result::Result<T, Box<dyn Error>>;
 
@@ -31,7 +58,17 @@ fn main() -> Result<()> {
     // let mut board = Board::from_fen("8/8/8/8/8/8/8/8 w QKqk - 0 1").unwrap();
     // let mut board =
     //     Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w QKqk - 0 1").unwrap();
+    kpk::init();
+
     let mut board = Board::new();
+    let mut searcher = Searcher::new(TranspositionTable::DEFAULT_SIZE_MB);
+    searcher.set_info_callback(Box::new(print_search_info));
+    let mut book = None;
+    // Time spent on each move played, for the clock annotations of saved games
+    let mut move_times = Vec::new();
+    let mut last_move_time = Instant::now();
+    // Game loaded from a PGN file, stepped through with `next` and `prev`
+    let mut loaded_game: Option<PgnGame> = None;
     // let mut board = Board::from_fen("8/5ppp/p1p3P1/1P2P3/5p2/6p1/5PP1/8 w - - 0 1").unwrap();
     // let mut board =
     // Board::from_fen("8/3p1ppp/p1p3P1/1PP1P3/p1p2p2/6p1/1P3PP1/8 w - - 0 1").unwrap();
@@ -39,15 +76,23 @@ fn main() -> Result<()> {
 
     // for line in std::io::stdin().lock().lines().map(|r| r.unwrap()) {
     loop {
-        let moves = board.generate_moves();
+        let moves = board.generate_legal_moves();
 
-        let line = get_line()?;
+        let Some(line) = get_line()? else {
+            return Ok(());
+        };
 
         let (command, args) = process_line(line);
 
         match command.as_str() {
             "" => {}
 
+            "quit" | "exit" => return Ok(()),
+
+            "uci" => return uci::run(),
+
+            "xboard" => return xboard::run(),
+
             "ep" => {
                 cprintln!(
                     "<green>en passant square:</> {:?}",
@@ -95,7 +140,10 @@ fn main() -> Result<()> {
             }
 
             "listmoves" | "list" | "moves" | "ls" => {
-                let moves_list = moves.iter().map(|&m| repr_move(m)).collect::<Vec<_>>();
+                let moves_list = moves
+                    .iter()
+                    .map(|&m| to_san(&mut board, m))
+                    .collect::<Vec<_>>();
                 cprintln!("<green>moves ({}):</> {:?}", moves.len(), moves_list);
             }
 
@@ -106,10 +154,23 @@ fn main() -> Result<()> {
             "unmake" | "undo" => {
                 if let Err(e) = board.undo_move() {
                     cprintln!("<red>cannot undo:</> {:?}", e);
+                } else {
+                    move_times.pop();
                 }
             }
 
-            "eval" | "evaluate" | "evaluation" => {
+            "eval" | "evaluate" | "evaluation" => 'blk: {
+                if let Some([subcommand]) = args.as_deref() {
+                    if subcommand != "trace" {
+                        cprintln!("<red>usage:</> eval [trace]");
+                        break 'blk;
+                    }
+
+                    print_eval_trace(&evaluation::trace(&board));
+                    break 'blk;
+                }
+
+                board.set_network(nnue::active_network());
                 let eval = board.evaluate();
                 let eval_string = if eval > 0 {
                     "+".to_string() + &eval.to_string()
@@ -120,6 +181,245 @@ fn main() -> Result<()> {
                 cprintln!("<green>evaluation:</> <bold, blue>{}</>", eval_string);
             }
 
+            "go" | "search" => 'blk: {
+                let limits = match parse_search_limits(args.as_deref().unwrap_or_default()) {
+                    Ok(limits) => limits,
+                    Err(e) => {
+                        cprintln!("<red>invalid search limits:</> {}", e);
+                        break 'blk;
+                    }
+                };
+
+                let result = searcher.search(&mut board, limits);
+                print_search_result(&board, &result);
+            }
+
+            "features" => {
+                for feature in SearchFeature::LIST {
+                    let state = if searcher.is_enabled(feature) {
+                        "on"
+                    } else {
+                        "off"
+                    };
+
+                    cprintln!("<green>{}:</> {}", SearchFeature::name(feature), state);
+                }
+            }
+
+            "feature" => 'blk: {
+                let Some([name, state]) = args.as_deref() else {
+                    cprintln!("<red>usage:</> feature <<name> <<on|off>");
+                    break 'blk;
+                };
+
+                let Some(feature) = SearchFeature::from_name(name) else {
+                    cprintln!("<red>unknown search feature:</> {}", name);
+                    break 'blk;
+                };
+
+                let enabled = match state.as_str() {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => {
+                        cprintln!("<red>invalid feature state:</> {}", state);
+                        break 'blk;
+                    }
+                };
+
+                searcher.set_feature(feature, enabled);
+                cprintln!("<green>{}:</> {}", SearchFeature::name(feature), state);
+            }
+
+            "threads" => 'blk: {
+                let Some(threads) = args.as_ref().and_then(|args| args.first()) else {
+                    cprintln!("<green>threads:</> {}", searcher.threads());
+                    break 'blk;
+                };
+
+                match threads.parse() {
+                    Ok(threads) => {
+                        searcher.set_threads(threads);
+                        cprintln!("<green>threads:</> {}", searcher.threads());
+                    }
+                    Err(e) => cprintln!("<red>invalid thread count</> ({}): {}", e, threads),
+                }
+            }
+
+            "pst" => match args.as_deref() {
+                Some([action, path]) if action == "load" => {
+                    let mut params = EVAL_PARAMS.read().unwrap().clone();
+                    match pst::load(path, &mut params) {
+                        Ok(()) => {
+                            set_eval_params(params);
+                            cprintln!("<green>loaded piece-square tables:</> {}", path);
+                        }
+                        Err(e) => cprintln!("<red>cannot load piece-square tables:</> {}", e),
+                    }
+                }
+                Some([action]) if action == "reset" => {
+                    let mut params = EVAL_PARAMS.read().unwrap().clone();
+                    pst::reset(&mut params);
+                    set_eval_params(params);
+                    cprintln!("<green>restored default piece-square tables</>");
+                }
+                _ => cprintln!("<red>usage:</> pst load <<file> | pst reset"),
+            },
+
+            "nnue" => match args.as_deref() {
+                Some([action, path]) if action == "load" => match Network::load(path) {
+                    Ok(network) => {
+                        nnue::set_network(Some(network));
+                        nnue::set_enabled(true);
+                        cprintln!("<green>loaded network:</> {}", path);
+                    }
+                    Err(e) => cprintln!("<red>cannot load network:</> {}", e),
+                },
+                Some([state]) if state == "on" || state == "off" => {
+                    nnue::set_enabled(state == "on");
+
+                    if state == "on" && !nnue::is_loaded() {
+                        cprintln!("<red>no network loaded, using the hand-crafted evaluation</>");
+                    } else {
+                        cprintln!("<green>nnue:</> {}", state);
+                    }
+                }
+                _ => cprintln!("<red>usage:</> nnue load <<file> | nnue <<on|off>"),
+            },
+
+            "book" => match args.as_deref() {
+                Some([action, path]) if action == "load" => match Book::load(path) {
+                    Ok(loaded) => {
+                        book = Some(loaded);
+                        cprintln!("<green>loaded book:</> {}", path);
+                    }
+                    Err(e) => cprintln!("<red>cannot load book:</> {}", e),
+                },
+                None => match &book {
+                    Some(book) => {
+                        let moves = book.moves(&mut board);
+                        let total = moves.iter().map(|&(_, weight)| weight as f64).sum::<f64>();
+
+                        cprintln!("<green>book moves ({}):</>", moves.len());
+                        for (v_move, weight) in moves {
+                            println!(
+                                "  {:<6} {:>6} {:>5.1}%",
+                                to_san(&mut board, v_move),
+                                weight,
+                                100. * weight as f64 / total.max(1.)
+                            );
+                        }
+                    }
+                    None => cprintln!("<red>no book loaded</>"),
+                },
+                Some([action, output, max_ply, min_games, pgns @ ..])
+                    if action == "build" && !pgns.is_empty() =>
+                {
+                    match (max_ply.parse(), min_games.parse()) {
+                        (Ok(max_ply), Ok(min_games)) => {
+                            match book::build(pgns, output, max_ply, min_games) {
+                                Ok(()) => cprintln!("<green>built book:</> {}", output),
+                                Err(e) => cprintln!("<red>cannot build book:</> {}", e),
+                            }
+                        }
+                        _ => cprintln!("<red>invalid book depth or minimum number of games</>"),
+                    }
+                }
+                _ => cprintln!(
+                    "<red>usage:</> book load <<file> | book build <<output> <<plies> <<min games> <<pgn>... | book"
+                ),
+            },
+
+            "tablebase" | "tb" => match args.as_deref() {
+                Some([action, directory, names @ ..])
+                    if action == "generate" && !names.is_empty() =>
+                {
+                    for name in names {
+                        if let Err(e) = tablebase::generate(name, directory) {
+                            cprintln!("<red>cannot generate tablebase {}:</> {}", name, e);
+                            break;
+                        }
+                    }
+                }
+                Some([action, directory]) if action == "load" => match tablebase::load(directory) {
+                    Ok(count) => cprintln!("<green>loaded {} tablebases:</> {}", count, directory),
+                    Err(e) => cprintln!("<red>cannot load tablebases:</> {}", e),
+                },
+                Some([action]) if action == "probe" => match tablebase::probe(&board) {
+                    Some(Dtm::Win(plies)) => cprintln!("<green>win</>, mate in {} plies", plies),
+                    Some(Dtm::Loss(plies)) => cprintln!("<red>loss</>, mated in {} plies", plies),
+                    Some(Dtm::Draw) => cprintln!("<yellow>draw</>"),
+                    None => cprintln!("<red>position not in the loaded tablebases</>"),
+                },
+                _ => cprintln!(
+                    "<red>usage:</> tb generate <<dir> <<material>... | tb load <<dir> | tb probe"
+                ),
+            },
+
+            "params" => match args.as_deref() {
+                Some([action, path]) if action == "load" => match EvalParams::load(path) {
+                    Ok(params) => {
+                        set_eval_params(params);
+                        cprintln!("<green>loaded evaluation parameters:</> {}", path);
+                    }
+                    Err(e) => cprintln!("<red>cannot load evaluation parameters:</> {}", e),
+                },
+                Some([action, path]) if action == "save" => {
+                    match EVAL_PARAMS.read().unwrap().save(path) {
+                        Ok(()) => cprintln!("<green>saved evaluation parameters:</> {}", path),
+                        Err(e) => cprintln!("<red>cannot save evaluation parameters:</> {}", e),
+                    }
+                }
+                Some([action]) if action == "reset" => {
+                    set_eval_params(EvalParams::default());
+                    cprintln!("<green>restored default evaluation parameters</>");
+                }
+                _ => cprintln!(
+                    "<red>usage:</> params load <<file> | params save <<file> | params reset"
+                ),
+            },
+
+            "datagen" => 'blk: {
+                let Some([output, games, rest @ ..]) = args.as_deref() else {
+                    cprintln!("<red>usage:</> datagen <<output> <<games> [nodes] [threads]");
+                    break 'blk;
+                };
+
+                let games = games.parse::<usize>();
+                let nodes = rest
+                    .first()
+                    .map_or(Ok(datagen::DEFAULT_NODES), |n| n.parse());
+                let threads = rest.get(1).map_or(Ok(1), |n| n.parse::<usize>());
+
+                let (Ok(games), Ok(nodes), Ok(threads)) = (games, nodes, threads) else {
+                    cprintln!("<red>invalid datagen arguments</>");
+                    break 'blk;
+                };
+
+                if let Err(e) = datagen::run(output, games, nodes, threads) {
+                    cprintln!("<red>cannot generate data:</> {}", e);
+                }
+            }
+
+            "tune" => 'blk: {
+                let (positions, output, passes) = match args.as_deref() {
+                    Some([positions, output]) => (positions, output, Ok(tuner::DEFAULT_PASSES)),
+                    Some([positions, output, passes]) => (positions, output, passes.parse()),
+                    _ => {
+                        cprintln!("<red>usage:</> tune <<positions> <<output> [passes]");
+                        break 'blk;
+                    }
+                };
+
+                let Ok(passes) = passes else {
+                    cprintln!("<red>invalid number of passes</>");
+                    break 'blk;
+                };
+
+                if let Err(e) = tuner::tune(positions, output, passes) {
+                    cprintln!("<red>cannot tune:</> {}", e);
+                }
+            }
+
             "play" | "move" => 'blk: {
                 let Some(args) = args else {
                     cprintln!("<red>not enough arguments: no move provided</>");
@@ -134,12 +434,150 @@ fn main() -> Result<()> {
                     }
                 };
 
-                let move_err = make_move(move_str, &moves, &mut board);
-                if let Err(e) = move_err {
-                    cprintln!("<red>invalid move</> ({:?}): {}", e, move_str);
+                let san = match make_move(move_str, &mut board) {
+                    Ok(san) => san,
+                    Err(e) => {
+                        cprintln!("<red>invalid move</> ({}): {}", e, move_str);
+                        break 'blk;
+                    }
+                };
+                cprintln!("<green>played move:</> {}", san);
+
+                move_times.push(last_move_time.elapsed());
+                last_move_time = Instant::now();
+            }
+
+            "load" => 'blk: {
+                let (path, number) = match args.as_deref() {
+                    Some([path]) => (path, Ok(1)),
+                    Some([path, number]) => (path, number.parse::<usize>()),
+                    _ => {
+                        cprintln!("<red>usage:</> load <<file> [game number]");
+                        break 'blk;
+                    }
+                };
+
+                let Ok(number) = number else {
+                    cprintln!("<red>invalid game number</>");
                     break 'blk;
+                };
+
+                let mut games = match pgn::load(path) {
+                    Ok(games) => games,
+                    Err(e) => {
+                        cprintln!("<red>cannot load games:</> {}", e);
+                        break 'blk;
+                    }
+                };
+
+                if number == 0 || number > games.len() {
+                    cprintln!("<red>no game {} in {} ({} games)</>", number, path, games.len());
+                    break 'blk;
+                }
+
+                let game = match games.swap_remove(number - 1) {
+                    Ok(game) => game,
+                    Err(e) => {
+                        cprintln!("<red>cannot load game {}:</> {}", number, e);
+                        break 'blk;
+                    }
+                };
+
+                board = match game.start_board() {
+                    Ok(start) => start,
+                    Err(e) => {
+                        cprintln!("<red>cannot load game {}:</> {}", number, e);
+                        break 'blk;
+                    }
+                };
+                move_times.clear();
+                last_move_time = Instant::now();
+
+                cprintln!(
+                    "<green>loaded game {}:</> {} - {}, {} ({} moves, {})",
+                    number,
+                    game.tag("White").unwrap_or("?"),
+                    game.tag("Black").unwrap_or("?"),
+                    game.tag("Event").unwrap_or("?"),
+                    game.moves.len(),
+                    game.result()
+                );
+                if let Some(comment) = game.moves.first().and_then(|m| m.comment_before.as_ref()) {
+                    cprintln!("<green>comment:</> {}", comment);
+                }
+
+                loaded_game = Some(game);
+            }
+
+            "next" | "prev" => 'blk: {
+                let Some(game) = &loaded_game else {
+                    cprintln!("<red>no game loaded</>");
+                    break 'blk;
+                };
+
+                let count = match args.as_ref().and_then(|args| args.first()) {
+                    Some(count) => count.parse::<usize>(),
+                    None => Ok(1),
+                };
+                let Ok(count) = count else {
+                    cprintln!("<red>usage:</> {} [moves]", command);
+                    break 'blk;
+                };
+
+                let played = board.played_moves();
+                let follows_game = played.len() <= game.moves.len()
+                    && played
+                        .iter()
+                        .zip(&game.moves)
+                        .all(|(&v_move, pgn_move)| v_move == pgn_move.v_move);
+                if !follows_game {
+                    cprintln!("<red>the board has left the loaded game</>");
+                    break 'blk;
+                }
+
+                if command == "prev" {
+                    for _ in 0..count.min(played.len()) {
+                        let _ = board.undo_move();
+                        move_times.pop();
+                    }
+
+                    cprintln!("<green>moves played:</> {}", board.played_moves().len());
+                    break 'blk;
+                }
+
+                for pgn_move in game.moves.iter().skip(played.len()).take(count) {
+                    print_pgn_move(&board, pgn_move);
+
+                    board.make_move(pgn_move.v_move);
+                    move_times.push(Duration::ZERO);
+                }
+
+                if board.played_moves().len() == game.moves.len() {
+                    cprintln!("<green>end of game:</> {}", game.result());
+                }
+            }
+
+            "save" => 'blk: {
+                let (path, clock) = match args.as_deref() {
+                    Some([path]) => (path, false),
+                    Some([path, option]) if option == "clock" => (path, true),
+                    _ => {
+                        cprintln!("<red>usage:</> save <<file> [clock]");
+                        break 'blk;
+                    }
+                };
+
+                let mut game = PgnGame::from_board(&board);
+                if clock {
+                    for (pgn_move, &time) in game.moves.iter_mut().zip(&move_times) {
+                        pgn_move.comment = Some(pgn::elapsed_time_comment(time));
+                    }
+                }
+
+                match pgn::save(path, &game) {
+                    Ok(()) => cprintln!("<green>saved game:</> {}", path),
+                    Err(e) => cprintln!("<red>cannot save game:</> {}", e),
                 }
-                cprintln!("<green>played move:</> {}", move_str);
             }
 
             _ => {
@@ -149,12 +587,18 @@ fn main() -> Result<()> {
     }
 }
 
-fn get_line() -> Result<String> {
-    print!("> ");
-    stdout().flush()?;
+/// Reads the next command, or `None` once stdin is closed.
+fn get_line() -> Result<Option<String>> {
+    // No prompt when driven by a program, which may be a UCI GUI about to send `uci`
+    if stdin().is_terminal() {
+        print!("> ");
+        stdout().flush()?;
+    }
 
     let mut line = String::new();
-    stdin().read_line(&mut line)?;
+    if stdin().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
     if let Some('\n') = line.chars().next_back() {
         line.pop();
     }
@@ -162,7 +606,7 @@ fn get_line() -> Result<String> {
         line.pop();
     }
 
-    Ok(line)
+    Ok(Some(line))
 }
 
 fn process_line(line: String) -> (String, Option<Vec<String>>) {
@@ -186,75 +630,217 @@ fn process_line(line: String) -> (String, Option<Vec<String>>) {
     (command, args)
 }
 
-fn make_move(move_string: &str, moves: &[u16], board: &mut Board) -> Result<()> {
-    let v_move = process_move(move_string)?;
+fn parse_search_limits(args: &[String]) -> Result<SearchLimits> {
+    let mut limits = SearchLimits::default();
+
+    let mut args = args.iter();
+    while let Some(name) = args.next() {
+        let value = args.next().ok_or("missing value")?;
 
-    if !moves.contains(&v_move) {
-        return Err(Box::new(MoveError::InvalidMove));
+        match name.as_str() {
+            "depth" => limits.depth = Some(value.parse()?),
+            "movetime" => limits.movetime = Some(Duration::from_millis(value.parse()?)),
+            "nodes" => limits.nodes = Some(value.parse()?),
+            "multipv" => limits.multi_pv = value.parse()?,
+            _ => return Err(format!("unknown limit {}", name).into()),
+        }
     }
 
-    board.make_move(v_move);
+    if limits.depth.is_none() && limits.movetime.is_none() && limits.nodes.is_none() {
+        limits.depth = Some(Searcher::DEFAULT_DEPTH);
+    }
 
-    Ok(())
+    Ok(limits)
 }
 
-fn process_move(string: &str) -> Result<u16> {
-    if string == "o-o" || string == "O-O" || string == "0-0" {
-        return Ok(Move::ShortCastle);
+fn print_search_info(board: &Board, info: &SearchInfo) {
+    // Root moves are only useful to GUIs, and the full multi-PV table is printed at the end
+    let SearchInfo::Iteration(info) = info else {
+        return;
+    };
+
+    if info.multi_pv != 1 {
+        return;
     }
 
-    if string == "o-o-o" || string == "O-O-O" || string == "0-0-0" {
-        return Ok(Move::LongCastle);
+    let pv = line_to_san(board, &info.pv);
+
+    cprintln!(
+        "<green>depth {:>2}/{:<2}</> <bold, blue>{:>7}</> {:>10} nodes {:>9} nps {:>4}‰ hash {:>6} ms  {}",
+        info.depth,
+        info.seldepth,
+        format_score(info.score),
+        info.nodes,
+        info.nps,
+        info.hashfull,
+        info.time.as_millis(),
+        pv.join(" ")
+    );
+}
+
+fn print_search_result(board: &Board, result: &SearchResult) {
+    let Some(best_move) = result.best_move else {
+        cprintln!("<red>no legal moves</>");
+        return;
+    };
+
+    let pv = line_to_san(board, &result.pv);
+    let stats = &result.stats;
+
+    if result.lines.len() > 1 {
+        print_lines_table(board, &result.lines);
+    } else {
+        let best_move = to_san(&mut board.clone(), best_move);
+
+        cprintln!("<green>best move:</> <bold>{}</>", best_move);
+        cprintln!(
+            "<green>score:</> <bold, blue>{}</>",
+            format_score(result.score)
+        );
+        cprintln!("<green>pv:</> {}", pv.join(" "));
     }
+    cprintln!(
+        "<green>depth:</> {} ({} selective)",
+        result.depth,
+        stats.seldepth
+    );
+    cprintln!(
+        "<green>nodes:</> {} ({} quiescence) in {} ms",
+        stats.nodes,
+        stats.quiescence_nodes,
+        result.elapsed.as_millis()
+    );
+    cprintln!(
+        "<green>check extensions:</> {}  <green>singular extensions:</> {} ({} tested)",
+        stats.check_extensions,
+        stats.singular_extensions,
+        stats.singular_searches
+    );
+}
 
-    let departure_square = string
-        .get(0..=1)
-        .ok_or(SquareParsingError::NotEnoughParts)?;
+fn print_lines_table(board: &Board, lines: &[PvLine]) {
+    cprintln!("<green>{:>3}  {:<7} {:>7}  pv</>", "#", "move", "score");
 
-    let target_square = string
-        .get(2..=3)
-        .ok_or(SquareParsingError::NotEnoughParts)?;
+    for (rank, line) in lines.iter().enumerate() {
+        let pv = line_to_san(board, &line.pv);
 
-    let departure_square = string_to_square(departure_square)?;
-    let target_square = string_to_square(target_square)?;
+        cprintln!(
+            "{:>3}  <bold>{:<7}</> <blue>{:>7}</>  {}",
+            rank + 1,
+            pv.first().map_or("", String::as_str),
+            format_score(line.score),
+            pv.join(" ")
+        );
+    }
+}
 
-    let en_passant_flag = match string.get(4..=4) {
-        Some(x) => {
-            if x == "*" {
-                Move::EnPassant
-            } else {
-                0
-            }
-        }
-        None => 0,
-    };
+fn print_eval_trace(trace: &EvalTrace) {
+    cprintln!(
+        "<green>{:<12} {:>13} {:>13} {:>13}</>",
+        "term",
+        "white",
+        "black",
+        "total"
+    );
+    cprintln!(
+        "<green>{:<12} {:>6} {:>6} {:>6} {:>6} {:>6} {:>6}</>",
+        "",
+        "mg",
+        "eg",
+        "mg",
+        "eg",
+        "mg",
+        "eg"
+    );
+
+    for (index, name) in TERM_NAMES.iter().enumerate() {
+        let [white, black] = trace.terms[index];
+        let total = trace.term_total(index);
+
+        cprintln!(
+            "<bold>{:<12}</> {:>6} {:>6} {:>6} {:>6} <blue>{:>6} {:>6}</>",
+            name,
+            white.mg,
+            white.eg,
+            black.mg,
+            black.eg,
+            total.mg,
+            total.eg
+        );
+    }
 
-    Ok(departure_square as u16 | (target_square as u16) << 6 | en_passant_flag)
+    let total = trace.total();
+    cprintln!(
+        "<bold>{:<12}</> {:>27} <blue>{:>6} {:>6}</>",
+        "total",
+        "",
+        total.mg,
+        total.eg
+    );
+    match trace.endgame {
+        Some(Endgame::Score(score)) => {
+            cprintln!("<green>endgame:</> known score {}", format_score(score))
+        }
+        Some(Endgame::Scale(factor)) => {
+            cprintln!("<green>endgame:</> scaled by {}/{}", factor, SCALE_NORMAL)
+        }
+        None => {}
+    }
+    cprintln!(
+        "<green>phase:</> {}/{}  <green>evaluation:</> <bold, blue>{}</>",
+        trace.phase,
+        trace.max_phase,
+        format_score(trace.evaluation())
+    );
 }
 
-fn repr_move(v_move: u16) -> String {
-    if Move::is_short_castling(v_move) {
-        return "O-O".to_string();
+fn print_pgn_move(board: &Board, pgn_move: &PgnMove) {
+    let number = match board.get_colour_to_move() {
+        Colour::White => format!("{}.", board.get_fullmove_number()),
+        Colour::Black => format!("{}...", board.get_fullmove_number()),
+    };
+    let nags = pgn_move
+        .nags
+        .iter()
+        .map(|nag| format!(" ${}", nag))
+        .collect::<String>();
+
+    cprintln!("<green>{}</> <bold>{}</>{}", number, pgn_move.san, nags);
+    if let Some(comment) = &pgn_move.comment {
+        cprintln!("  <green>comment:</> {}", comment);
     }
+    if !pgn_move.variations.is_empty() {
+        cprintln!(
+            "  <green>variations:</> {}",
+            pgn::variations_text(pgn_move, board)
+        );
+    }
+}
 
-    if Move::is_long_castling(v_move) {
-        return "O-O-O".to_string();
+fn format_score(score: i32) -> String {
+    if let Some(moves_to_mate) = score_to_mate(score) {
+        let sign = if moves_to_mate > 0 { "" } else { "-" };
+
+        return format!("{}M{}", sign, moves_to_mate.abs());
     }
 
-    let departure_square = v_move & 0b111111;
-    let target_square = (v_move & 0b111111000000) >> 6;
+    if score > 0 {
+        "+".to_string() + &score.to_string()
+    } else {
+        score.to_string()
+    }
+}
 
-    let (departure_file, departure_rank) = square_to_coods(departure_square);
-    let (target_file, target_rank) = square_to_coods(target_square);
+/// Plays `move_string`, in UCI long algebraic notation or in SAN, returning
+/// the move in SAN.
+fn make_move(move_string: &str, board: &mut Board) -> Result<String> {
+    let v_move = match Move::from_uci(board, move_string) {
+        Some(v_move) => v_move,
+        None => parse_san(board, move_string)?,
+    };
 
-    let en_passant = if Move::is_en_passant(v_move) { "*" } else { "" };
+    let san = to_san(board, v_move);
+    board.make_move(v_move);
 
-    format!(
-        "{}{}{}{}{}",
-        get_file_letter(departure_file).unwrap(),
-        departure_rank + 1,
-        get_file_letter(target_file).unwrap(),
-        target_rank + 1,
-        en_passant
-    )
+    Ok(san)
 }
@@ -1,11 +1,13 @@
-#[non_exhaustive]
-pub struct Move;
+use crate::{board::Board, colour::Colour, utils::square_to_string};
+
 /// A move is represented by 16 bits.
 /// 00->special moves (castling or promotion type)
 /// 0->promotion
 /// 0->en passant
 /// 000000->target square
 /// 000000->departure square
+#[non_exhaustive]
+pub struct Move;
 
 #[allow(non_upper_case_globals, dead_code)]
 impl Move {
@@ -73,8 +75,7 @@ impl Move {
     }
 
     pub fn is_castling(v_move: u16) -> bool {
-        (v_move & Self::CASTLING_MASK) == Self::ShortCastle
-            || (v_move & Self::CASTLING_MASK) == Self::LongCastle && !Self::is_promotion(v_move)
+        Self::is_short_castling(v_move) || Self::is_long_castling(v_move)
     }
 
     pub fn is_short_castling(v_move: u16) -> bool {
@@ -92,18 +93,71 @@ impl Move {
     pub fn special_two(v_move: u16) -> bool {
         (v_move & Self::SPECIAL_TWO_MASK) == Self::SPECIAL_TWO_MASK
     }
+
+    /// Formats a move in long algebraic notation, with castling as the king's two-square move.
+    pub fn to_uci(board: &Board, v_move: u16) -> String {
+        if Self::is_castling(v_move) {
+            let rank = match board.get_colour_to_move() {
+                Colour::White => 1,
+                Colour::Black => 8,
+            };
+            let target_file = if Self::is_short_castling(v_move) {
+                'g'
+            } else {
+                'c'
+            };
+
+            return format!("e{}{}{}", rank, target_file, rank);
+        }
+
+        let promotion = match Self::is_promotion(v_move) {
+            true => match Self::promotion_type(v_move) {
+                Self::PromoteToKnight => "n",
+                Self::PromoteToBishop => "b",
+                Self::PromoteToRook => "r",
+                _ => "q",
+            },
+            false => "",
+        };
+
+        format!(
+            "{}{}{}",
+            square_to_string(Self::departure_square(v_move)),
+            square_to_string(Self::target_square(v_move)),
+            promotion
+        )
+    }
+
+    /// The legal move of `board` written `string` in UCI long algebraic
+    /// notation, with its en passant or castling flag.
+    pub fn from_uci(board: &mut Board, string: &str) -> Option<u16> {
+        board
+            .generate_legal_moves()
+            .into_iter()
+            .find(|&v_move| Self::to_uci(board, v_move) == string)
+    }
+
+    /// Formats a principal variation, playing it out to know who castles at each step.
+    pub fn line_to_uci(board: &Board, pv: &[u16]) -> Vec<String> {
+        let mut board = board.clone();
+
+        pv.iter()
+            .map(|&v_move| {
+                let string = Self::to_uci(&board, v_move);
+                board.make_move(v_move);
+                string
+            })
+            .collect()
+    }
 }
 
+/// Everything `make_move` overwrites, so that `undo_move` can restore it.
+#[derive(Clone)]
 pub struct MoveRecord {
     pub v_move: u16,
     pub piece_on_target_square: u8,
-}
-
-impl MoveRecord {
-    pub fn new(v_move: u16, piece_on_target_square: u8) -> Self {
-        Self {
-            v_move,
-            piece_on_target_square,
-        }
-    }
+    pub castling_rights: u8,
+    pub en_passant_square: Option<u8>,
+    pub halfmove_clock: u16,
+    pub pawn_key: u64,
 }
@@ -0,0 +1,323 @@
+//! Efficiently updatable neural network evaluation.
+//!
+//! The network has one input per piece type, colour and square, seen from
+//! each side: 768 inputs feeding a hidden layer of `HIDDEN_SIZE` neurons,
+//! the accumulator, whose weights are shared by both perspectives. The two
+//! accumulators go through a clipped ReLU and are concatenated, side to
+//! move first, before a single output neuron.
+//!
+//! Network files are little endian and laid out as follows:
+//!
+//! | field           | type  | count                   |
+//! |-----------------|-------|-------------------------|
+//! | magic `NNUE`    | u8    | 4                       |
+//! | version, 1      | u32   | 1                       |
+//! | hidden size     | u32   | 1                       |
+//! | feature weights | i16   | 768 × hidden size       |
+//! | feature biases  | i16   | hidden size             |
+//! | output weights  | i16   | 2 × hidden size         |
+//! | output bias     | i32   | 1                       |
+//!
+//! Feature weights are grouped by input, the input of a piece being
+//! `colour * 384 + (type - 1) * 64 + square`, where the colour is 0 for the
+//! perspective's own pieces and the square is flipped vertically for black.
+//! Piece types count from pawn as 1 to king as 6, as in `Piece`. Feature
+//! weights and biases are quantised by `QA`, output weights by `QB` and the
+//! output bias by `QA * QB`, and the output is scaled by `SCALE` to give
+//! centipawns.
+
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use lazy_static::lazy_static;
+
+use crate::{colour::Colour, errors::NnueError, piece::Piece, Result};
+
+pub const HIDDEN_SIZE: usize = 256;
+const INPUT_SIZE: usize = 768;
+const MAGIC: &[u8; 4] = b"NNUE";
+const VERSION: u32 = 1;
+
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+lazy_static! {
+    static ref NETWORK: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+}
+
+static USE_NNUE: AtomicBool = AtomicBool::new(false);
+
+pub struct Network {
+    feature_weights: Vec<[i16; HIDDEN_SIZE]>,
+    feature_biases: [i16; HIDDEN_SIZE],
+    /// Weights for the side to move's accumulator, then the other side's.
+    output_weights: [[i16; HIDDEN_SIZE]; 2],
+    output_bias: i32,
+}
+
+impl Network {
+    /// Reads a network file in the format described in the module documentation.
+    pub fn load(path: &str) -> Result<Self> {
+        let bytes = fs::read(path)?;
+
+        let expected_length =
+            12 + 2 * (INPUT_SIZE * HIDDEN_SIZE + HIDDEN_SIZE + 2 * HIDDEN_SIZE) + 4;
+        if bytes.len() < 12 || &bytes[..4] != MAGIC {
+            return Err(Box::new(NnueError::BadMagic));
+        }
+
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let version = read_u32(4);
+        if version != VERSION {
+            return Err(Box::new(NnueError::UnsupportedVersion(version)));
+        }
+
+        let hidden_size = read_u32(8);
+        if hidden_size as usize != HIDDEN_SIZE {
+            return Err(Box::new(NnueError::WrongHiddenSize(hidden_size)));
+        }
+
+        if bytes.len() != expected_length {
+            return Err(Box::new(NnueError::WrongLength(
+                bytes.len(),
+                expected_length,
+            )));
+        }
+
+        let mut values = bytes[12..bytes.len() - 4]
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]));
+        let mut layer = || {
+            let mut layer = [0; HIDDEN_SIZE];
+            layer
+                .iter_mut()
+                .for_each(|value| *value = values.next().unwrap());
+            layer
+        };
+
+        let feature_weights = (0..INPUT_SIZE).map(|_| layer()).collect();
+        let feature_biases = layer();
+        let output_weights = [layer(), layer()];
+        let output_bias = i32::from_le_bytes(bytes[bytes.len() - 4..].try_into().unwrap());
+
+        Ok(Self {
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        })
+    }
+}
+
+/// Hidden layer values of both perspectives, white's first.
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+struct Accumulator {
+    values: [[i16; HIDDEN_SIZE]; 2],
+}
+
+/// The network and the accumulators of every position of the game, so
+/// that undoing a move only drops the last one.
+#[derive(Clone)]
+pub struct NnueState {
+    network: Arc<Network>,
+    accumulators: Vec<Accumulator>,
+}
+
+impl NnueState {
+    /// Computes the accumulators of `pieces` from scratch.
+    pub fn new(network: Arc<Network>, pieces: &[u8; 64]) -> Self {
+        let mut accumulator = Accumulator {
+            values: [network.feature_biases; 2],
+        };
+
+        for (square, &piece) in pieces.iter().enumerate() {
+            if piece != Piece::None {
+                for perspective in [Colour::White, Colour::Black] {
+                    let weights = &network.feature_weights[feature(piece, square, perspective)];
+                    add(
+                        &mut accumulator.values[perspective_index(perspective)],
+                        weights,
+                    );
+                }
+            }
+        }
+
+        Self {
+            network,
+            accumulators: vec![accumulator],
+        }
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    /// Starts the accumulators of a new position from the current ones.
+    pub fn push(&mut self) {
+        let accumulator = *self
+            .accumulators
+            .last()
+            .expect("accumulator stack is never empty");
+        self.accumulators.push(accumulator);
+    }
+
+    /// Drops the accumulators of the current position, returning `false`
+    /// when there is no earlier position to go back to.
+    pub fn pop(&mut self) -> bool {
+        if self.accumulators.len() == 1 {
+            return false;
+        }
+
+        self.accumulators.pop();
+        true
+    }
+
+    /// Replaces `old_piece` by `new_piece` on `square`, either may be `Piece::None`.
+    pub fn update(&mut self, square: usize, old_piece: u8, new_piece: u8) {
+        let network = &self.network;
+        let accumulator = self
+            .accumulators
+            .last_mut()
+            .expect("accumulator stack is never empty");
+
+        for perspective in [Colour::White, Colour::Black] {
+            let values = &mut accumulator.values[perspective_index(perspective)];
+
+            if old_piece != Piece::None {
+                sub(
+                    values,
+                    &network.feature_weights[feature(old_piece, square, perspective)],
+                );
+            }
+            if new_piece != Piece::None {
+                add(
+                    values,
+                    &network.feature_weights[feature(new_piece, square, perspective)],
+                );
+            }
+        }
+    }
+
+    /// The evaluation in centipawns from the point of view of `colour_to_move`.
+    pub fn evaluate(&self, colour_to_move: bool) -> i32 {
+        let accumulator = self
+            .accumulators
+            .last()
+            .expect("accumulator stack is never empty");
+
+        let us = &accumulator.values[perspective_index(colour_to_move)];
+        let them = &accumulator.values[perspective_index(!colour_to_move)];
+
+        let output = crelu_dot(us, &self.network.output_weights[0])
+            + crelu_dot(them, &self.network.output_weights[1])
+            + self.network.output_bias;
+
+        output * SCALE / (QA * QB)
+    }
+}
+
+/// Replaces the loaded network, `None` unloading it.
+pub fn set_network(network: Option<Network>) {
+    *NETWORK.write().unwrap() = network.map(Arc::new);
+}
+
+pub fn set_enabled(enabled: bool) {
+    USE_NNUE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_loaded() -> bool {
+    NETWORK.read().unwrap().is_some()
+}
+
+/// The network evaluations should use, if it is both loaded and enabled.
+pub fn active_network() -> Option<Arc<Network>> {
+    if !USE_NNUE.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    NETWORK.read().unwrap().clone()
+}
+
+fn perspective_index(colour: bool) -> usize {
+    match colour {
+        Colour::White => 0,
+        Colour::Black => 1,
+    }
+}
+
+fn feature(piece: u8, square: usize, perspective: bool) -> usize {
+    let colour_offset = if Piece::is_colour_bool(piece, perspective) {
+        0
+    } else {
+        384
+    };
+    let square = match perspective {
+        Colour::White => square,
+        Colour::Black => square ^ 56,
+    };
+
+    colour_offset + (Piece::piece_type(piece) as usize - 1) * 64 + square
+}
+
+// These loops are simple enough for the compiler to vectorise on its own
+fn add(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) {
+    for (value, weight) in values.iter_mut().zip(weights) {
+        *value = value.wrapping_add(*weight);
+    }
+}
+
+fn sub(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) {
+    for (value, weight) in values.iter_mut().zip(weights) {
+        *value = value.wrapping_sub(*weight);
+    }
+}
+
+/// The dot product of the clipped ReLU of `values` with `weights`.
+fn crelu_dot(values: &[i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: the CPU supports AVX2, checked just above
+        return unsafe { crelu_dot_avx2(values, weights) };
+    }
+
+    crelu_dot_scalar(values, weights)
+}
+
+fn crelu_dot_scalar(values: &[i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) -> i32 {
+    values
+        .iter()
+        .zip(weights)
+        .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+        .sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn crelu_dot_avx2(values: &[i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) -> i32 {
+    use std::arch::x86_64::*;
+
+    let zero = _mm256_setzero_si256();
+    let qa = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+
+    for index in (0..HIDDEN_SIZE).step_by(16) {
+        let value = _mm256_loadu_si256(values.as_ptr().add(index) as *const __m256i);
+        let weight = _mm256_loadu_si256(weights.as_ptr().add(index) as *const __m256i);
+
+        let clipped = _mm256_min_epi16(_mm256_max_epi16(value, zero), qa);
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, weight));
+    }
+
+    let mut lanes = [0i32; 8];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+
+    lanes.iter().sum()
+}
@@ -0,0 +1,647 @@
+use std::{fmt::Write as _, fs, path::Path, sync::RwLock};
+
+use lazy_static::lazy_static;
+
+use crate::{errors::ParamsError, evaluation::Score, piece::Piece, pst, Result};
+
+lazy_static! {
+    /// The weights used by `Board::evaluate`, replaceable at runtime.
+    pub static ref EVAL_PARAMS: RwLock<EvalParams> = RwLock::new(EvalParams::default());
+}
+
+/// A weight made of one or more integer values, stored flat in parameter
+/// files and exposed one value per UCI option.
+pub trait Param {
+    fn values(&self) -> Vec<i32>;
+
+    /// Sets the weight from exactly `len()` values.
+    fn set_values(&mut self, values: &[i32]);
+
+    fn len(&self) -> usize;
+
+    /// The name of each value, `name` itself for a single value.
+    fn value_names(&self, name: &str) -> Vec<String>;
+
+    /// Whether the weight is a single term rather than a table of them.
+    fn is_scalar(&self) -> bool {
+        true
+    }
+}
+
+impl Param for i32 {
+    fn values(&self) -> Vec<i32> {
+        vec![*self]
+    }
+
+    fn set_values(&mut self, values: &[i32]) {
+        *self = values[0];
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn value_names(&self, name: &str) -> Vec<String> {
+        vec![name.to_string()]
+    }
+}
+
+impl Param for Score {
+    fn values(&self) -> Vec<i32> {
+        vec![self.mg, self.eg]
+    }
+
+    fn set_values(&mut self, values: &[i32]) {
+        *self = Score::new(values[0], values[1]);
+    }
+
+    fn len(&self) -> usize {
+        2
+    }
+
+    fn value_names(&self, name: &str) -> Vec<String> {
+        vec![format!("{}_mg", name), format!("{}_eg", name)]
+    }
+}
+
+impl<T: Param, const N: usize> Param for [T; N] {
+    fn values(&self) -> Vec<i32> {
+        self.iter().flat_map(Param::values).collect()
+    }
+
+    fn set_values(&mut self, values: &[i32]) {
+        let mut values = values;
+        for element in self.iter_mut() {
+            let (element_values, rest) = values.split_at(element.len());
+            element.set_values(element_values);
+            values = rest;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.iter().map(Param::len).sum()
+    }
+
+    fn value_names(&self, name: &str) -> Vec<String> {
+        self.iter()
+            .enumerate()
+            .flat_map(|(index, element)| element.value_names(&format!("{}_{}", name, index)))
+            .collect()
+    }
+
+    fn is_scalar(&self) -> bool {
+        false
+    }
+}
+
+/// Thresholds and fixed levels rather than weights, which the tuner leaves alone.
+const UNTUNED: [&str; 4] = [
+    "min_king_attackers",
+    "mobility_baseline",
+    "trapped_rook_max_mobility",
+    "known_win",
+];
+
+/// Declares `EvalParams` with one public field per weight, along with its
+/// defaults and the list of its weights by name.
+macro_rules! eval_params {
+    ($($(#[doc = $doc:literal])* $name:ident: $type:ty = $default:expr,)*) => {
+        /// Every evaluation weight. The piece-square tables can also be read
+        /// from their own file format, see `pst::load`.
+        #[derive(Clone)]
+        pub struct EvalParams {
+            $($(#[doc = $doc])* pub $name: $type,)*
+            /// Bumped whenever the global parameters are replaced, so that
+            /// cached evaluations made with older weights are not reused.
+            version: u32,
+        }
+
+        impl Default for EvalParams {
+            fn default() -> Self {
+                Self {
+                    $($name: $default,)*
+                    version: 0,
+                }
+            }
+        }
+
+        impl EvalParams {
+            /// Every weight with its name, in declaration order.
+            pub fn params(&self) -> Vec<(&'static str, &dyn Param)> {
+                vec![$((stringify!($name), &self.$name as &dyn Param),)*]
+            }
+
+            fn params_mut(&mut self) -> Vec<(&'static str, &mut dyn Param)> {
+                vec![$((stringify!($name), &mut self.$name as &mut dyn Param),)*]
+            }
+        }
+    };
+}
+
+eval_params! {
+    /// Value of a pawn, knight, bishop, rook and queen.
+    material: [Score; 5] = [
+        Score::new(Piece::PawnValue, 120),
+        Score::new(Piece::KnightValue, 280),
+        Score::new(Piece::BishopValue, 310),
+        Score::new(Piece::RookValue, 520),
+        Score::new(Piece::QueenValue, 940),
+    ],
+    /// Weight of a knight, bishop, rook and queen in the game phase, pawns and
+    /// kings not counting.
+    phase_weight: [i32; 4] = [1, 1, 2, 4],
+    /// Piece-square tables, laid out as seen from white's side with rank 8 first.
+    pawn_pst: [Score; 64] = pst::default_table(Piece::Pawn),
+    knight_pst: [Score; 64] = pst::default_table(Piece::Knight),
+    bishop_pst: [Score; 64] = pst::default_table(Piece::Bishop),
+    rook_pst: [Score; 64] = pst::default_table(Piece::Rook),
+    queen_pst: [Score; 64] = pst::default_table(Piece::Queen),
+    king_pst: [Score; 64] = pst::default_table(Piece::King),
+
+    doubled_pawn: Score = Score::new(-10, -25),
+    isolated_pawn: Score = Score::new(-15, -20),
+    backward_pawn: Score = Score::new(-10, -15),
+    /// Bonus for a pawn with a friendly pawn beside it or defending it, by relative rank.
+    connected_pawn: [Score; 8] = [
+        Score::new(0, 0),
+        Score::new(3, 2),
+        Score::new(5, 4),
+        Score::new(8, 6),
+        Score::new(15, 12),
+        Score::new(25, 25),
+        Score::new(40, 45),
+        Score::new(0, 0),
+    ],
+    /// Bonus for a passed pawn by relative rank.
+    passed_pawn: [Score; 8] = [
+        Score::new(0, 0),
+        Score::new(5, 10),
+        Score::new(10, 15),
+        Score::new(15, 25),
+        Score::new(30, 45),
+        Score::new(50, 80),
+        Score::new(80, 130),
+        Score::new(0, 0),
+    ],
+    /// Extra bonus for a passed pawn with nothing between it and promotion, by relative rank.
+    passed_pawn_free_path: [Score; 8] = [
+        Score::new(0, 0),
+        Score::new(0, 5),
+        Score::new(0, 5),
+        Score::new(5, 10),
+        Score::new(10, 20),
+        Score::new(15, 40),
+        Score::new(25, 70),
+        Score::new(0, 0),
+    ],
+
+    /// Bonus for the closest friendly pawn in front of the king on its file or a
+    /// neighbouring one, by the pawn's relative rank.
+    pawn_shield: [Score; 8] = [
+        Score::new(0, 0),
+        Score::new(0, 0),
+        Score::new(20, 0),
+        Score::new(12, 0),
+        Score::new(5, 0),
+        Score::new(0, 0),
+        Score::new(0, 0),
+        Score::new(0, 0),
+    ],
+    /// Penalty for a file next to the king with no friendly pawn in front of it.
+    missing_shield_pawn: Score = Score::new(-25, 0),
+    /// Penalty for the closest enemy pawn coming up a file next to the king, by
+    /// its rank relative to the king's side.
+    pawn_storm: [Score; 8] = [
+        Score::new(0, 0),
+        Score::new(0, 0),
+        Score::new(-30, 0),
+        Score::new(-20, 0),
+        Score::new(-10, 0),
+        Score::new(-5, 0),
+        Score::new(0, 0),
+        Score::new(0, 0),
+    ],
+    semi_open_file_near_king: Score = Score::new(-15, 0),
+    open_file_near_king: Score = Score::new(-25, 0),
+    /// Attack units per king zone square attacked by a knight, bishop, rook and queen.
+    attack_weight: [i32; 4] = [2, 2, 3, 5],
+    /// Extra units for an attacked king zone square that no piece but the king defends.
+    undefended_zone_square_weight: i32 = 1,
+    /// A lone attacker is rarely dangerous, so attacks only count from this many pieces on.
+    min_king_attackers: i32 = 2,
+    /// Penalty per squared attack unit, in 64ths of a centipawn.
+    king_danger: Score = Score::new(16, 2),
+    max_king_danger: Score = Score::new(500, 62),
+
+    /// Bonus per safe square a knight, bishop, rook and queen can move to.
+    mobility: [Score; 4] = [
+        Score::new(4, 4),
+        Score::new(5, 5),
+        Score::new(2, 4),
+        Score::new(1, 2),
+    ],
+    /// Safe squares each piece type is expected to have, scoring zero mobility.
+    mobility_baseline: [i32; 4] = [4, 7, 7, 14],
+    bishop_pair: Score = Score::new(30, 50),
+    /// Bonus for a knight on the fourth to sixth rank that no enemy pawn can chase away.
+    knight_outpost: Score = Score::new(20, 10),
+    /// Extra bonus for an outpost knight defended by a pawn.
+    defended_knight_outpost: Score = Score::new(10, 5),
+    rook_on_open_file: Score = Score::new(25, 10),
+    rook_on_semi_open_file: Score = Score::new(12, 5),
+    /// Bonus for a rook on the seventh rank hitting pawns there or cutting off the king.
+    rook_on_seventh: Score = Score::new(20, 30),
+    /// Penalty for a rook boxed in by its uncastled king.
+    trapped_rook: Score = Score::new(-40, -10),
+    /// Penalty for a bishop on a7 or h7 shut in by a pawn on b6 or g6.
+    trapped_bishop: Score = Score::new(-100, -80),
+    /// A rook this mobile or less next to its king counts as trapped.
+    trapped_rook_max_mobility: i32 = 3,
+
+    /// Score given to a position known to be won, above anything the regular
+    /// evaluation reaches but below mate scores.
+    known_win: i32 = 10_000,
+    /// Bonus per step the losing king is pushed towards the edge, or the
+    /// mating corner with bishop and knight.
+    push_to_edge: i32 = 20,
+    push_to_corner: i32 = 40,
+    /// Bonus per step the winning king comes closer to the losing one.
+    push_close: i32 = 10,
+    /// Scale factor, in 64ths, for rook endings without pawns, which the defender holds with ease.
+    scale_rook_ending: i32 = 4,
+    /// Scale factor for bishops of opposite colours with nothing but pawns besides them.
+    scale_opposite_bishops: i32 = 24,
+    /// Scale factor for opposite coloured bishops with other pieces still on the board.
+    scale_opposite_bishops_with_pieces: i32 = 48,
+    /// Scale factor for a side without pawns only a minor piece ahead, which rarely wins.
+    scale_no_pawns: i32 = 8,
+}
+
+impl EvalParams {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Phase of the starting position, where the middlegame score counts fully.
+    pub fn max_phase(&self) -> i32 {
+        let [knight, bishop, rook, queen] = self.phase_weight;
+
+        (4 * knight + 4 * bishop + 4 * rook + 2 * queen).max(1)
+    }
+
+    /// Every value of every weight, flattened in declaration order, leaving
+    /// out the thresholds that are not weights.
+    pub fn values(&self) -> Vec<i32> {
+        self.params()
+            .into_iter()
+            .filter(|(name, _)| !UNTUNED.contains(name))
+            .flat_map(|(_, param)| param.values())
+            .collect()
+    }
+
+    /// Sets every weight from values flattened like `values` returns them.
+    pub fn set_all(&mut self, values: &[i32]) {
+        let mut values = values;
+        for (_, param) in self
+            .params_mut()
+            .into_iter()
+            .filter(|(name, _)| !UNTUNED.contains(name))
+        {
+            let (param_values, rest) = values.split_at(param.len());
+            param.set_values(param_values);
+            values = rest;
+        }
+    }
+
+    /// The name and value of every weight that is a single term, leaving out
+    /// the tables, which are only set from files.
+    pub fn scalar_values(&self) -> Vec<(String, i32)> {
+        self.params()
+            .into_iter()
+            .filter(|(_, param)| param.is_scalar())
+            .flat_map(|(name, param)| param.value_names(name).into_iter().zip(param.values()))
+            .collect()
+    }
+
+    /// Sets one value by the name `Param::value_names` gives it, ignoring
+    /// case. Unlike `set_all`, this reaches the thresholds too.
+    pub fn set_value(&mut self, name: &str, value: i32) -> Result<()> {
+        for (param_name, param) in self.params_mut() {
+            let index = param
+                .value_names(param_name)
+                .iter()
+                .position(|value_name| value_name.eq_ignore_ascii_case(name));
+
+            if let Some(index) = index {
+                let mut values = param.values();
+                values[index] = value;
+                param.set_values(&values);
+
+                return Ok(());
+            }
+        }
+
+        Err(Box::new(ParamsError::UnknownParam(name.to_string())))
+    }
+
+    fn set_param(&mut self, name: &str, values: &[i32]) -> Result<()> {
+        let mut params = self.params_mut();
+        let (_, param) = params
+            .iter_mut()
+            .find(|(param_name, _)| *param_name == name)
+            .ok_or(ParamsError::UnknownParam(name.to_string()))?;
+
+        if values.len() != param.len() {
+            return Err(Box::new(ParamsError::WrongCount(
+                name.to_string(),
+                values.len(),
+                param.len(),
+            )));
+        }
+
+        param.set_values(values);
+
+        Ok(())
+    }
+
+    /// Reads parameters from a file, as JSON if its name ends in `.json`
+    /// and in the text format otherwise.
+    ///
+    /// The text format has one weight per line, its name followed by its
+    /// whitespace separated values, middlegame before endgame and arrays
+    /// flattened in order. Everything after a `#` on a line is ignored. The
+    /// JSON format is an object mapping each name to a number or an array
+    /// of numbers. Weights missing from the file keep their default values.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut params = Self::default();
+
+        let entries = if is_json(path) {
+            parse_json(&contents)?
+        } else {
+            parse_text(&contents)?
+        };
+
+        for (name, values) in entries {
+            params.set_param(&name, &values)?;
+        }
+
+        Ok(params)
+    }
+
+    /// Writes the parameters in the format `load` picks for `path`.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let contents = if is_json(path) {
+            self.to_json()
+        } else {
+            self.to_text()
+        };
+
+        fs::write(path, contents)?;
+
+        Ok(())
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::from("# Evaluation parameters, middlegame before endgame\n");
+
+        for (name, param) in self.params() {
+            let values = param
+                .values()
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>();
+            let _ = writeln!(text, "{} {}", name, values.join(" "));
+        }
+
+        text
+    }
+
+    fn to_json(&self) -> String {
+        let entries = self
+            .params()
+            .into_iter()
+            .map(|(name, param)| {
+                let values = param
+                    .values()
+                    .iter()
+                    .map(i32::to_string)
+                    .collect::<Vec<_>>();
+                format!("  \"{}\": [{}]", name, values.join(", "))
+            })
+            .collect::<Vec<_>>();
+
+        format!("{{\n{}\n}}\n", entries.join(",\n"))
+    }
+}
+
+/// Replaces the global parameters, invalidating evaluations cached with the old ones.
+pub fn set_eval_params(mut params: EvalParams) {
+    let mut current = EVAL_PARAMS.write().unwrap();
+    params.version = current.version.wrapping_add(1);
+    *current = params;
+}
+
+/// Sets one value of the global parameters, see `EvalParams::set_value`.
+pub fn set_eval_param(name: &str, value: i32) -> Result<()> {
+    let mut params = EVAL_PARAMS.read().unwrap().clone();
+    params.set_value(name, value)?;
+    set_eval_params(params);
+
+    Ok(())
+}
+
+fn is_json(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+fn parse_text(contents: &str) -> Result<Vec<(String, Vec<i32>)>> {
+    let mut entries = Vec::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+
+        let Some(name) = tokens.next() else {
+            continue;
+        };
+
+        let values = tokens
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| ParamsError::BadValue(value.to_string(), line_number + 1))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        entries.push((name.to_string(), values));
+    }
+
+    Ok(entries)
+}
+
+/// Parses the flat JSON object `to_json` writes, which is all `load` needs.
+fn parse_json(contents: &str) -> Result<Vec<(String, Vec<i32>)>> {
+    let bad_json = |message: &str| ParamsError::BadJson(message.to_string());
+
+    let body = contents
+        .trim()
+        .strip_prefix('{')
+        .and_then(|body| body.strip_suffix('}'))
+        .ok_or(bad_json("expected an object"))?;
+
+    let mut entries = Vec::new();
+    let mut rest = body.trim_start();
+
+    while !rest.is_empty() {
+        let after_quote = rest.strip_prefix('"').ok_or(bad_json("expected a name"))?;
+        let (name, after_name) = after_quote
+            .split_once('"')
+            .ok_or(bad_json("unterminated name"))?;
+        let after_colon = after_name
+            .trim_start()
+            .strip_prefix(':')
+            .ok_or(bad_json("expected ':' after a name"))?
+            .trim_start();
+
+        let (value, after_value) = match after_colon.strip_prefix('[') {
+            Some(array) => array
+                .split_once(']')
+                .ok_or(bad_json("unterminated array"))?,
+            None => after_colon.split_at(after_colon.find(',').unwrap_or(after_colon.len())),
+        };
+
+        let values = value
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| bad_json(&format!("bad value {} for {}", value, name)))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        entries.push((name.to_string(), values));
+
+        let after_value = after_value.trim_start();
+        rest = match after_value.strip_prefix(',') {
+            Some(rest) => rest.trim_start(),
+            None if after_value.is_empty() => after_value,
+            None => return Err(Box::new(bad_json("expected ',' between entries"))),
+        };
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn piece_square_values_are_named_and_settable() {
+        let mut params = EvalParams::default();
+
+        params.set_value("knight_pst_27_eg", 42).unwrap();
+        assert_eq!(params.knight_pst[27].eg, 42);
+        params.set_value("KNIGHT_PST_27_MG", -42).unwrap();
+        assert_eq!(params.knight_pst[27].mg, -42);
+
+        assert!(params.set_value("knight_pst_64_eg", 0).is_err());
+    }
+
+    #[test]
+    fn thresholds_are_left_out_of_the_tuned_values() {
+        let defaults = EvalParams::default();
+        let mut params = EvalParams::default();
+
+        let values = params
+            .values()
+            .iter()
+            .map(|value| value + 1)
+            .collect::<Vec<_>>();
+        params.set_all(&values);
+        assert_eq!(params.doubled_pawn.mg, defaults.doubled_pawn.mg + 1);
+        assert_eq!(params.mobility_baseline, defaults.mobility_baseline);
+        assert_eq!(
+            params.trapped_rook_max_mobility,
+            defaults.trapped_rook_max_mobility
+        );
+
+        // They can still be set by name
+        params.set_value("trapped_rook_max_mobility", 5).unwrap();
+        assert_eq!(params.trapped_rook_max_mobility, 5);
+    }
+
+    #[test]
+    fn only_single_terms_are_scalar_values() {
+        let names = EvalParams::default()
+            .scalar_values()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+
+        assert!(names.contains(&"doubled_pawn_mg".to_string()));
+        assert!(names.contains(&"known_win".to_string()));
+        assert!(!names
+            .iter()
+            .any(|name| name.starts_with("knight_pst") || name.starts_with("phase_weight")));
+        assert!(names.len() < 50);
+    }
+
+    #[test]
+    fn max_phase_follows_the_phase_weights() {
+        let mut params = EvalParams::default();
+        assert_eq!(params.max_phase(), 24);
+
+        params.phase_weight = [0, 0, 1, 2];
+        assert_eq!(params.max_phase(), 8);
+
+        // Never zero, as the evaluation divides by it
+        params.phase_weight = [0; 4];
+        assert_eq!(params.max_phase(), 1);
+    }
+
+    #[test]
+    fn piece_square_tables_round_trip_through_files() {
+        let mut params = EvalParams::default();
+        params.pawn_pst[12] = Score::new(-7, 99);
+        params.king_pst[63] = Score::new(13, -13);
+
+        for name in ["params.txt", "params.json"] {
+            let path = temp_path(name);
+            params.save(&path).unwrap();
+            let loaded = EvalParams::load(&path).unwrap();
+            let _ = fs::remove_file(&path);
+
+            assert_eq!(loaded.values(), params.values(), "{name}");
+        }
+    }
+
+    #[test]
+    fn piece_square_table_files_set_the_params() {
+        let path = temp_path("tables.txt");
+        let rook_values = (0..64).map(|value| value.to_string()).collect::<Vec<_>>();
+        fs::write(&path, format!("rook.eg:\n{}\n", rook_values.join(" "))).unwrap();
+
+        let mut params = EvalParams::default();
+        pst::load(&path, &mut params).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(params.rook_pst[5].eg, 5);
+        assert_eq!(params.rook_pst[5].mg, EvalParams::default().rook_pst[5].mg);
+
+        pst::reset(&mut params);
+        assert_eq!(params.values(), EvalParams::default().values());
+    }
+}
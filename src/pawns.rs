@@ -0,0 +1,180 @@
+use std::cell::RefCell;
+
+use crate::{
+    bitboard::{adjacent_files, file_mask, rank_mask, squares},
+    board::Board,
+    colour::Colour,
+    evaluation::Score,
+    params::EvalParams,
+    piece::Piece,
+};
+
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+thread_local! {
+    /// Each search thread caches its own pawn structures, so no locking is needed.
+    static PAWN_TABLE: RefCell<Vec<Option<PawnEntry>>> =
+        RefCell::new(vec![None; PAWN_TABLE_SIZE]);
+}
+
+/// The evaluation of a pawn structure, which only depends on the pawns.
+#[derive(Clone, Copy)]
+pub struct PawnEntry {
+    key: u64,
+    /// Version of the parameters the entry was evaluated with.
+    params_version: u32,
+    /// Scores of white and black.
+    pub scores: [Score; 2],
+    /// Passed pawns of white and black, one bit per square.
+    pub passed: [u64; 2],
+}
+
+/// The pawn structure evaluation of `colour`'s pawns, including the passed
+/// pawn bonuses that depend on other pieces.
+pub fn evaluate(board: &Board, colour: bool, params: &EvalParams) -> Score {
+    let entry = probe(board, params);
+    let index = colour_index(colour);
+
+    entry.scores[index] + free_path_bonus(board, entry.passed[index], colour, params)
+}
+
+/// The cached pawn structure of `board`, evaluating and storing it on a miss.
+pub fn probe(board: &Board, params: &EvalParams) -> PawnEntry {
+    let key = board.pawn_hash();
+    let index = (key % PAWN_TABLE_SIZE as u64) as usize;
+
+    PAWN_TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+
+        match table[index] {
+            Some(entry) if entry.key == key && entry.params_version == params.version() => entry,
+            _ => {
+                let entry = evaluate_structure(board, key, params);
+                table[index] = Some(entry);
+                entry
+            }
+        }
+    })
+}
+
+fn evaluate_structure(board: &Board, key: u64, params: &EvalParams) -> PawnEntry {
+    let mut pawns = [0u64; 2];
+    for (square, &piece) in board.pieces().iter().enumerate() {
+        if Piece::is_type(piece, Piece::Pawn) {
+            pawns[colour_index(Piece::colour_bool(piece))] |= 1 << square;
+        }
+    }
+
+    let (white_score, white_passed) = evaluate_colour(pawns[0], pawns[1], Colour::White, params);
+    let (black_score, black_passed) = evaluate_colour(pawns[1], pawns[0], Colour::Black, params);
+
+    PawnEntry {
+        key,
+        params_version: params.version(),
+        scores: [white_score, black_score],
+        passed: [white_passed, black_passed],
+    }
+}
+
+fn evaluate_colour(own: u64, enemy: u64, colour: bool, params: &EvalParams) -> (Score, u64) {
+    let mut score = Score::default();
+    let mut passed = 0;
+
+    for square in squares(own) {
+        let file = square % 8;
+        let rank = relative_rank(square, colour);
+        let adjacent = adjacent_files(file);
+
+        // Only reachable from a malformed FEN
+        if rank == 7 {
+            continue;
+        }
+
+        // Every pawn but the most advanced one on a file counts as doubled
+        if own & front_span(square, colour) & file_mask(file) != 0 {
+            score += params.doubled_pawn;
+        }
+
+        let isolated = own & adjacent == 0;
+        if isolated {
+            score += params.isolated_pawn;
+        }
+
+        let beside = own & adjacent & rank_mask(square / 8);
+        let defenders = own & pawn_attacks(square, !colour);
+        if beside != 0 || defenders != 0 {
+            score += params.connected_pawn[rank];
+        }
+
+        // No neighbour can come up to support it, and advancing loses it to an enemy pawn
+        let supporters = own & adjacent & !front_span(square, colour);
+        let stop_square = match colour {
+            Colour::White => square + 8,
+            Colour::Black => square - 8,
+        };
+        if !isolated
+            && supporters & !rank_mask(square / 8) == 0
+            && beside == 0
+            && enemy & pawn_attacks(stop_square, colour) != 0
+        {
+            score += params.backward_pawn;
+        }
+
+        let blockers = front_span(square, colour) & (file_mask(file) | adjacent);
+        if enemy & blockers == 0 {
+            score += params.passed_pawn[rank];
+            passed |= 1 << square;
+        }
+    }
+
+    (score, passed)
+}
+
+fn free_path_bonus(board: &Board, passed: u64, colour: bool, params: &EvalParams) -> Score {
+    squares(passed)
+        .filter(|&square| {
+            squares(front_span(square, colour) & file_mask(square % 8))
+                .all(|ahead| board.piece_at(ahead as u8) == Piece::None)
+        })
+        .fold(Score::default(), |score, square| {
+            score + params.passed_pawn_free_path[relative_rank(square, colour)]
+        })
+}
+
+pub fn colour_index(colour: bool) -> usize {
+    match colour {
+        Colour::White => 0,
+        Colour::Black => 1,
+    }
+}
+
+/// The rank of `square` counted from `colour`'s own side.
+pub fn relative_rank(square: usize, colour: bool) -> usize {
+    match colour {
+        Colour::White => square / 8,
+        Colour::Black => 7 - square / 8,
+    }
+}
+
+/// Every square on the ranks in front of `square` from `colour`'s point of view.
+pub fn front_span(square: usize, colour: bool) -> u64 {
+    let rank = square / 8;
+
+    match colour {
+        Colour::White if rank < 7 => u64::MAX << ((rank + 1) * 8),
+        Colour::Black if rank > 0 => u64::MAX >> ((8 - rank) * 8),
+        _ => 0,
+    }
+}
+
+/// The squares a `colour` pawn on `square` attacks.
+pub fn pawn_attacks(square: usize, colour: bool) -> u64 {
+    let file = square % 8;
+    let target_rank = match colour {
+        Colour::White if square < 56 => square / 8 + 1,
+        Colour::Black if square >= 8 => square / 8 - 1,
+        _ => return 0,
+    };
+
+    adjacent_files(file) & rank_mask(target_rank)
+}
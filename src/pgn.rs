@@ -0,0 +1,769 @@
+//! Portable Game Notation.
+//!
+//! Games are read with their tag pairs, comments, NAGs and recursive
+//! variations. Every move is checked against the position it is played in,
+//! variations included, and errors give the line and column they were found
+//! at.
+//!
+//! Games are exported in the export format: the seven-tag roster first,
+//! then the movetext wrapped at 80 columns.
+
+use std::{
+    fs,
+    iter::Peekable,
+    str::Chars,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    board::Board,
+    colour::Colour,
+    errors::PgnError,
+    san::{parse_san, to_san},
+    Result,
+};
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// The tags every exported game has, in order.
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+const LINE_WIDTH: usize = 80;
+
+/// Move suffix annotations and the NAGs they stand for.
+const SUFFIX_ANNOTATIONS: [(&str, u8); 6] = [
+    ("!", 1),
+    ("?", 2),
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+];
+
+#[derive(Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>,
+    /// The game termination marker, if the movetext has one.
+    pub result: Option<String>,
+}
+
+pub struct PgnMove {
+    pub v_move: u16,
+    /// The move in SAN, without suffix annotations.
+    pub san: String,
+    /// Numeric annotation glyphs, `!` being 1 and `?` being 2.
+    pub nags: Vec<u8>,
+    /// The comment before the move, found only at the start of a game or
+    /// of a variation.
+    pub comment_before: Option<String>,
+    /// The comment following the move, such as `[%clk 0:04:59]`.
+    pub comment: Option<String>,
+    /// Lines played instead of this move, from the position before it.
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    fn new(v_move: u16, san: String) -> Self {
+        Self {
+            v_move,
+            san,
+            nags: Vec::new(),
+            comment_before: None,
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The result from the termination marker or the `Result` tag, `*` when
+    /// neither is known.
+    pub fn result(&self) -> &str {
+        self.result
+            .as_deref()
+            .or_else(|| self.tag("Result"))
+            .unwrap_or("*")
+    }
+
+    /// The position the game starts from, given by the `FEN` tag or the
+    /// standard starting position.
+    pub fn start_board(&self) -> Result<Board> {
+        match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen),
+            None => Ok(Board::new()),
+        }
+    }
+
+    /// The game played on `board`, from the position it was set up in, with
+    /// the seven-tag roster filled with unknown values but for the date and
+    /// the result.
+    pub fn from_board(board: &Board) -> Self {
+        let mut start = board.clone();
+        while start.undo_move().is_ok() {}
+
+        let result = result_of(&mut board.clone());
+        let mut tags = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|&name| {
+                let value = match name {
+                    "Date" => today(),
+                    "Result" => result.to_string(),
+                    _ => "?".to_string(),
+                };
+
+                (name.to_string(), value)
+            })
+            .collect::<Vec<_>>();
+
+        let fen = start.to_fen();
+        if fen != STARTING_FEN {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), fen));
+        }
+
+        let moves = board
+            .played_moves()
+            .into_iter()
+            .map(|v_move| {
+                let san = to_san(&mut start, v_move);
+                start.make_move(v_move);
+
+                PgnMove::new(v_move, san)
+            })
+            .collect();
+
+        Self {
+            tags,
+            moves,
+            result: Some(result.to_string()),
+        }
+    }
+
+    /// The game in export format, ending with a blank line.
+    pub fn to_pgn(&self) -> Result<String> {
+        let mut pgn = String::new();
+
+        let roster = SEVEN_TAG_ROSTER.iter().map(|&name| {
+            let value = self.tag(name).unwrap_or(match name {
+                "Date" => "????.??.??",
+                "Result" => self.result(),
+                _ => "?",
+            });
+
+            (name, value)
+        });
+        let others = self
+            .tags
+            .iter()
+            .filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str()))
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+
+        for (name, value) in roster.chain(others) {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        pgn.push('\n');
+
+        let start = self.start_board()?;
+        let mut tokens = Vec::new();
+        line_tokens(
+            &self.moves,
+            start.get_fullmove_number(),
+            start.get_colour_to_move(),
+            &mut tokens,
+        );
+        tokens.push(self.result().to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push_str("\n\n");
+
+        Ok(pgn)
+    }
+}
+
+/// The games in the PGN file at `path`, each of which may have an error.
+pub fn load(path: &str) -> Result<Vec<Result<PgnGame>>> {
+    Ok(parse(&fs::read_to_string(path)?))
+}
+
+pub fn save(path: &str, game: &PgnGame) -> Result<()> {
+    fs::write(path, game.to_pgn()?)?;
+
+    Ok(())
+}
+
+/// A comment giving the time spent on a move, as in `[%emt 0:01:05]`.
+pub fn elapsed_time_comment(time: Duration) -> String {
+    let seconds = time.as_secs();
+
+    format!(
+        "[%emt {}:{:02}:{:02}]",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The result of the game on `board`: decided by checkmate, drawn by
+/// stalemate, the fifty-move rule or insufficient material, or `*` while
+/// it goes on.
+pub fn result_of(board: &mut Board) -> &'static str {
+    if board.generate_legal_moves().is_empty() {
+        return match (board.is_in_check(), board.get_colour_to_move()) {
+            (false, _) => "1/2-1/2",
+            (true, Colour::White) => "0-1",
+            (true, Colour::Black) => "1-0",
+        };
+    }
+
+    if board.get_halfmove_clock() >= 100 || board.is_insufficient_material() {
+        return "1/2-1/2";
+    }
+
+    "*"
+}
+
+/// The variations of a move as they would be written after it, played from
+/// `board`, the position before the move.
+pub fn variations_text(pgn_move: &PgnMove, board: &Board) -> String {
+    pgn_move
+        .variations
+        .iter()
+        .map(|variation| {
+            let mut tokens = Vec::new();
+            line_tokens(
+                variation,
+                board.get_fullmove_number(),
+                board.get_colour_to_move(),
+                &mut tokens,
+            );
+
+            format!("({})", tokens.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Appends the movetext of `moves` to `tokens`, the first move being move
+/// `number` of `colour`. Black moves are numbered again after anything that
+/// interrupts the line.
+fn line_tokens(moves: &[PgnMove], mut number: u16, mut colour: bool, tokens: &mut Vec<String>) {
+    let mut interrupted = true;
+
+    for pgn_move in moves {
+        if let Some(comment) = &pgn_move.comment_before {
+            tokens.push(format!("{{{}}}", comment));
+            interrupted = true;
+        }
+
+        match colour {
+            Colour::White => tokens.push(format!("{}.", number)),
+            Colour::Black if interrupted => tokens.push(format!("{}...", number)),
+            Colour::Black => {}
+        }
+
+        tokens.push(pgn_move.san.clone());
+        tokens.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+        interrupted = false;
+
+        if let Some(comment) = &pgn_move.comment {
+            tokens.push(format!("{{{}}}", comment));
+            interrupted = true;
+        }
+
+        for variation in &pgn_move.variations {
+            let mut variation_tokens = Vec::new();
+            line_tokens(variation, number, colour, &mut variation_tokens);
+
+            if let Some(first) = variation_tokens.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = variation_tokens.last_mut() {
+                last.push(')');
+            }
+            tokens.extend(variation_tokens);
+            interrupted = true;
+        }
+
+        if colour == Colour::Black {
+            number += 1;
+        }
+        colour = !colour;
+    }
+}
+
+/// Parses every game in `text`. After an error, reading goes on with the
+/// next game, found by a tag at the start of a line.
+pub fn parse(text: &str) -> Vec<Result<PgnGame>> {
+    let mut reader = Reader::new(text);
+    let mut games = Vec::new();
+
+    loop {
+        reader.skip_whitespace();
+        if reader.peek().is_none() {
+            break;
+        }
+
+        let game = parse_game(&mut reader);
+        if game.is_err() {
+            reader.skip_to_next_game();
+        }
+
+        games.push(game);
+    }
+
+    games
+}
+
+fn parse_game(reader: &mut Reader) -> Result<PgnGame> {
+    let mut game = PgnGame::default();
+    let mut fen_position = reader.position();
+
+    loop {
+        reader.skip_whitespace();
+        if reader.peek() != Some('[') {
+            break;
+        }
+
+        let position = reader.position();
+        let tag = read_tag(reader)?;
+        if tag.0 == "FEN" {
+            fen_position = position;
+        }
+
+        game.tags.push(tag);
+    }
+
+    let mut board = game.start_board().map_err(|e| {
+        let (line, column) = fen_position;
+        PgnError::BadFen(e.to_string(), line, column)
+    })?;
+
+    let start = reader.position();
+    game.moves = parse_line(reader, &mut board, start, 0, &mut game.result)?;
+
+    Ok(game)
+}
+
+/// Reads a tag pair such as `[Event "Casual game"]`.
+fn read_tag(reader: &mut Reader) -> Result<(String, String)> {
+    let (line, column) = reader.position();
+    reader.next();
+    reader.skip_whitespace();
+
+    let name = reader.read_while(|c| c.is_ascii_alphanumeric() || c == '_');
+    if name.is_empty() {
+        return Err(reader.unexpected());
+    }
+
+    reader.skip_whitespace();
+    if reader.peek() != Some('"') {
+        return Err(reader.unexpected());
+    }
+    reader.next();
+
+    let mut value = String::new();
+    loop {
+        match reader.next() {
+            Some('"') => break,
+            Some('\\') => value.extend(reader.next()),
+            Some('\n') | None => return Err(Box::new(PgnError::Unterminated("tag", line, column))),
+            Some(c) => value.push(c),
+        }
+    }
+
+    reader.skip_whitespace();
+    if reader.peek() != Some(']') {
+        return Err(reader.unexpected());
+    }
+    reader.next();
+
+    Ok((name, value))
+}
+
+/// Reads the moves of a line up to its end, playing them on `board`: the
+/// closing parenthesis of a variation, or the result, the next game or the
+/// end of the text for the mainline. `start` is where the line begins.
+fn parse_line(
+    reader: &mut Reader,
+    board: &mut Board,
+    start: (usize, usize),
+    depth: usize,
+    result: &mut Option<String>,
+) -> Result<Vec<PgnMove>> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+    let mut comment_before = None;
+
+    loop {
+        reader.skip_whitespace();
+        let (line, column) = reader.position();
+
+        let Some(c) = reader.peek() else {
+            if depth > 0 {
+                let (line, column) = start;
+                return Err(Box::new(PgnError::Unterminated("variation", line, column)));
+            }
+
+            return Ok(moves);
+        };
+
+        match c {
+            '{' | ';' => {
+                reader.next();
+                let end = if c == '{' { '}' } else { '\n' };
+                let Some(comment) = reader
+                    .read_until(end)
+                    .or_else(|| (c == ';').then(String::new))
+                else {
+                    return Err(Box::new(PgnError::Unterminated("comment", line, column)));
+                };
+
+                let comment = comment.split_whitespace().collect::<Vec<_>>().join(" ");
+                let target = match moves.last_mut() {
+                    Some(pgn_move) => &mut pgn_move.comment,
+                    None => &mut comment_before,
+                };
+                *target = Some(match target.take() {
+                    Some(previous) => format!("{} {}", previous, comment),
+                    None => comment,
+                });
+            }
+            '$' => {
+                reader.next();
+                let digits = reader.read_while(|c| c.is_ascii_digit());
+                let (Ok(nag), Some(pgn_move)) = (digits.parse(), moves.last_mut()) else {
+                    return Err(Box::new(PgnError::Unexpected(
+                        format!("${}", digits),
+                        line,
+                        column,
+                    )));
+                };
+
+                pgn_move.nags.push(nag);
+            }
+            '(' => {
+                reader.next();
+                let Some(pgn_move) = moves.last_mut() else {
+                    return Err(Box::new(PgnError::Unexpected(
+                        "variation".to_string(),
+                        line,
+                        column,
+                    )));
+                };
+
+                let mut variation_board = board.clone();
+                let _ = variation_board.undo_move();
+                let variation = parse_line(
+                    reader,
+                    &mut variation_board,
+                    (line, column),
+                    depth + 1,
+                    result,
+                )?;
+                pgn_move.variations.push(variation);
+            }
+            ')' if depth > 0 => {
+                reader.next();
+                return Ok(moves);
+            }
+            '[' if depth == 0 => return Ok(moves),
+            c if c.is_ascii_alphanumeric() || c == '*' => {
+                let token =
+                    reader.read_while(|c| c.is_ascii_alphanumeric() || "_+#=:-/*!?".contains(c));
+
+                if RESULTS.contains(&token.as_str()) {
+                    if depth > 0 {
+                        return Err(Box::new(PgnError::Unexpected(token, line, column)));
+                    }
+
+                    *result = Some(token);
+                    return Ok(moves);
+                }
+
+                // A move number, followed by `.` for white or `...` for black
+                if token.chars().all(|c| c.is_ascii_digit()) {
+                    reader.read_while(|c| c == '.');
+                    continue;
+                }
+
+                let san = token.trim_end_matches(['!', '?']);
+                let v_move = parse_san(board, san)
+                    .map_err(|e| PgnError::BadMove(e.to_string(), line, column))?;
+
+                let mut pgn_move = PgnMove::new(v_move, san.to_string());
+                let suffix = &token[san.len()..];
+                if !suffix.is_empty() {
+                    let Some(&(_, nag)) = SUFFIX_ANNOTATIONS.iter().find(|(s, _)| *s == suffix)
+                    else {
+                        return Err(Box::new(PgnError::Unexpected(token, line, column)));
+                    };
+
+                    pgn_move.nags.push(nag);
+                }
+                pgn_move.comment_before = comment_before.take();
+
+                board.make_move(v_move);
+                moves.push(pgn_move);
+            }
+            c => return Err(Box::new(PgnError::Unexpected(c.to_string(), line, column))),
+        }
+    }
+}
+
+/// Reads PGN text one character at a time, keeping track of the line and
+/// column for errors.
+struct Reader<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn position(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(c)
+    }
+
+    fn read_while(&mut self, condition: impl Fn(char) -> bool) -> String {
+        let mut string = String::new();
+        while let Some(c) = self.peek().filter(|&c| condition(c)) {
+            string.push(c);
+            self.next();
+        }
+
+        string
+    }
+
+    /// The text up to `end`, which is consumed, or `None` if the text ends
+    /// first.
+    fn read_until(&mut self, end: char) -> Option<String> {
+        let mut string = String::new();
+        loop {
+            match self.next()? {
+                c if c == end => return Some(string),
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// Skips whitespace and escaped lines, which start with `%`.
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '%' && self.column == 1 {
+                self.read_until('\n');
+            } else if c.is_whitespace() {
+                self.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn skip_to_next_game(&mut self) {
+        while self.next().is_some() {
+            if self.column == 1 && self.peek() == Some('[') {
+                break;
+            }
+        }
+    }
+
+    /// An error for the character about to be read.
+    fn unexpected(&mut self) -> Box<PgnError> {
+        let (line, column) = self.position();
+        let found = self
+            .peek()
+            .map_or("end of file".to_string(), |c| c.to_string());
+
+        Box::new(PgnError::Unexpected(found, line, column))
+    }
+}
+
+/// Today's date as a PGN `Date` tag value.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() / 86400) as i64;
+
+    // Days since 1970-01-01 to a civil date, counting years from March
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::Move;
+
+    /// The error of the only game in `text`.
+    fn parse_error(text: &str) -> PgnError {
+        let mut games = parse(text);
+        assert_eq!(games.len(), 1);
+        match games.remove(0) {
+            Ok(_) => panic!("{text:?} parsed"),
+            Err(e) => *e.downcast::<PgnError>().unwrap(),
+        }
+    }
+
+    fn sans(moves: &[PgnMove]) -> Vec<&str> {
+        moves.iter().map(|pgn_move| pgn_move.san.as_str()).collect()
+    }
+
+    #[test]
+    fn annotations_and_nested_variations() {
+        let text = "[Event \"Test\"]\n\
+                    [White \"A\"]\n\
+                    \n\
+                    {Opening} 1. e4 $1 e5 ({Sicilian} 1... c5 2. Nf3 (2. Nc3 Nc6 (2... d6))\n\
+                    2... d6) 2. Nf3 ; knight out\n\
+                    Nc6 $14 3. Bb5! a6 1-0\n";
+
+        let games = parse(text);
+        assert_eq!(games.len(), 1);
+        let game = games[0].as_ref().unwrap();
+
+        assert_eq!(game.tag("Event"), Some("Test"));
+        assert_eq!(game.tag("White"), Some("A"));
+        assert_eq!(game.result.as_deref(), Some("1-0"));
+        assert_eq!(sans(&game.moves), ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+
+        let moves = &game.moves;
+        assert_eq!(moves[0].comment_before.as_deref(), Some("Opening"));
+        assert_eq!(moves[0].nags, [1]);
+        assert_eq!(moves[2].comment.as_deref(), Some("knight out"));
+        assert_eq!(moves[3].nags, [14]);
+        assert_eq!(moves[4].san, "Bb5");
+        assert_eq!(moves[4].nags, [1]);
+
+        assert_eq!(moves[1].variations.len(), 1);
+        let sicilian = &moves[1].variations[0];
+        assert_eq!(sans(sicilian), ["c5", "Nf3", "d6"]);
+        assert_eq!(sicilian[0].comment_before.as_deref(), Some("Sicilian"));
+
+        assert_eq!(sicilian[1].variations.len(), 1);
+        let closed = &sicilian[1].variations[0];
+        assert_eq!(sans(closed), ["Nc3", "Nc6"]);
+        assert_eq!(closed[1].variations.len(), 1);
+        assert_eq!(sans(&closed[1].variations[0]), ["d6"]);
+
+        // Variation moves are played from the position before the move
+        let mut board = Board::new();
+        for uci in ["e2e4", "c7c5", "b1c3"] {
+            let v_move = Move::from_uci(&mut board, uci).unwrap();
+            board.make_move(v_move);
+        }
+        let d6 = Move::from_uci(&mut board, "d7d6").unwrap();
+        assert_eq!(closed[1].variations[0][0].v_move, d6);
+    }
+
+    #[test]
+    fn illegal_move_gives_its_line_and_column() {
+        let error = parse_error("[Event \"Test\"]\n\n1. e4 e5\n2. Nf3 Ke3 *\n");
+        assert!(matches!(error, PgnError::BadMove(_, 4, 8)), "got {error:?}");
+    }
+
+    #[test]
+    fn illegal_move_in_a_variation_gives_its_line_and_column() {
+        let error = parse_error("1. e4 (1. d4 d5\n  2. Nf6) e5 *\n");
+        assert!(matches!(error, PgnError::BadMove(_, 2, 6)), "got {error:?}");
+    }
+
+    #[test]
+    fn unterminated_comment_gives_where_it_starts() {
+        let error = parse_error("1. e4 e5\n2. Nf3 {never\nclosed 2... Nc6\n");
+        assert!(
+            matches!(error, PgnError::Unterminated("comment", 2, 8)),
+            "got {error:?}"
+        );
+    }
+
+    #[test]
+    fn unterminated_variation_gives_where_it_starts() {
+        let error = parse_error("1. e4 e5 (1... c5 2. Nf3\n");
+        assert!(
+            matches!(error, PgnError::Unterminated("variation", 1, 10)),
+            "got {error:?}"
+        );
+    }
+
+    #[test]
+    fn reading_goes_on_after_a_bad_game() {
+        let games = parse("[Event \"Bad\"]\n\n1. e4 e4 *\n\n[Event \"Good\"]\n\n1. d4 d5 *\n");
+        assert_eq!(games.len(), 2);
+        assert!(games[0].is_err());
+        let game = games[1].as_ref().unwrap();
+        assert_eq!(game.tag("Event"), Some("Good"));
+        assert_eq!(sans(&game.moves), ["d4", "d5"]);
+    }
+
+    #[test]
+    fn exported_games_read_back() {
+        let game = parse("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. O-O Nf6 *")
+            .remove(0)
+            .unwrap();
+        let pgn = game.to_pgn().unwrap();
+        let read_back = parse(&pgn).remove(0).unwrap();
+
+        assert_eq!(sans(&read_back.moves), sans(&game.moves));
+        assert_eq!(read_back.tag("Result"), Some("*"));
+    }
+}
@@ -31,6 +31,17 @@ impl Piece {
     const BLACK_MASK: u8 = 0b00100000;
     const COLOUR_MASK: u8 = Self::WHITE_MASK | Self::BLACK_MASK;
 
+    pub fn value(piece: u8) -> i32 {
+        match Self::piece_type(piece) {
+            Piece::Pawn => Piece::PawnValue,
+            Piece::Knight => Piece::KnightValue,
+            Piece::Bishop => Piece::BishopValue,
+            Piece::Rook => Piece::RookValue,
+            Piece::Queen => Piece::QueenValue,
+            _ => 0,
+        }
+    }
+
     pub fn colour(piece: u8) -> u8 {
         piece & Self::COLOUR_MASK
     }
@@ -0,0 +1,234 @@
+use std::fs;
+
+use crate::{
+    colour::Colour, errors::PstError, evaluation::Score, params::EvalParams, piece::Piece, Result,
+};
+
+/// Names of the tables in a table file, in piece type order.
+const TABLE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+// Tables are laid out as seen from white's side, rank 8 first, so that they
+// read like a board diagram.
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10,  10,  10,  10,  10,   5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+// Pawns gain value as they advance and the king should head for the centre
+// once the queens are gone, the other pieces use their middlegame tables.
+#[rustfmt::skip]
+const PAWN_ENDGAME_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     80,  80,  80,  80,  80,  80,  80,  80,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     15,  15,  15,  15,  15,  15,  15,  15,
+      5,   5,   5,   5,   5,   5,   5,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+/// The default table of `piece_type`, pairing its middlegame and endgame values.
+pub fn default_table(piece_type: u8) -> [Score; 64] {
+    let (middlegame, endgame) = match piece_type {
+        Piece::Pawn => (PAWN_TABLE, PAWN_ENDGAME_TABLE),
+        Piece::Knight => (KNIGHT_TABLE, KNIGHT_TABLE),
+        Piece::Bishop => (BISHOP_TABLE, BISHOP_TABLE),
+        Piece::Rook => (ROOK_TABLE, ROOK_TABLE),
+        Piece::Queen => (QUEEN_TABLE, QUEEN_TABLE),
+        _ => (KING_TABLE, KING_ENDGAME_TABLE),
+    };
+
+    std::array::from_fn(|square| Score::new(middlegame[square], endgame[square]))
+}
+
+/// The bonus in `params` for `piece` standing on `square`, mirrored for black.
+pub fn value(params: &EvalParams, piece: u8, square: usize) -> Score {
+    // Flipping the rank turns a board index into a diagram index for
+    // white, and mirrors the table for black
+    let square = match Piece::colour_bool(piece) {
+        Colour::White => square ^ 56,
+        Colour::Black => square,
+    };
+
+    table(params, Piece::piece_type(piece))[square]
+}
+
+/// Reads tables from a text file into `params`.
+///
+/// Each table starts with a line holding its piece name followed by a
+/// colon (`pawn:`, `knight:`, ...) and is followed by 64 whitespace
+/// separated values laid out like the default tables, rank 8 first.
+/// A plain name sets both the middlegame and endgame tables, `pawn.mg:`
+/// or `pawn.eg:` only one of them. Tables missing from the file keep
+/// their current values, and everything after a `#` on a line is ignored.
+pub fn load(path: &str, params: &mut EvalParams) -> Result<()> {
+    let contents = fs::read_to_string(path)?;
+
+    let mut current: Option<(&str, Vec<i32>)> = None;
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        if let Some(name) = line.strip_suffix(':') {
+            if let Some((name, values)) = current.take() {
+                set_table(params, name, values)?;
+            }
+
+            current = Some((name.trim(), Vec::with_capacity(64)));
+
+            continue;
+        }
+
+        for value in line.split_whitespace() {
+            let (_, values) = current
+                .as_mut()
+                .ok_or(PstError::ValueOutsideTable(line_number + 1))?;
+
+            values.push(
+                value
+                    .parse()
+                    .map_err(|_| PstError::BadValue(value.to_string(), line_number + 1))?,
+            );
+        }
+    }
+
+    if let Some((name, values)) = current {
+        set_table(params, name, values)?;
+    }
+
+    Ok(())
+}
+
+/// Restores the default tables in `params`, leaving the other weights alone.
+pub fn reset(params: &mut EvalParams) {
+    for piece_type in Piece::Pawn..=Piece::King {
+        *table_mut(params, piece_type) = default_table(piece_type);
+    }
+}
+
+fn set_table(params: &mut EvalParams, name: &str, values: Vec<i32>) -> Result<()> {
+    let (piece_name, stage) = name.split_once('.').unwrap_or((name, ""));
+
+    let index = TABLE_NAMES
+        .iter()
+        .position(|&table_name| table_name == piece_name)
+        .ok_or(PstError::UnknownTable(name.to_string()))?;
+
+    let count = values.len();
+    let values: [i32; 64] = values
+        .try_into()
+        .map_err(|_| PstError::WrongSize(name.to_string(), count))?;
+
+    let table = table_mut(params, index as u8 + 1);
+    for (score, value) in table.iter_mut().zip(values) {
+        match stage {
+            "" => *score = Score::new(value, value),
+            "mg" => score.mg = value,
+            "eg" => score.eg = value,
+            _ => return Err(Box::new(PstError::UnknownTable(name.to_string()))),
+        }
+    }
+
+    Ok(())
+}
+
+fn table(params: &EvalParams, piece_type: u8) -> &[Score; 64] {
+    match piece_type {
+        Piece::Pawn => &params.pawn_pst,
+        Piece::Knight => &params.knight_pst,
+        Piece::Bishop => &params.bishop_pst,
+        Piece::Rook => &params.rook_pst,
+        Piece::Queen => &params.queen_pst,
+        _ => &params.king_pst,
+    }
+}
+
+fn table_mut(params: &mut EvalParams, piece_type: u8) -> &mut [Score; 64] {
+    match piece_type {
+        Piece::Pawn => &mut params.pawn_pst,
+        Piece::Knight => &mut params.knight_pst,
+        Piece::Bishop => &mut params.bishop_pst,
+        Piece::Rook => &mut params.rook_pst,
+        Piece::Queen => &mut params.queen_pst,
+        _ => &mut params.king_pst,
+    }
+}
@@ -0,0 +1,403 @@
+//! Standard Algebraic Notation, the move format of PGN files.
+
+use crate::{
+    board::Board, errors::SanError, moves::Move, piece::Piece, utils::square_to_string, Result,
+};
+
+/// The legal move of `board` written `san`. Check, mate and annotation
+/// suffixes are ignored, and castling may be written with zeros.
+pub fn parse_san(board: &mut Board, san: &str) -> Result<u16> {
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+    let moves = board.generate_legal_moves();
+
+    let castling = match text {
+        "O-O" | "0-0" => Some(Move::ShortCastle),
+        "O-O-O" | "0-0-0" => Some(Move::LongCastle),
+        _ => None,
+    };
+    if let Some(castling) = castling {
+        return match moves.contains(&castling) {
+            true => Ok(castling),
+            false => Err(Box::new(SanError::NoSuchMove(san.to_string()))),
+        };
+    }
+
+    let malformed = || SanError::Malformed(san.to_string());
+
+    let (piece_type, text) = match text.chars().next().and_then(piece_from_letter) {
+        Some(piece_type) => (piece_type, &text[1..]),
+        None => (Piece::Pawn, text),
+    };
+
+    let (text, promotion) = match text.chars().next_back().and_then(piece_from_letter) {
+        Some(promotion) => (
+            text[..text.len() - 1].trim_end_matches('='),
+            Some(promotion),
+        ),
+        None => (text, None),
+    };
+
+    let squares = text.chars().filter(|&c| c != 'x').collect::<Vec<_>>();
+    if squares.len() < 2 || squares.len() > 4 {
+        return Err(Box::new(malformed()));
+    }
+
+    let (disambiguation, target) = squares.split_at(squares.len() - 2);
+    let target_file = file_index(target[0]).ok_or_else(malformed)?;
+    let target_rank = rank_index(target[1]).ok_or_else(malformed)?;
+    let target_square = target_rank * 8 + target_file;
+
+    let mut departure_file = None;
+    let mut departure_rank = None;
+    for &c in disambiguation {
+        match (file_index(c), rank_index(c)) {
+            (Some(file), _) => departure_file = Some(file),
+            (_, Some(rank)) => departure_rank = Some(rank),
+            _ => return Err(Box::new(malformed())),
+        }
+    }
+
+    let candidates = moves
+        .into_iter()
+        .filter(|&v_move| {
+            let departure_square = Move::departure_square(v_move);
+
+            !Move::is_castling(v_move)
+                && Move::target_square(v_move) == target_square
+                && Piece::piece_type(board.piece_at(departure_square)) == piece_type
+                && departure_file.is_none_or(|file| departure_square % 8 == file)
+                && departure_rank.is_none_or(|rank| departure_square / 8 == rank)
+                && promotion_piece(v_move) == promotion
+        })
+        .collect::<Vec<_>>();
+
+    match candidates[..] {
+        [v_move] => Ok(v_move),
+        [] => Err(Box::new(SanError::NoSuchMove(san.to_string()))),
+        _ => Err(Box::new(SanError::Ambiguous(san.to_string()))),
+    }
+}
+
+/// `v_move` in SAN, with the departure file or rank given only when another
+/// piece of the same kind could reach the target square, and with a check
+/// or mate suffix.
+pub fn to_san(board: &mut Board, v_move: u16) -> String {
+    let mut san = if Move::is_short_castling(v_move) {
+        "O-O".to_string()
+    } else if Move::is_long_castling(v_move) {
+        "O-O-O".to_string()
+    } else {
+        let departure_square = Move::departure_square(v_move);
+        let target_square = Move::target_square(v_move);
+        let piece_type = Piece::piece_type(board.piece_at(departure_square));
+        let capture = board.is_capture(v_move);
+
+        let mut san = String::new();
+        if piece_type == Piece::Pawn {
+            if capture {
+                san.push(file_letter(departure_square));
+            }
+        } else {
+            san.push(piece_letter(piece_type));
+            san.push_str(&disambiguation(board, v_move));
+        }
+
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&square_to_string(target_square));
+
+        if let Some(promotion) = promotion_piece(v_move) {
+            san.push('=');
+            san.push(piece_letter(promotion));
+        }
+
+        san
+    };
+
+    board.make_move(v_move);
+    if board.is_in_check() {
+        san.push(match board.generate_legal_moves().is_empty() {
+            true => '#',
+            false => '+',
+        });
+    }
+    let _ = board.undo_move();
+
+    san
+}
+
+/// `moves`, played one after the other from `board`, in SAN.
+pub fn line_to_san(board: &Board, moves: &[u16]) -> Vec<String> {
+    let mut board = board.clone();
+
+    moves
+        .iter()
+        .map(|&v_move| {
+            let san = to_san(&mut board, v_move);
+            board.make_move(v_move);
+
+            san
+        })
+        .collect()
+}
+
+/// What tells the piece played in `v_move` apart from others of its kind
+/// that could also move to the target square: its file if that is enough,
+/// else its rank, else both.
+fn disambiguation(board: &mut Board, v_move: u16) -> String {
+    let departure_square = Move::departure_square(v_move);
+    let target_square = Move::target_square(v_move);
+    let piece = board.piece_at(departure_square);
+
+    let rivals = board
+        .generate_legal_moves()
+        .into_iter()
+        .filter(|&other| {
+            !Move::is_castling(other)
+                && Move::target_square(other) == target_square
+                && Move::departure_square(other) != departure_square
+                && board.piece_at(Move::departure_square(other)) == piece
+        })
+        .map(Move::departure_square)
+        .collect::<Vec<_>>();
+
+    let square = square_to_string(departure_square);
+    if rivals.is_empty() {
+        String::new()
+    } else if rivals
+        .iter()
+        .all(|&rival| rival % 8 != departure_square % 8)
+    {
+        square[..1].to_string()
+    } else if rivals
+        .iter()
+        .all(|&rival| rival / 8 != departure_square / 8)
+    {
+        square[1..].to_string()
+    } else {
+        square
+    }
+}
+
+/// The piece a pawn becomes with `v_move`, if it is a promotion.
+fn promotion_piece(v_move: u16) -> Option<u8> {
+    if !Move::is_promotion(v_move) {
+        return None;
+    }
+
+    match Move::promotion_type(v_move) {
+        Move::PromoteToKnight => Some(Piece::Knight),
+        Move::PromoteToBishop => Some(Piece::Bishop),
+        Move::PromoteToRook => Some(Piece::Rook),
+        _ => Some(Piece::Queen),
+    }
+}
+
+fn piece_letter(piece_type: u8) -> char {
+    match piece_type {
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        _ => 'K',
+    }
+}
+
+fn file_letter(square: u8) -> char {
+    (b'a' + square % 8) as char
+}
+
+fn piece_from_letter(letter: char) -> Option<u8> {
+    match letter {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+fn file_index(file: char) -> Option<u8> {
+    ('a'..='h').contains(&file).then(|| file as u8 - b'a')
+}
+
+fn rank_index(rank: char) -> Option<u8> {
+    ('1'..='8').contains(&rank).then(|| rank as u8 - b'1')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::SanError;
+
+    const POSITIONS: [&str; 7] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+        "8/7k/8/8/8/Q7/8/Q1Q1K3 w - - 0 1",
+    ];
+
+    /// The move of `board` from `uci`.
+    fn uci(board: &mut Board, uci: &str) -> u16 {
+        Move::from_uci(board, uci).unwrap()
+    }
+
+    fn san_of(fen: &str, uci_move: &str) -> String {
+        let mut board = Board::from_fen(fen).unwrap();
+        let v_move = uci(&mut board, uci_move);
+        to_san(&mut board, v_move)
+    }
+
+    fn san_error(fen: &str, san: &str) -> SanError {
+        let mut board = Board::from_fen(fen).unwrap();
+        match parse_san(&mut board, san) {
+            Ok(v_move) => panic!("{san:?} parsed as {}", Move::to_uci(&board, v_move)),
+            Err(e) => *e.downcast::<SanError>().unwrap(),
+        }
+    }
+
+    #[test]
+    fn every_legal_move_round_trips() {
+        for fen in POSITIONS {
+            let mut board = Board::from_fen(fen).unwrap();
+            let moves = board.generate_legal_moves();
+
+            let mut sans = Vec::new();
+            for v_move in moves {
+                let san = to_san(&mut board, v_move);
+                assert_eq!(
+                    parse_san(&mut board, &san).unwrap(),
+                    v_move,
+                    "{san} in {fen}"
+                );
+                assert!(!sans.contains(&san), "{san} twice in {fen}");
+                sans.push(san);
+            }
+
+            assert_eq!(board.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn disambiguation() {
+        let knights = "4k3/8/8/8/8/8/4N3/1N2K3 w - - 0 1";
+        assert_eq!(san_of(knights, "b1c3"), "Nbc3");
+        assert_eq!(san_of(knights, "e2c3"), "Nec3");
+        assert_eq!(san_of(knights, "b1d2"), "Nd2");
+        assert!(matches!(san_error(knights, "Nc3"), SanError::Ambiguous(_)));
+
+        let rooks = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san_of(rooks, "a1a3"), "R1a3");
+        assert_eq!(san_of(rooks, "a5a3"), "R5a3");
+
+        let queens = "8/7k/8/8/8/Q7/8/Q1Q1K3 w - - 0 1";
+        assert_eq!(san_of(queens, "a1b2"), "Qa1b2");
+        assert_eq!(san_of(queens, "c1b2"), "Qcb2");
+        assert_eq!(san_of(queens, "a3b2"), "Q3b2");
+
+        let mut board = Board::from_fen(queens).unwrap();
+        let v_move = uci(&mut board, "a1b2");
+        assert_eq!(parse_san(&mut board, "Qa1b2").unwrap(), v_move);
+        assert_eq!(parse_san(&mut board, "Qa1xb2").unwrap(), v_move);
+        assert!(matches!(san_error(queens, "Qab2"), SanError::Ambiguous(_)));
+    }
+
+    #[test]
+    fn pawn_moves() {
+        let promotion = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san_of(promotion, "a7b8q"), "axb8=Q+");
+        assert_eq!(san_of(promotion, "a7b8n"), "axb8=N");
+        assert_eq!(san_of(promotion, "a7a8r"), "a8=R");
+
+        let mut board = Board::from_fen(promotion).unwrap();
+        let v_move = uci(&mut board, "a7b8r");
+        assert_eq!(parse_san(&mut board, "axb8=R").unwrap(), v_move);
+        assert_eq!(parse_san(&mut board, "axb8R").unwrap(), v_move);
+
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(san_of(en_passant, "e5d6"), "exd6");
+        assert_eq!(san_of(en_passant, "e5e6"), "e6");
+    }
+
+    #[test]
+    fn castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        assert_eq!(to_san(&mut board, Move::ShortCastle), "O-O");
+        assert_eq!(to_san(&mut board, Move::LongCastle), "O-O-O");
+        assert_eq!(parse_san(&mut board, "O-O").unwrap(), Move::ShortCastle);
+        assert_eq!(parse_san(&mut board, "0-0-0").unwrap(), Move::LongCastle);
+
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        assert_eq!(to_san(&mut board, Move::ShortCastle), "O-O");
+        assert_eq!(to_san(&mut board, Move::LongCastle), "O-O-O");
+
+        // The rook gives check from its castled square
+        let fen = "5k2/8/8/8/8/8/8/4K2R w K - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        assert_eq!(to_san(&mut board, Move::ShortCastle), "O-O+");
+    }
+
+    #[test]
+    fn check_and_mate_suffixes() {
+        // The pinned knight on d7 needs no telling apart from the one on g8
+        let checks = ["e4", "d6", "Bb5+", "Nd7", "Qh5", "Nf6", "Qxf7+"];
+        let mate = ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6", "Qxf7#"];
+        for line in [&checks[..], &mate[..]] {
+            let mut board = Board::new();
+            let mut sans = Vec::new();
+            for &san in line {
+                let v_move = parse_san(&mut board, san).unwrap();
+                sans.push(to_san(&mut board, v_move));
+                board.make_move(v_move);
+            }
+
+            assert_eq!(sans, line);
+            assert_eq!(board.generate_legal_moves().is_empty(), line == mate);
+        }
+
+        // Suffixes are not needed to read a move
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let v_move = uci(&mut board, "a1a8");
+        assert_eq!(parse_san(&mut board, "Ra8").unwrap(), v_move);
+        assert_eq!(parse_san(&mut board, "Ra8+!?").unwrap(), v_move);
+    }
+
+    #[test]
+    fn invalid_san_is_rejected() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        for san in ["", "e", "Nz3", "e9", "Qxx", "Nb1c3d4", "x"] {
+            assert!(
+                matches!(san_error(start, san), SanError::Malformed(_)),
+                "{san:?}"
+            );
+        }
+        for san in ["e5", "Ke2", "exd3", "O-O", "O-O-O", "e8=Q", "Nc3+x", "Bb5"] {
+            assert!(
+                matches!(
+                    san_error(start, san),
+                    SanError::NoSuchMove(_) | SanError::Malformed(_)
+                ),
+                "{san:?}"
+            );
+        }
+        assert!(matches!(san_error(start, "Nd2"), SanError::NoSuchMove(_)));
+        assert!(matches!(san_error(start, "Bc4"), SanError::NoSuchMove(_)));
+
+        let promotion = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert!(matches!(
+            san_error(promotion, "a8"),
+            SanError::NoSuchMove(_)
+        ));
+        assert!(matches!(
+            san_error(promotion, "a8=K"),
+            SanError::NoSuchMove(_)
+        ));
+    }
+}
@@ -0,0 +1,1226 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::Board,
+    colour::Colour,
+    kpk,
+    moves::Move,
+    nnue,
+    piece::Piece,
+    syzygy::{self, Wdl},
+    tablebase::{self, Dtm},
+    tt::{Bound, TranspositionTable},
+};
+
+pub const INFINITY: i32 = 32_000;
+pub const MATE_SCORE: i32 = 31_000;
+/// Scores beyond this are mates, with the distance to mate encoded in the remainder.
+pub const MATE_BOUND: i32 = MATE_SCORE - MAX_PLY as i32;
+pub const MAX_PLY: usize = 128;
+/// Tablebase wins score just below mates, less the distance from the root.
+pub const TB_WIN: i32 = MATE_BOUND - 1;
+/// Scores beyond this are tablebase wins or mates.
+const TB_WIN_BOUND: i32 = TB_WIN - MAX_PLY as i32;
+
+const SINGULAR_MIN_DEPTH: i32 = 4;
+/// Root moves are only reported once the search has run this long, as GUIs
+/// do not need a flood of them on fast searches.
+const CURRENT_MOVE_REPORT_DELAY: Duration = Duration::from_secs(1);
+const SINGULAR_TT_DEPTH_MARGIN: i32 = 3;
+/// Tablebase results are stored deeper than the search reached, as no
+/// search would change them.
+const TB_DEPTH_BONUS: i32 = 6;
+
+/// The moves to mate of a mate score, negative when getting mated, or
+/// `None` for any other score.
+pub fn score_to_mate(score: i32) -> Option<i32> {
+    (score.abs() >= MATE_BOUND).then(|| (MATE_SCORE - score.abs() + 1) / 2 * score.signum())
+}
+
+/// Optional parts of the search, stored as a bitmask so they can be switched
+/// on and off independently
+#[non_exhaustive]
+pub struct SearchFeature;
+
+#[allow(non_upper_case_globals)]
+impl SearchFeature {
+    /// Searches one ply deeper when the side to move is in check.
+    pub const CheckExtensions: u8 = 0b01;
+    /// Searches the transposition table move one ply deeper when every
+    /// alternative scores clearly below it.
+    pub const SingularExtensions: u8 = 0b10;
+
+    pub const All: u8 = Self::CheckExtensions | Self::SingularExtensions;
+
+    pub const LIST: [u8; 2] = [Self::CheckExtensions, Self::SingularExtensions];
+
+    pub fn name(feature: u8) -> &'static str {
+        match feature {
+            Self::CheckExtensions => "checkextensions",
+            Self::SingularExtensions => "singularextensions",
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<u8> {
+        match name.to_ascii_lowercase().as_str() {
+            "check" | "checkextensions" => Some(Self::CheckExtensions),
+            "singular" | "singularextensions" => Some(Self::SingularExtensions),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SearchStats {
+    pub nodes: u64,
+    pub quiescence_nodes: u64,
+    pub tt_cutoffs: u64,
+    pub check_extensions: u64,
+    pub singular_extensions: u64,
+    /// Reduced searches run to test whether a move is singular.
+    pub singular_searches: u64,
+    pub tb_hits: u64,
+    pub seldepth: usize,
+}
+
+impl SearchStats {
+    fn add(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.quiescence_nodes += other.quiescence_nodes;
+        self.tt_cutoffs += other.tt_cutoffs;
+        self.check_extensions += other.check_extensions;
+        self.singular_extensions += other.singular_extensions;
+        self.singular_searches += other.singular_searches;
+        self.tb_hits += other.tb_hits;
+        self.seldepth = self.seldepth.max(other.seldepth);
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct SearchLimits {
+    pub depth: Option<i32>,
+    pub movetime: Option<Duration>,
+    pub nodes: Option<u64>,
+    /// Number of best lines to search for, one if unset.
+    pub multi_pv: usize,
+    /// Whether the search starts on the opponent's time, its limits only
+    /// applying after a ponder hit.
+    pub ponder: bool,
+}
+
+#[derive(Clone)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Vec<u16>,
+}
+
+/// Progress reported while searching, for frontends to format.
+pub enum SearchInfo {
+    /// A completed iteration, once per line in multi-PV mode.
+    Iteration(IterationInfo),
+    /// The root move about to be searched, numbered from one.
+    CurrentMove {
+        depth: i32,
+        v_move: u16,
+        number: usize,
+    },
+}
+
+pub struct IterationInfo {
+    pub depth: i32,
+    pub seldepth: usize,
+    /// Rank of the line, from one.
+    pub multi_pv: usize,
+    pub score: i32,
+    /// Nodes searched by every thread.
+    pub nodes: u64,
+    pub nps: u64,
+    /// Permille of the transposition table used by this search.
+    pub hashfull: u32,
+    /// Tablebase probes that gave a result, by the reporting thread.
+    pub tb_hits: u64,
+    pub time: Duration,
+    pub pv: Vec<u16>,
+}
+
+/// Receives search progress along with the root position it refers to.
+pub type InfoFn = dyn Fn(&Board, &SearchInfo) + Send + Sync;
+pub type InfoCallback = Box<InfoFn>;
+
+pub struct SearchResult {
+    pub best_move: Option<u16>,
+    pub score: i32,
+    pub depth: i32,
+    pub pv: Vec<u16>,
+    /// The best lines when searching several, starting with the principal variation.
+    pub lines: Vec<PvLine>,
+    pub stats: SearchStats,
+    pub elapsed: Duration,
+}
+
+/// Owns the state shared by every search thread and runs Lazy SMP: all
+/// threads search the same position and only communicate through the
+/// transposition table.
+pub struct Searcher {
+    tt: TranspositionTable,
+    features: u8,
+    threads: usize,
+    stop: Arc<AtomicBool>,
+    /// Set while searching on the opponent's time, when limits do not apply yet.
+    ponder: Arc<AtomicBool>,
+    /// Set while a search runs, once its stop and ponder flags are reset.
+    running: Arc<AtomicBool>,
+    nodes: AtomicU64,
+    info_callback: Option<InfoCallback>,
+}
+
+/// The state private to a single search thread.
+struct Worker<'a> {
+    id: usize,
+    tt: &'a TranspositionTable,
+    stop: &'a AtomicBool,
+    ponder: &'a AtomicBool,
+    /// Nodes searched by every thread, updated in batches.
+    shared_nodes: &'a AtomicU64,
+    flushed_nodes: u64,
+    /// Only the main thread reports progress.
+    info_callback: Option<&'a InfoFn>,
+    features: u8,
+    stats: SearchStats,
+    limits: SearchLimits,
+    start_time: Instant,
+    /// When the time limit started counting, which is after a ponder hit when pondering.
+    clock_start: Instant,
+    was_pondering: bool,
+    stopped: bool,
+    killers: [[u16; 2]; MAX_PLY],
+    history: Box<[[i32; 64]; 64]>,
+    excluded_moves: [u16; MAX_PLY],
+    /// The moves searched at the root, only the best ones by the
+    /// tablebases when the root position is in them.
+    root_moves: Vec<u16>,
+    /// Root moves already reported as better lines in multi-PV mode.
+    root_excluded_moves: Vec<u16>,
+    pv_table: Box<[[u16; MAX_PLY]; MAX_PLY]>,
+    pv_length: [usize; MAX_PLY],
+    /// Score of the best root move searched to the end, the score of a line
+    /// whose search was stopped before the root returned.
+    root_best_score: i32,
+    /// Hashes of every position since the last irreversible move, for repetition detection.
+    hash_history: Vec<u64>,
+}
+
+impl Searcher {
+    pub const DEFAULT_DEPTH: i32 = 5;
+    pub const MAX_THREADS: usize = 256;
+
+    pub fn new(tt_size_mb: usize) -> Self {
+        Self {
+            tt: TranspositionTable::new(tt_size_mb),
+            features: SearchFeature::All,
+            threads: 1,
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::new(AtomicBool::new(false)),
+            running: Arc::new(AtomicBool::new(false)),
+            nodes: AtomicU64::new(0),
+            info_callback: None,
+        }
+    }
+
+    pub fn set_feature(&mut self, feature: u8, enabled: bool) {
+        if enabled {
+            self.features |= feature;
+        } else {
+            self.features &= !feature;
+        }
+    }
+
+    pub fn is_enabled(&self, feature: u8) -> bool {
+        self.features & feature == feature
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.clamp(1, Self::MAX_THREADS);
+    }
+
+    pub fn resize_tt(&mut self, size_mb: usize) {
+        self.tt = TranspositionTable::new(size_mb.min(TranspositionTable::MAX_SIZE_MB));
+    }
+
+    pub fn clear(&mut self) {
+        self.tt.clear();
+    }
+
+    /// A handle that stops the running search when set, from any thread.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    pub fn set_info_callback(&mut self, callback: InfoCallback) {
+        self.info_callback = Some(callback);
+    }
+
+    /// A handle that, while set, makes the search ignore its limits and keep
+    /// going until it is cleared (a ponder hit) or the search is stopped.
+    pub fn ponder_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.ponder)
+    }
+
+    /// A handle set while a search runs, from the moment its stop and ponder
+    /// flags are reset until it returns. Frontends searching on another
+    /// thread wait for it before accepting a stop or a ponder hit, which
+    /// would otherwise be undone by the reset.
+    pub fn running_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.running)
+    }
+
+    /// Searches `board` until `limits` or the stop handle say otherwise.
+    ///
+    /// The stop and ponder flags are reset when the search starts, so that a
+    /// stop or ponder hit arriving after the previous search ended does not
+    /// leak into this one.
+    pub fn search(&mut self, board: &mut Board, limits: SearchLimits) -> SearchResult {
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(limits.ponder, Ordering::Relaxed);
+        self.running.store(true, Ordering::Release);
+
+        self.tt.new_search();
+        self.nodes.store(0, Ordering::Relaxed);
+        board.set_network(nnue::active_network());
+
+        let start_time = Instant::now();
+        let hash_history = game_hashes(board);
+        let root_moves = syzygy::root_moves(board, &hash_history)
+            .unwrap_or_else(|| board.generate_legal_moves());
+
+        // With a single thread the search runs on the caller's thread and is deterministic
+        let result = if self.threads == 1 {
+            let mut worker = self.worker(0, limits, start_time, hash_history, root_moves);
+            let result = worker.search(board);
+            self.wait_while_pondering();
+            result
+        } else {
+            self.search_threads(board, limits, start_time, hash_history, root_moves)
+        };
+
+        self.running.store(false, Ordering::Release);
+
+        result
+    }
+
+    fn search_threads(
+        &self,
+        board: &mut Board,
+        limits: SearchLimits,
+        start_time: Instant,
+        hash_history: Vec<u64>,
+        root_moves: Vec<u16>,
+    ) -> SearchResult {
+        let mut results = thread::scope(|scope| {
+            let helpers = (1..self.threads)
+                .map(|id| {
+                    let mut board = board.clone();
+                    let mut worker = self.worker(
+                        id,
+                        limits,
+                        start_time,
+                        hash_history.clone(),
+                        root_moves.clone(),
+                    );
+
+                    scope.spawn(move || worker.search(&mut board))
+                })
+                .collect::<Vec<_>>();
+
+            let mut main_worker =
+                self.worker(0, limits, start_time, hash_history.clone(), root_moves);
+            let main_result = main_worker.search(board);
+            self.wait_while_pondering();
+
+            // Helpers never finish on their own before the main thread, so stop them
+            self.stop.store(true, Ordering::Relaxed);
+
+            let mut results = vec![main_result];
+            results.extend(helpers.into_iter().filter_map(|helper| helper.join().ok()));
+            results
+        });
+
+        let mut stats = SearchStats::default();
+        for result in &results {
+            stats.add(&result.stats);
+        }
+
+        // Prefer the deepest completed iteration, and the main thread among equals
+        let best_index = results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| result.best_move.is_some())
+            .max_by_key(|&(index, result)| (result.depth, index == 0))
+            .map_or(0, |(index, _)| index);
+
+        let mut result = results.swap_remove(best_index);
+        result.stats = stats;
+        result.elapsed = start_time.elapsed();
+
+        result
+    }
+
+    /// A search may not return while pondering, even once it hit its depth
+    /// limit, as the move would be reported before the opponent played.
+    fn wait_while_pondering(&self) {
+        while self.ponder.load(Ordering::Relaxed) && !self.stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// The move expected in reply to `best_move`, taken from the principal
+    /// variation or, when that is too short, from the transposition table.
+    pub fn ponder_move(&self, board: &Board, result: &SearchResult) -> Option<u16> {
+        if let Some(&ponder_move) = result.pv.get(1) {
+            return Some(ponder_move);
+        }
+
+        let mut board = board.clone();
+        board.make_move(result.best_move?);
+
+        let tt_move = self.tt.probe(board.hash())?.best_move;
+        board
+            .generate_legal_moves()
+            .contains(&tt_move)
+            .then_some(tt_move)
+    }
+
+    /// The captures quiescence search expects to be played from `board`,
+    /// leading to the quiet position its score comes from.
+    pub fn quiescence_pv(&self, board: &mut Board) -> Vec<u16> {
+        let mut worker = self.worker(
+            0,
+            SearchLimits::default(),
+            Instant::now(),
+            Vec::new(),
+            Vec::new(),
+        );
+        worker.quiescence(board, -INFINITY, INFINITY, 0);
+
+        worker.pv_table[0][..worker.pv_length[0]].to_vec()
+    }
+
+    fn worker(
+        &self,
+        id: usize,
+        limits: SearchLimits,
+        start_time: Instant,
+        hash_history: Vec<u64>,
+        root_moves: Vec<u16>,
+    ) -> Worker<'_> {
+        Worker {
+            id,
+            tt: &self.tt,
+            stop: &self.stop,
+            ponder: &self.ponder,
+            shared_nodes: &self.nodes,
+            flushed_nodes: 0,
+            info_callback: self.info_callback.as_deref().filter(|_| id == 0),
+            features: self.features,
+            stats: SearchStats::default(),
+            limits,
+            start_time,
+            clock_start: start_time,
+            was_pondering: self.ponder.load(Ordering::Relaxed),
+            stopped: false,
+            killers: [[0; 2]; MAX_PLY],
+            history: Box::new([[0; 64]; 64]),
+            excluded_moves: [0; MAX_PLY],
+            root_moves,
+            root_excluded_moves: Vec::new(),
+            pv_table: Box::new([[0; MAX_PLY]; MAX_PLY]),
+            pv_length: [0; MAX_PLY],
+            root_best_score: -INFINITY,
+            hash_history,
+        }
+    }
+}
+
+impl Worker<'_> {
+    fn is_enabled(&self, feature: u8) -> bool {
+        self.features & feature == feature
+    }
+
+    fn search(&mut self, board: &mut Board) -> SearchResult {
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as i32 - 1);
+        let multi_pv = self.limits.multi_pv.clamp(1, self.root_moves.len().max(1));
+
+        let mut result = SearchResult {
+            best_move: self.root_moves.first().copied(),
+            score: 0,
+            depth: 0,
+            pv: Vec::new(),
+            lines: Vec::new(),
+            stats: self.stats,
+            elapsed: Duration::ZERO,
+        };
+
+        // Helpers start at staggered depths so the threads do not all search the same tree
+        let start_depth = 1 + (self.id % 2) as i32;
+
+        for depth in start_depth..=max_depth {
+            let lines = self.search_lines(board, depth, multi_pv);
+
+            // A partial iteration is only trusted if it has already found a move
+            let Some(best_line) = lines.first() else {
+                break;
+            };
+
+            if self.stopped {
+                // Only the first line is complete enough to beat the previous iteration
+                let mut previous_lines = std::mem::take(&mut result.lines);
+                previous_lines.retain(|line| line.pv.first() != best_line.pv.first());
+
+                result.lines = vec![best_line.clone()];
+                result.lines.extend(previous_lines);
+                result.lines.truncate(multi_pv);
+            } else {
+                self.report_iteration(board, depth, &lines);
+                result.lines = lines;
+                result.depth = depth;
+            }
+
+            result.pv = result.lines[0].pv.clone();
+            result.best_move = result.pv.first().copied().or(result.best_move);
+            result.score = result.lines[0].score;
+
+            if self.stopped || self.out_of_time() {
+                break;
+            }
+        }
+
+        result.stats = self.stats;
+        result.elapsed = self.start_time.elapsed();
+
+        result
+    }
+
+    fn report_iteration(&mut self, board: &Board, depth: i32, lines: &[PvLine]) {
+        let Some(info_callback) = self.info_callback else {
+            return;
+        };
+
+        self.flush_nodes();
+        let nodes = self.shared_nodes.load(Ordering::Relaxed);
+        let time = self.start_time.elapsed();
+        let nps = (nodes as u128 * 1000 / time.as_millis().max(1)) as u64;
+        let hashfull = self.tt.hashfull();
+
+        for (index, line) in lines.iter().enumerate() {
+            let info = IterationInfo {
+                depth,
+                seldepth: self.stats.seldepth,
+                multi_pv: index + 1,
+                score: line.score,
+                nodes,
+                nps,
+                hashfull,
+                tb_hits: self.stats.tb_hits,
+                time,
+                pv: line.pv.clone(),
+            };
+
+            info_callback(board, &SearchInfo::Iteration(info));
+        }
+    }
+
+    fn flush_nodes(&mut self) {
+        self.shared_nodes
+            .fetch_add(self.stats.nodes - self.flushed_nodes, Ordering::Relaxed);
+        self.flushed_nodes = self.stats.nodes;
+    }
+
+    /// Searches the root `multi_pv` times, excluding the moves of the lines
+    /// already found, and returns the lines from best to worst.
+    fn search_lines(&mut self, board: &mut Board, depth: i32, multi_pv: usize) -> Vec<PvLine> {
+        let mut lines = Vec::with_capacity(multi_pv);
+        self.root_excluded_moves.clear();
+
+        for _ in 0..multi_pv {
+            let score = self.negamax(board, depth, -INFINITY, INFINITY, 0);
+
+            if self.pv_length[0] == 0 {
+                break;
+            }
+
+            let pv = self.pv_table[0][..self.pv_length[0]].to_vec();
+
+            // A stopped root returns nothing meaningful, but its PV move was
+            // searched to the end. Only the first line can still beat the
+            // previous iteration, later ones are left out of the sort.
+            if self.stopped {
+                if lines.is_empty() {
+                    lines.push(PvLine {
+                        score: self.root_best_score,
+                        pv,
+                    });
+                }
+                break;
+            }
+
+            self.root_excluded_moves.push(pv[0]);
+            lines.push(PvLine { score, pv });
+        }
+
+        // Later lines can outscore earlier ones when the search is unstable
+        lines.sort_by_key(|line| -line.score);
+
+        lines
+    }
+
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        mut depth: i32,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+    ) -> i32 {
+        self.pv_length[ply] = 0;
+
+        if self.should_stop() {
+            return 0;
+        }
+
+        let key = board.hash();
+        let excluded_move = self.excluded_moves[ply];
+
+        if ply > 0 && (self.is_draw(board, key) || kpk::probe(board) == Some(false)) {
+            return 0;
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return relative_evaluation(board);
+        }
+
+        let in_check = board.is_in_check();
+        if in_check && self.is_enabled(SearchFeature::CheckExtensions) && excluded_move == 0 {
+            depth += 1;
+            self.stats.check_extensions += 1;
+        }
+
+        if depth <= 0 {
+            return self.quiescence(board, alpha, beta, ply);
+        }
+
+        self.stats.nodes += 1;
+
+        let tt_entry = self.tt.probe(key);
+        let tt_move = tt_entry.map_or(0, |entry| entry.best_move);
+
+        if let Some(entry) = tt_entry {
+            if ply > 0 && excluded_move == 0 && entry.depth >= depth {
+                let score = score_from_tt(entry.score, ply);
+                let cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+
+                if cutoff {
+                    self.stats.tt_cutoffs += 1;
+                    return score;
+                }
+            }
+        }
+
+        // Tablebases are only trusted right after a capture or pawn move, as
+        // they ignore the moves already played towards the fifty-move rule
+        if ply > 0 && excluded_move == 0 && board.get_halfmove_clock() == 0 {
+            if let Some(wdl) = syzygy::probe_wdl(board) {
+                self.stats.tb_hits += 1;
+
+                let (score, bound) = match wdl {
+                    Wdl::Win => (TB_WIN - ply as i32, Bound::Lower),
+                    Wdl::Loss => (-TB_WIN + ply as i32, Bound::Upper),
+                    _ => (0, Bound::Exact),
+                };
+                let cutoff = match bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+
+                if cutoff {
+                    let depth = (depth + TB_DEPTH_BONUS).min(MAX_PLY as i32 - 1);
+                    self.tt.store(key, 0, score_to_tt(score, ply), depth, bound);
+                    return score;
+                }
+            }
+        }
+
+        if ply > 0 && excluded_move == 0 {
+            if let Some(score) = tablebase::probe(board)
+                .and_then(|dtm| dtm_score(dtm, board.get_halfmove_clock(), ply))
+            {
+                self.stats.tb_hits += 1;
+                return score;
+            }
+        }
+
+        let mut moves = board.generate_legal_moves();
+        if moves.is_empty() {
+            return if in_check {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+        self.order_moves(board, &mut moves, tt_move, ply);
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = 0;
+        let mut searched = 0;
+
+        for v_move in moves {
+            if v_move == excluded_move
+                || (ply == 0
+                    && (self.root_excluded_moves.contains(&v_move)
+                        || !self.root_moves.contains(&v_move)))
+            {
+                continue;
+            }
+
+            if ply == 0 && excluded_move == 0 {
+                self.report_current_move(board, depth, v_move, searched + 1);
+            }
+
+            let mut extension = 0;
+            if v_move == tt_move && ply > 0 && self.is_singular(board, tt_entry, depth, ply) {
+                extension = 1;
+                self.stats.singular_extensions += 1;
+            }
+
+            let is_capture = board.is_capture(v_move);
+
+            board.make_move(v_move);
+            self.hash_history.push(key);
+
+            let new_depth = depth - 1 + extension;
+            let score = if searched == 0 {
+                -self.negamax(board, new_depth, -beta, -alpha, ply + 1)
+            } else {
+                // Principal variation search: prove the move is worse with a null window
+                let score = -self.negamax(board, new_depth, -alpha - 1, -alpha, ply + 1);
+                if score > alpha && score < beta {
+                    -self.negamax(board, new_depth, -beta, -alpha, ply + 1)
+                } else {
+                    score
+                }
+            };
+
+            self.hash_history.pop();
+            let _ = board.undo_move();
+            searched += 1;
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = v_move;
+            }
+
+            if score > alpha {
+                alpha = score;
+                self.update_pv(v_move, ply);
+
+                if ply == 0 {
+                    self.root_best_score = score;
+                }
+            }
+
+            if score >= beta {
+                if !is_capture && !Move::is_promotion(v_move) {
+                    self.store_killer(v_move, ply);
+                    let departure = Move::departure_square(v_move) as usize;
+                    let target = Move::target_square(v_move) as usize;
+                    self.history[departure][target] += depth * depth;
+                }
+                break;
+            }
+        }
+
+        // Every move was excluded, so this singular verification found no alternative
+        if searched == 0 {
+            return alpha;
+        }
+
+        // Scores with excluded moves are not the position's true score
+        if excluded_move == 0 && (ply > 0 || self.root_excluded_moves.is_empty()) {
+            let bound = if best_score >= beta {
+                Bound::Lower
+            } else if best_score > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+
+            self.tt
+                .store(key, best_move, score_to_tt(best_score, ply), depth, bound);
+        }
+
+        best_score
+    }
+
+    /// Checks whether the transposition table move beats every alternative by a
+    /// margin, by searching the position again at reduced depth without it.
+    fn is_singular(
+        &mut self,
+        board: &mut Board,
+        tt_entry: Option<crate::tt::TtEntry>,
+        depth: i32,
+        ply: usize,
+    ) -> bool {
+        if !self.is_enabled(SearchFeature::SingularExtensions)
+            || depth < SINGULAR_MIN_DEPTH
+            || self.excluded_moves[ply] != 0
+        {
+            return false;
+        }
+
+        let Some(entry) = tt_entry else {
+            return false;
+        };
+
+        // Mates and tablebase wins are exact, searching below them tells nothing
+        if entry.bound == Bound::Upper
+            || entry.depth < depth - SINGULAR_TT_DEPTH_MARGIN
+            || entry.score.abs() >= TB_WIN_BOUND
+        {
+            return false;
+        }
+
+        let singular_beta = entry.score - 2 * depth;
+
+        self.stats.singular_searches += 1;
+        self.excluded_moves[ply] = entry.best_move;
+        let score = self.negamax(
+            board,
+            (depth - 1) / 2,
+            singular_beta - 1,
+            singular_beta,
+            ply,
+        );
+        self.excluded_moves[ply] = 0;
+
+        !self.stopped && score < singular_beta
+    }
+
+    fn quiescence(&mut self, board: &mut Board, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.stats.nodes += 1;
+        self.stats.quiescence_nodes += 1;
+        self.stats.seldepth = self.stats.seldepth.max(ply);
+        self.pv_length[ply] = 0;
+
+        if self.should_stop() {
+            return 0;
+        }
+
+        let stand_pat = relative_evaluation(board);
+        if stand_pat >= beta || ply >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let colour = board.get_colour_to_move();
+        let mut moves = board.generate_moves();
+        moves.retain(|&v_move| board.is_capture(v_move) || Move::is_promotion(v_move));
+        self.order_moves(board, &mut moves, 0, ply);
+
+        for v_move in moves {
+            board.make_move(v_move);
+            if board.is_colour_in_check(colour) {
+                let _ = board.undo_move();
+                continue;
+            }
+
+            let score = -self.quiescence(board, -beta, -alpha, ply + 1);
+            let _ = board.undo_move();
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score >= beta {
+                return score;
+            }
+
+            if score > alpha {
+                alpha = score;
+                self.update_pv(v_move, ply);
+            }
+        }
+
+        alpha
+    }
+
+    fn report_current_move(&self, board: &Board, depth: i32, v_move: u16, number: usize) {
+        let Some(info_callback) = self.info_callback else {
+            return;
+        };
+
+        if self.start_time.elapsed() >= CURRENT_MOVE_REPORT_DELAY {
+            let info = SearchInfo::CurrentMove {
+                depth,
+                v_move,
+                number,
+            };
+
+            info_callback(board, &info);
+        }
+    }
+
+    fn order_moves(&self, board: &Board, moves: &mut [u16], tt_move: u16, ply: usize) {
+        moves.sort_by_cached_key(|&v_move| -self.move_score(board, v_move, tt_move, ply));
+    }
+
+    fn move_score(&self, board: &Board, v_move: u16, tt_move: u16, ply: usize) -> i32 {
+        if v_move == tt_move {
+            return 1_000_000;
+        }
+
+        if board.is_capture(v_move) {
+            // Most valuable victim, least valuable attacker
+            let victim = match Move::is_en_passant(v_move) {
+                true => Piece::PawnValue,
+                false => Piece::value(board.piece_at(Move::target_square(v_move))),
+            };
+            let attacker = Piece::value(board.piece_at(Move::departure_square(v_move)));
+
+            return 500_000 + victim * 10 - attacker / 10;
+        }
+
+        if Move::is_promotion(v_move) {
+            return 400_000 + (v_move >> 13) as i32;
+        }
+
+        if self.killers[ply].contains(&v_move) {
+            return 300_000;
+        }
+
+        if Move::is_castling(v_move) {
+            return 0;
+        }
+
+        let departure = Move::departure_square(v_move) as usize;
+        let target = Move::target_square(v_move) as usize;
+
+        self.history[departure][target]
+    }
+
+    fn store_killer(&mut self, v_move: u16, ply: usize) {
+        if self.killers[ply][0] != v_move {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = v_move;
+        }
+    }
+
+    fn update_pv(&mut self, v_move: u16, ply: usize) {
+        self.pv_table[ply][ply] = v_move;
+
+        let child_length = self.pv_length[ply + 1].max(ply + 1);
+        for next_ply in ply + 1..child_length {
+            self.pv_table[ply][next_ply] = self.pv_table[ply + 1][next_ply];
+        }
+
+        self.pv_length[ply] = child_length;
+    }
+
+    fn is_draw(&self, board: &Board, key: u64) -> bool {
+        if board.get_halfmove_clock() >= 100 {
+            return true;
+        }
+
+        let reversible_plies = board.get_halfmove_clock() as usize;
+        self.hash_history
+            .iter()
+            .rev()
+            .take(reversible_plies)
+            .any(|&hash| hash == key)
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped || self.stop.load(Ordering::Relaxed) {
+            self.stopped = true;
+            return true;
+        }
+
+        // Counted over every thread, the nodes of the others as last flushed
+        if let Some(nodes) = self.limits.nodes {
+            let searched =
+                self.shared_nodes.load(Ordering::Relaxed) + self.stats.nodes - self.flushed_nodes;
+            if searched >= nodes {
+                self.stopped = true;
+            }
+        }
+
+        if self.stats.nodes.is_multiple_of(1024) {
+            self.flush_nodes();
+
+            if self.out_of_time() {
+                self.stopped = true;
+            }
+        }
+
+        self.stopped
+    }
+
+    fn out_of_time(&mut self) -> bool {
+        if self.was_pondering {
+            if self.ponder.load(Ordering::Relaxed) {
+                return false;
+            }
+
+            // The opponent played the expected move, our own clock starts now
+            self.was_pondering = false;
+            self.clock_start = Instant::now();
+        }
+
+        self.limits
+            .movetime
+            .is_some_and(|movetime| self.clock_start.elapsed() >= movetime)
+    }
+}
+
+/// Hashes of the positions leading to the current one, oldest first.
+fn game_hashes(board: &Board) -> Vec<u64> {
+    let mut board = board.clone();
+    let mut hashes = Vec::new();
+
+    while board.undo_move().is_ok() {
+        hashes.push(board.hash());
+    }
+
+    hashes.reverse();
+    hashes
+}
+
+/// The static evaluation from the point of view of the side to move.
+pub fn relative_evaluation(board: &Board) -> i32 {
+    match board.get_colour_to_move() {
+        Colour::White => board.evaluate(),
+        Colour::Black => -board.evaluate(),
+    }
+}
+
+/// The score of mating in `plies` plies from the root, mates too deep for
+/// the search being reported as the deepest it knows.
+fn mate_in(plies: usize) -> i32 {
+    MATE_SCORE - plies.min(MAX_PLY) as i32
+}
+
+/// The score of a generated tablebase result, unless its mate would only
+/// land after the fifty-move rule has drawn the game.
+fn dtm_score(dtm: Dtm, halfmove_clock: u16, ply: usize) -> Option<i32> {
+    match dtm {
+        Dtm::Win(plies) | Dtm::Loss(plies) if halfmove_clock as u32 + plies >= 100 => None,
+        Dtm::Win(plies) => Some(mate_in(ply + plies as usize)),
+        Dtm::Loss(plies) => Some(-mate_in(ply + plies as usize)),
+        Dtm::Draw => Some(0),
+    }
+}
+
+/// Mate and tablebase scores are stored relative to the node rather than the root.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= TB_WIN_BOUND {
+        score + ply as i32
+    } else if score <= -TB_WIN_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= TB_WIN_BOUND {
+        score - ply as i32
+    } else if score <= -TB_WIN_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// White is a queen up.
+    const QUEEN_UP: &str = "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn search(fen: &str, limits: SearchLimits) -> SearchResult {
+        let mut board = Board::from_fen(fen).unwrap();
+
+        Searcher::new(16).search(&mut board, limits)
+    }
+
+    #[test]
+    fn node_limit_is_shared_by_the_threads() {
+        let mut board = Board::new();
+        let mut searcher = Searcher::new(16);
+        searcher.set_threads(4);
+
+        let nodes = 50_000;
+        let result = searcher.search(
+            &mut board,
+            SearchLimits {
+                nodes: Some(nodes),
+                ..Default::default()
+            },
+        );
+
+        // Each thread may overshoot by the nodes it has not flushed yet
+        assert!(
+            result.stats.nodes < nodes + 4 * 1024,
+            "{} nodes searched",
+            result.stats.nodes
+        );
+    }
+
+    #[test]
+    fn pawn_on_the_first_rank_is_searched() {
+        let result = search(
+            "4k3/8/8/8/8/8/8/P3K3 w - - 0 1",
+            SearchLimits {
+                depth: Some(2),
+                ..Default::default()
+            },
+        );
+
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn tablebase_wins_are_not_tested_for_singularity() {
+        let searcher = Searcher::new(1);
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let key = board.hash();
+        let v_move = board.generate_legal_moves()[0];
+        let mut worker = searcher.worker(
+            0,
+            SearchLimits::default(),
+            Instant::now(),
+            Vec::new(),
+            Vec::new(),
+        );
+
+        for score in [TB_WIN - 3, -TB_WIN + 3, mate_in(5)] {
+            searcher.tt.store(key, v_move, score, 10, Bound::Lower);
+            assert!(!worker.is_singular(&mut board, searcher.tt.probe(key), 8, 1));
+        }
+        assert_eq!(worker.stats.singular_searches, 0);
+
+        searcher.tt.store(key, v_move, 50, 10, Bound::Lower);
+        worker.is_singular(&mut board, searcher.tt.probe(key), 8, 1);
+        assert_eq!(worker.stats.singular_searches, 1);
+    }
+
+    #[test]
+    fn mate_scores_give_the_moves_to_mate() {
+        assert_eq!(score_to_mate(MATE_SCORE - 1), Some(1));
+        assert_eq!(score_to_mate(MATE_SCORE - 4), Some(2));
+        assert_eq!(score_to_mate(-MATE_SCORE + 2), Some(-1));
+        assert_eq!(score_to_mate(-MATE_SCORE + 5), Some(-3));
+
+        assert_eq!(score_to_mate(TB_WIN), None);
+        assert_eq!(score_to_mate(-TB_WIN), None);
+        assert_eq!(score_to_mate(250), None);
+    }
+
+    #[test]
+    fn tablebase_mates_past_the_fifty_move_rule_are_not_trusted() {
+        assert_eq!(dtm_score(Dtm::Win(19), 80, 3), Some(mate_in(22)));
+        assert_eq!(dtm_score(Dtm::Loss(18), 81, 2), Some(-mate_in(20)));
+        assert_eq!(dtm_score(Dtm::Win(19), 81, 3), None);
+        assert_eq!(dtm_score(Dtm::Loss(20), 90, 2), None);
+        assert_eq!(dtm_score(Dtm::Draw, 99, 2), Some(0));
+    }
+
+    #[test]
+    fn stopped_search_keeps_a_searched_score() {
+        for nodes in (1..=20).map(|n| n * 500) {
+            let result = search(
+                QUEEN_UP,
+                SearchLimits {
+                    nodes: Some(nodes),
+                    ..Default::default()
+                },
+            );
+
+            assert!(
+                result.score > 500,
+                "score {} at {nodes} nodes",
+                result.score
+            );
+            assert_eq!(result.lines[0].score, result.score);
+        }
+    }
+
+    /// The lines of a fresh search of `board` to `depth`, stopped after `nodes`.
+    fn search_lines(
+        board: &mut Board,
+        depth: i32,
+        multi_pv: usize,
+        nodes: Option<u64>,
+    ) -> (Vec<PvLine>, bool) {
+        let searcher = Searcher::new(16);
+        let limits = SearchLimits {
+            nodes,
+            ..Default::default()
+        };
+        let root_moves = board.generate_legal_moves();
+        let mut worker = searcher.worker(0, limits, Instant::now(), Vec::new(), root_moves);
+
+        let lines = worker.search_lines(board, depth, multi_pv);
+        (lines, worker.stopped)
+    }
+
+    #[test]
+    fn stopped_multi_pv_iteration_keeps_only_complete_lines() {
+        let mut board = Board::from_fen(QUEEN_UP).unwrap();
+        let (complete_lines, _) = search_lines(&mut board, 4, 3, None);
+
+        // Stops while searching the second and third lines
+        for nodes in (5_000..6_500).step_by(25) {
+            let (lines, stopped) = search_lines(&mut board, 4, 3, Some(nodes));
+
+            if stopped {
+                assert!(lines.len() < 3, "partial third line at {nodes} nodes");
+            }
+
+            // A lone line may be the partial first one, whose score is only a bound
+            if stopped && lines.len() > 1 {
+                for line in lines {
+                    assert!(
+                        complete_lines
+                            .iter()
+                            .any(|complete| complete.pv[0] == line.pv[0]
+                                && complete.score == line.score),
+                        "partial line scored {} at {nodes} nodes",
+                        line.score
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1396 @@
+//! Syzygy tablebase probing.
+//!
+//! Tables are found by material, like `KQvKR.rtbw`, in the directories given
+//! to `init`, and each file is read whole into memory the first time it is
+//! probed. WDL tables give the result of a position under the fifty-move
+//! rule and are probed during the search. DTZ tables give the distance to
+//! the next capture or pawn move, the zeroing move, and rank the root moves
+//! so that a won position is converted before the fifty-move rule draws it.
+//! Positions with castling rights are not in the tables, and those with an
+//! en passant capture are resolved by playing the captures first.
+//!
+//! The file layout follows the format of the original generator: a header
+//! describing how the pieces are ordered and grouped into an index, then
+//! for every table the Huffman code of its symbols, a sparse index into the
+//! blocks, the block lengths and the compressed blocks themselves.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock, RwLock,
+    },
+};
+
+use lazy_static::lazy_static;
+
+use crate::{bitboard::king_attacks, board::Board, colour::Colour, moves::Move, piece::Piece};
+
+/// The most pieces any Syzygy table covers.
+pub const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: u32 = 0x5D23_E871;
+const DTZ_MAGIC: u32 = 0xA50C_66D7;
+const WDL_EXTENSION: &str = "rtbw";
+const DTZ_EXTENSION: &str = "rtbz";
+
+/// More than any distance to zeroing, to rank root moves.
+const MAX_DTZ: i32 = 1 << 18;
+
+/// Piece letters of table names after the king, in the order they appear.
+pub const PIECE_LETTERS: [(char, u8); 5] = [
+    ('Q', Piece::Queen),
+    ('R', Piece::Rook),
+    ('B', Piece::Bishop),
+    ('N', Piece::Knight),
+    ('P', Piece::Pawn),
+];
+
+/// Results stored in WDL tables, from the side to move's point of view.
+/// Cursed wins and blessed losses are drawn by the fifty-move rule.
+#[non_exhaustive]
+pub struct Wdl;
+
+#[allow(non_upper_case_globals)]
+impl Wdl {
+    pub const Loss: i32 = -2;
+    pub const BlessedLoss: i32 = -1;
+    pub const Draw: i32 = 0;
+    pub const CursedWin: i32 = 1;
+    pub const Win: i32 = 2;
+}
+
+#[non_exhaustive]
+struct TableFlag;
+
+#[allow(non_upper_case_globals)]
+impl TableFlag {
+    /// Set when a DTZ table stores black to move rather than white.
+    const BlackToMove: u8 = 1;
+    const Mapped: u8 = 2;
+    const WinPlies: u8 = 4;
+    const LossPlies: u8 = 8;
+    /// The DTZ map holds 16-bit values.
+    const Wide: u8 = 16;
+    const SingleValue: u8 = 128;
+}
+
+lazy_static! {
+    static ref TABLES: RwLock<HashMap<String, TableFiles>> = RwLock::new(HashMap::new());
+    static ref INDICES: Indices = Indices::new();
+}
+
+/// Pieces in the largest table found, 0 without tables.
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+/// Positions with more pieces than this are not probed during the search.
+static PROBE_LIMIT: AtomicUsize = AtomicUsize::new(MAX_PIECES);
+
+/// The files of one material combination, loaded on first use.
+#[derive(Default)]
+struct TableFiles {
+    wdl_path: Option<PathBuf>,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+/// Looks for tables in `paths`, directories separated as in the `PATH`
+/// environment variable, replacing the tables found before. Returns the
+/// number of WDL tables found.
+pub fn init(paths: &str) -> usize {
+    let mut tables = HashMap::<String, TableFiles>::new();
+
+    for directory in env::split_paths(paths) {
+        let Ok(entries) = fs::read_dir(&directory) else {
+            continue;
+        };
+
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            let (Some(name), Some(extension)) = (
+                path.file_stem().and_then(|stem| stem.to_str()),
+                path.extension().and_then(|extension| extension.to_str()),
+            ) else {
+                continue;
+            };
+
+            if Material::from_name(name).is_none() {
+                continue;
+            }
+
+            let files = tables.entry(name.to_string()).or_default();
+            match extension {
+                WDL_EXTENSION => files.wdl_path = Some(path),
+                DTZ_EXTENSION => files.dtz_path = Some(path),
+                _ => {}
+            }
+        }
+    }
+
+    tables.retain(|_, files| files.wdl_path.is_some() || files.dtz_path.is_some());
+
+    let wdl_names = tables
+        .iter()
+        .filter(|(_, files)| files.wdl_path.is_some())
+        .map(|(name, _)| name);
+    let largest = wdl_names
+        .clone()
+        .map(|name| name.len() - 1)
+        .max()
+        .unwrap_or(0);
+    let count = wdl_names.count();
+
+    *TABLES.write().unwrap() = tables;
+    LARGEST.store(largest, Ordering::Relaxed);
+
+    count
+}
+
+pub fn set_probe_limit(limit: usize) {
+    PROBE_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// The most pieces of a position probed during the search, 0 without tables.
+pub fn probe_limit() -> usize {
+    LARGEST
+        .load(Ordering::Relaxed)
+        .min(PROBE_LIMIT.load(Ordering::Relaxed))
+}
+
+/// The result of `board` for the side to move if it is in the tables and
+/// has no more pieces than the probe limit.
+pub fn probe_wdl(board: &mut Board) -> Option<i32> {
+    let limit = probe_limit();
+    if limit == 0 || board.get_castling_rights() != 0 || piece_count(board) > limit {
+        return None;
+    }
+
+    search(board, false).map(|(wdl, _)| wdl)
+}
+
+/// The distance to zeroing in plies of `board`, positive when the side to
+/// move wins, negative when it loses and 0 for a draw. Cursed wins and
+/// blessed losses are 100 plies further away.
+pub fn probe_dtz(board: &mut Board) -> Option<i32> {
+    let (wdl, zeroing) = search(board, true)?;
+
+    // DTZ tables do not store draws
+    if wdl == Wdl::Draw {
+        return Some(0);
+    }
+
+    // The stored value is meaningless when the best move zeroes
+    if zeroing {
+        return Some(dtz_before_zeroing(wdl));
+    }
+
+    let value = match probe_table(board, true, wdl)? {
+        Lookup::Value(dtz) => {
+            let cursed = wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss;
+            return Some((dtz + 100 * i32::from(cursed)) * wdl.signum());
+        }
+        Lookup::OtherSide => i32::MAX,
+    };
+
+    // The table stores the other side to move, so look one ply ahead for
+    // the move keeping the result with the best distance
+    let mut best_dtz = value;
+    for v_move in board.generate_legal_moves() {
+        let zeroing = is_zeroing(board, v_move);
+
+        board.make_move(v_move);
+        let dtz = match zeroing {
+            true => search(board, false).map(|(wdl, _)| -dtz_before_zeroing(wdl)),
+            false => probe_dtz(board).map(|dtz| -dtz),
+        };
+        let mates = board.is_in_check() && board.generate_legal_moves().is_empty();
+        let _ = board.undo_move();
+
+        let mut dtz = dtz?;
+        if dtz == 1 && mates {
+            best_dtz = 1;
+        }
+
+        // Zeroing moves already count the move itself
+        if !zeroing {
+            dtz += dtz.signum();
+        }
+
+        if dtz < best_dtz && dtz.signum() == wdl.signum() {
+            best_dtz = dtz;
+        }
+    }
+
+    // Without legal moves the side to move is mated
+    Some(if best_dtz == i32::MAX { -1 } else { best_dtz })
+}
+
+/// The legal moves of `board` that keep its result with the shortest
+/// distance to zeroing when winning, or the longest when losing, if it is
+/// in the tables. `history` holds the hashes of the positions before it,
+/// oldest first, to tell repetitions apart.
+pub fn root_moves(board: &mut Board, history: &[u64]) -> Option<Vec<u16>> {
+    let largest = LARGEST.load(Ordering::Relaxed);
+    if largest == 0 || board.get_castling_rights() != 0 || piece_count(board) > largest {
+        return None;
+    }
+
+    let halfmove_clock = board.get_halfmove_clock() as usize;
+    let mut reversible = history[history.len().saturating_sub(halfmove_clock)..].to_vec();
+    reversible.push(board.hash());
+
+    let repeated = reversible
+        .iter()
+        .enumerate()
+        .any(|(index, hash)| reversible[index + 1..].contains(hash));
+
+    let mut ranked = Vec::new();
+    for v_move in board.generate_legal_moves() {
+        board.make_move(v_move);
+
+        let dtz = if board.get_halfmove_clock() == 0 {
+            search(board, false).map(|(wdl, _)| dtz_before_zeroing(-wdl))
+        } else if board.get_halfmove_clock() >= 100 || reversible.contains(&board.hash()) {
+            Some(0)
+        } else {
+            probe_dtz(board).map(|dtz| -dtz + (-dtz).signum())
+        };
+        let mates = board.is_in_check() && board.generate_legal_moves().is_empty();
+        let _ = board.undo_move();
+
+        let dtz = match dtz? {
+            2 if mates => 1,
+            dtz => dtz,
+        };
+
+        // Wins are ranked by how soon the pawn moves or captures come, and
+        // so are losses, the other way round, unless the fifty-move rule
+        // may step in first
+        let rank = if dtz > 0 {
+            let certain = dtz + halfmove_clock as i32 <= 99 && !repeated;
+            MAX_DTZ - dtz - if certain { 0 } else { halfmove_clock as i32 }
+        } else if dtz < 0 {
+            let certain = -dtz * 2 + (halfmove_clock as i32) < 100;
+            -MAX_DTZ - dtz + if certain { 0 } else { halfmove_clock as i32 }
+        } else {
+            0
+        };
+
+        ranked.push((v_move, rank));
+    }
+
+    let best_rank = ranked.iter().map(|&(_, rank)| rank).max()?;
+
+    Some(
+        ranked
+            .into_iter()
+            .filter(|&(_, rank)| rank == best_rank)
+            .map(|(v_move, _)| v_move)
+            .collect(),
+    )
+}
+
+fn piece_count(board: &Board) -> usize {
+    board
+        .pieces()
+        .iter()
+        .filter(|&&piece| piece != Piece::None)
+        .count()
+}
+
+fn is_zeroing(board: &Board, v_move: u16) -> bool {
+    board.is_capture(v_move)
+        || (!Move::is_castling(v_move)
+            && Piece::is_type(board.piece_at(Move::departure_square(v_move)), Piece::Pawn))
+}
+
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        _ => 0,
+    }
+}
+
+/// The result of `board` after trying the captures, and pawn moves too
+/// when `zeroing_moves` is set, as the tables do not account for en
+/// passant captures. The flag is set when the best move is one of those.
+fn search(board: &mut Board, zeroing_moves: bool) -> Option<(i32, bool)> {
+    let moves = board.generate_legal_moves();
+    let mut best = Wdl::Loss;
+    let mut searched = 0;
+
+    for &v_move in &moves {
+        if !(board.is_capture(v_move) || zeroing_moves && is_zeroing(board, v_move)) {
+            continue;
+        }
+
+        searched += 1;
+
+        board.make_move(v_move);
+        let result = search(board, false);
+        let _ = board.undo_move();
+
+        let value = -result?.0;
+        if value > best {
+            best = value;
+
+            if value >= Wdl::Win {
+                return Some((value, true));
+            }
+        }
+    }
+
+    // With every move searched the table is not needed, and could even be
+    // wrong when the only moves are en passant captures
+    let all_searched = searched > 0 && searched == moves.len();
+    let value = match all_searched {
+        true => best,
+        false => match probe_table(board, false, Wdl::Draw)? {
+            Lookup::Value(value) => value,
+            Lookup::OtherSide => return None,
+        },
+    };
+
+    if best >= value {
+        return Some((best, best > Wdl::Draw || all_searched));
+    }
+
+    Some((value, false))
+}
+
+enum Lookup {
+    Value(i32),
+    /// The DTZ table only stores the other side to move.
+    OtherSide,
+}
+
+/// Looks `board` up in its WDL table, or its DTZ table when `dtz` is set,
+/// in which case `wdl` is the position's result.
+fn probe_table(board: &Board, dtz: bool, wdl: i32) -> Option<Lookup> {
+    let white = Material::of(board, Colour::White);
+    let black = Material::of(board, Colour::Black);
+
+    // Kings alone are not worth a table
+    if white.count == 1 && black.count == 1 {
+        return Some(Lookup::Value(Wdl::Draw));
+    }
+
+    let tables = TABLES.read().unwrap();
+    let (files, black_stronger) = match tables.get(&format!("{}v{}", white.name, black.name)) {
+        Some(files) => (files, false),
+        None => (tables.get(&format!("{}v{}", black.name, white.name))?, true),
+    };
+
+    let (path, table) = match dtz {
+        true => (files.dtz_path.as_ref()?, &files.dtz),
+        false => (files.wdl_path.as_ref()?, &files.wdl),
+    };
+    let table = table.get_or_init(|| Table::load(path, dtz)).as_ref()?;
+
+    table.probe(board, black_stronger, wdl)
+}
+
+/// The pieces of one side, as in a table name.
+struct Material {
+    name: String,
+    count: usize,
+}
+
+impl Material {
+    fn of(board: &Board, colour: bool) -> Self {
+        let mut counts = [0; 7];
+        for &piece in board.pieces() {
+            if piece != Piece::None && Piece::is_colour_bool(piece, colour) {
+                counts[Piece::piece_type(piece) as usize] += 1;
+            }
+        }
+
+        let mut name = String::from("K");
+        for (letter, piece_type) in PIECE_LETTERS {
+            (0..counts[piece_type as usize]).for_each(|_| name.push(letter));
+        }
+
+        Self {
+            count: name.len(),
+            name,
+        }
+    }
+
+    /// White's and black's piece counts by type from a name like `KRPvKR`,
+    /// if it is one.
+    fn from_name(name: &str) -> Option<[[usize; 7]; 2]> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0; 7]; 2];
+
+        for (side, pieces) in [white, black].into_iter().enumerate() {
+            let mut letters = pieces.chars();
+            if letters.next()? != 'K' {
+                return None;
+            }
+
+            for letter in letters {
+                let (_, piece_type) = PIECE_LETTERS.iter().find(|&&(l, _)| l == letter)?;
+                counts[side][*piece_type as usize] += 1;
+            }
+        }
+
+        let pieces = name.len() - 1;
+        (pieces <= MAX_PIECES).then_some(counts)
+    }
+}
+
+/// Encoding tables shared by every table.
+struct Indices {
+    /// `binomial[k][n]` is the number of ways to pick `k` of `n` squares.
+    binomial: [[u64; 64]; MAX_PIECES],
+    /// Squares a2 to h7 numbered so that the leading pawn, the one nearest
+    /// the edge and lowest on its file, has the highest number.
+    map_pawns: [usize; 64],
+    /// Index of the leading pawn by number of leading pawns and square.
+    lead_pawn_index: [[u64; 64]; MAX_PIECES],
+    /// Positions of the leading pawns by their number and file.
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+    /// Squares below the a1-h8 diagonal numbered from 0.
+    map_b1h1h7: [usize; 64],
+    /// The a1-d1-d4 triangle numbered from 0, the diagonal last.
+    map_a1d1d4: [usize; 64],
+    /// The 462 placements of two kings, the first in the triangle.
+    map_kk: [[usize; 64]; 10],
+}
+
+impl Indices {
+    #[allow(clippy::needless_range_loop)]
+    fn new() -> Self {
+        let mut binomial = [[0; 64]; MAX_PIECES];
+        for n in 0..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                binomial[k][n] = match (k, n) {
+                    (0, _) => 1,
+                    _ if k == n => 1,
+                    _ => binomial[k - 1][n - 1] + binomial[k][n - 1],
+                };
+            }
+        }
+
+        let mut map_pawns = [0; 64];
+        let mut lead_pawn_index = [[0; 64]; MAX_PIECES];
+        let mut lead_pawns_size = [[0; 4]; MAX_PIECES];
+        let mut available: usize = 47;
+
+        for lead_pawns in 1..MAX_PIECES {
+            for file in 0..4 {
+                let mut index = 0;
+
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+
+                    if lead_pawns == 1 {
+                        map_pawns[square] = available;
+                        map_pawns[square ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+
+                    lead_pawn_index[lead_pawns][square] = index;
+                    index += binomial[lead_pawns - 1][map_pawns[square]];
+                }
+
+                lead_pawns_size[lead_pawns][file] = index;
+            }
+        }
+
+        let mut map_b1h1h7 = [0; 64];
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut map_a1d1d4 = [0; 64];
+        let triangle = (0..28).filter(|&square| square % 8 <= 3);
+        let below = triangle.clone().filter(|&square| off_diagonal(square) < 0);
+        let diagonal = triangle.filter(|&square| off_diagonal(square) == 0);
+        for (code, square) in below.chain(diagonal).enumerate() {
+            map_a1d1d4[square] = code;
+        }
+
+        let mut map_kk = [[0; 64]; 10];
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for index in 0..10 {
+            for first in 0..28 {
+                if map_a1d1d4[first] != index || first % 8 > 3 || (index == 0 && first != 1) {
+                    continue;
+                }
+
+                for second in 0..64 {
+                    if second == first || king_attacks(first) & (1 << second) != 0 {
+                        continue;
+                    }
+
+                    match (off_diagonal(first), off_diagonal(second)) {
+                        // The first king is on the diagonal, the second above it
+                        (0, second_offset) if second_offset > 0 => {}
+                        (0, 0) => both_on_diagonal.push((index, second)),
+                        _ => {
+                            map_kk[index][second] = code;
+                            code += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Both kings on the diagonal come last
+        for (index, second) in both_on_diagonal {
+            map_kk[index][second] = code;
+            code += 1;
+        }
+
+        Self {
+            binomial,
+            map_pawns,
+            lead_pawn_index,
+            lead_pawns_size,
+            map_b1h1h7,
+            map_a1d1d4,
+            map_kk,
+        }
+    }
+}
+
+/// Ranks above the a1-h8 diagonal minus files, negative below it.
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+fn flip_diagonal(square: usize) -> usize {
+    ((square >> 3) | (square << 3)) & 63
+}
+
+/// One table of a file: the positions of one side to move, and of one
+/// leading pawn file in pawn tables.
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_symbol_length: u8,
+    block_size: usize,
+    span: usize,
+    num_blocks: usize,
+    block_length_size: usize,
+    sparse_index_size: usize,
+    /// Offsets into the file of the table's parts.
+    lowest_symbol: usize,
+    btree: usize,
+    sparse_index: usize,
+    block_lengths: usize,
+    data: usize,
+    /// The lowest code of each symbol length, left aligned.
+    base: Vec<u64>,
+    /// Number of values, minus one, each symbol stands for.
+    symbol_lengths: Vec<u8>,
+    /// Table piece codes in index order.
+    pieces: [u8; MAX_PIECES],
+    /// Multiplier of each group's index.
+    group_index: [u64; MAX_PIECES + 1],
+    /// Pieces in each group, ending with 0.
+    group_length: [usize; MAX_PIECES + 1],
+    /// Start of the DTZ map of each result, in map entries.
+    map_index: [usize; 4],
+}
+
+struct Table {
+    data: Vec<u8>,
+    dtz: bool,
+    symmetric: bool,
+    has_pawns: bool,
+    /// Set when some piece other than a king is alone of its kind.
+    has_unique_pieces: bool,
+    both_sides_pawns: bool,
+    piece_count: usize,
+    /// By leading pawn file and side to move, one file for piece tables.
+    pairs: Vec<Vec<PairsData>>,
+    /// Offset of the DTZ map.
+    map: usize,
+}
+
+impl Table {
+    fn load(path: &PathBuf, dtz: bool) -> Option<Self> {
+        let name = path.file_stem()?.to_str()?;
+        let data = fs::read(path).ok()?;
+
+        Self::parse(data, name, dtz)
+    }
+
+    fn parse(data: Vec<u8>, name: &str, dtz: bool) -> Option<Self> {
+        let counts = Material::from_name(name)?;
+        let (white, black) = name.split_once('v')?;
+
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if data.len() < 5 || read_u32_le(&data, 0) != Some(magic) {
+            return None;
+        }
+
+        let pawns = [
+            counts[0][Piece::Pawn as usize],
+            counts[1][Piece::Pawn as usize],
+        ];
+        let mut table = Self {
+            dtz,
+            symmetric: white == black,
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces: counts
+                .iter()
+                .any(|side| side[Piece::Pawn as usize..Piece::King as usize].contains(&1)),
+            both_sides_pawns: pawns[0] > 0 && pawns[1] > 0,
+            piece_count: name.len() - 1,
+            pairs: Vec::new(),
+            map: 0,
+            data: Vec::new(),
+        };
+
+        let split = !dtz && data[4] & 1 != 0;
+        if (data[4] & 2 != 0) != table.has_pawns || split == table.symmetric && !dtz {
+            return None;
+        }
+
+        let sides = if split { 2 } else { 1 };
+        let files = if table.has_pawns { 4 } else { 1 };
+        table.pairs = vec![vec![PairsData::default(); sides]; files];
+
+        let both_pawns = usize::from(table.both_sides_pawns);
+        let mut offset = 5;
+
+        for file in 0..files {
+            data.get(offset + both_pawns + table.piece_count)?;
+            let order_byte = |shift: u32| -> [u8; 2] {
+                let second = match table.both_sides_pawns {
+                    true => (data[offset + 1] >> shift) & 0xF,
+                    false => 0xF,
+                };
+                [(data[offset] >> shift) & 0xF, second]
+            };
+            let orders = [order_byte(0), order_byte(4)];
+            offset += 1 + both_pawns;
+
+            for piece in 0..table.piece_count {
+                for (side, pairs) in table.pairs[file].iter_mut().enumerate() {
+                    pairs.pieces[piece] = (data[offset] >> (4 * side)) & 0xF;
+                }
+                offset += 1;
+            }
+
+            for (side, &order) in orders.iter().enumerate().take(sides) {
+                let mut pairs = std::mem::take(&mut table.pairs[file][side]);
+                table.set_groups(&mut pairs, order, file);
+                table.pairs[file][side] = pairs;
+            }
+        }
+
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = set_sizes(&mut table.pairs[file][side], &data, offset)?;
+            }
+        }
+
+        if dtz {
+            table.map = offset;
+            offset = table.set_dtz_map(&data, offset)?;
+        }
+
+        for file in 0..files {
+            for pairs in &mut table.pairs[file] {
+                pairs.sparse_index = offset;
+                offset += pairs.sparse_index_size * 6;
+            }
+        }
+
+        for file in 0..files {
+            for pairs in &mut table.pairs[file] {
+                pairs.block_lengths = offset;
+                offset += pairs.block_length_size * 2;
+            }
+        }
+
+        for file in 0..files {
+            for pairs in &mut table.pairs[file] {
+                offset = (offset + 0x3F) & !0x3F;
+                pairs.data = offset;
+                offset += pairs.num_blocks * pairs.block_size;
+            }
+        }
+
+        if offset > data.len() {
+            return None;
+        }
+
+        table.data = data;
+        Some(table)
+    }
+
+    /// Splits the pieces into groups encoded together and sets the
+    /// multiplier of each group's index, the groups being combined in the
+    /// order the file gives.
+    fn set_groups(&self, pairs: &mut PairsData, order: [u8; 2], file: usize) {
+        let indices = &*INDICES;
+
+        // The first group always holds the leading pieces, like both kings
+        // and a third unique piece
+        let mut first_length: i32 = match (self.has_pawns, self.has_unique_pieces) {
+            (true, _) => 0,
+            (false, true) => 3,
+            (false, false) => 2,
+        };
+
+        let mut groups = 0;
+        pairs.group_length[0] = 1;
+        for piece in 1..self.piece_count {
+            first_length -= 1;
+            if first_length > 0 || pairs.pieces[piece] == pairs.pieces[piece - 1] {
+                pairs.group_length[groups] += 1;
+            } else {
+                groups += 1;
+                pairs.group_length[groups] = 1;
+            }
+        }
+        groups += 1;
+        pairs.group_length[groups] = 0;
+
+        let mut next = if self.both_sides_pawns { 2 } else { 1 };
+        let mut free_squares = 64
+            - pairs.group_length[0]
+            - if self.both_sides_pawns {
+                pairs.group_length[1]
+            } else {
+                0
+            };
+        let mut index = 1;
+
+        let mut k = 0;
+        while next < groups || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                pairs.group_index[0] = index;
+                index *= match (self.has_pawns, self.has_unique_pieces) {
+                    (true, _) => indices.lead_pawns_size[pairs.group_length[0]][file],
+                    (false, true) => 31_332,
+                    (false, false) => 462,
+                };
+            } else if k == order[1] as usize {
+                pairs.group_index[1] = index;
+                index *= indices.binomial[pairs.group_length[1]][48 - pairs.group_length[0]];
+            } else {
+                pairs.group_index[next] = index;
+                index *= indices.binomial[pairs.group_length[next]][free_squares];
+                free_squares -= pairs.group_length[next];
+                next += 1;
+            }
+
+            k += 1;
+        }
+
+        pairs.group_index[groups] = index;
+    }
+
+    /// Reads where the DTZ values of each result start, for the tables that
+    /// map their stored values to distances.
+    fn set_dtz_map(&mut self, data: &[u8], mut offset: usize) -> Option<usize> {
+        for pairs in self.pairs.iter_mut().map(|sides| &mut sides[0]) {
+            if pairs.flags & TableFlag::Mapped == 0 {
+                continue;
+            }
+
+            if pairs.flags & TableFlag::Wide != 0 {
+                offset += offset & 1;
+                for index in &mut pairs.map_index {
+                    *index = (offset - self.map) / 2 + 1;
+                    offset += 2 * read_u16_le(data, offset)? as usize + 2;
+                }
+            } else {
+                for index in &mut pairs.map_index {
+                    *index = offset - self.map + 1;
+                    offset += *data.get(offset)? as usize + 1;
+                }
+            }
+        }
+
+        Some(offset + (offset & 1))
+    }
+
+    /// The stored value of `board`, a WDL result or a DTZ distance in plies.
+    fn probe(&self, board: &Board, black_stronger: bool, wdl: i32) -> Option<Lookup> {
+        let indices = &*INDICES;
+        let colour_to_move = usize::from(board.get_colour_to_move() == Colour::Black);
+
+        // Tables are stored with white as the stronger side and, when both
+        // sides have the same pieces, with white to move, so flip the board
+        // when needed
+        let flip = black_stronger || (self.symmetric && colour_to_move == 1);
+        let flip_colour = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let side_to_move = colour_to_move ^ usize::from(flip);
+
+        let mut squares = [0; MAX_PIECES];
+        let mut pieces = [0; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0;
+        let mut lead_pawn_count = 0;
+        let mut file = 0;
+
+        if self.has_pawns {
+            // Leading pawns come first in every file's piece order
+            let lead_piece = self.pairs[0][0].pieces[0] ^ flip_colour;
+            let lead_colour = lead_piece & 8 == 0;
+
+            for (square, &piece) in board.pieces().iter().enumerate() {
+                if Piece::is_type(piece, Piece::Pawn) && Piece::is_colour_bool(piece, lead_colour) {
+                    lead_pawns |= 1u64 << square;
+                    squares[size] = square ^ flip_squares;
+                    size += 1;
+                }
+            }
+
+            lead_pawn_count = size;
+            let leading =
+                (0..lead_pawn_count).max_by_key(|&index| indices.map_pawns[squares[index]])?;
+            squares.swap(0, leading);
+
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+
+        let sides = self.pairs[file].len();
+        let pairs = &self.pairs[file][side_to_move.min(sides - 1)];
+
+        if self.dtz
+            && (pairs.flags & TableFlag::BlackToMove) as usize != side_to_move
+            && (self.has_pawns || !self.symmetric)
+        {
+            return Some(Lookup::OtherSide);
+        }
+
+        for (square, &piece) in board.pieces().iter().enumerate() {
+            if piece == Piece::None || lead_pawns & (1 << square) != 0 {
+                continue;
+            }
+
+            squares[size] = square ^ flip_squares;
+            pieces[size] = table_piece(piece) ^ flip_colour;
+            size += 1;
+        }
+
+        if size != self.piece_count {
+            return None;
+        }
+
+        // Put the pieces in the table's order
+        for i in lead_pawn_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| pairs.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // The leading piece goes on the a to d files
+        if squares[0] % 8 > 3 {
+            squares[..size].iter_mut().for_each(|square| *square ^= 7);
+        }
+
+        let mut index;
+        if self.has_pawns {
+            index = indices.lead_pawn_index[lead_pawn_count][squares[0]];
+
+            squares[1..lead_pawn_count].sort_by_key(|&square| indices.map_pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawn_count).skip(1) {
+                index += indices.binomial[i][indices.map_pawns[square]];
+            }
+        } else {
+            // The leading piece also goes on the first four ranks, and below
+            // the a1-h8 diagonal for the first piece of its group off it
+            if squares[0] / 8 > 3 {
+                squares[..size].iter_mut().for_each(|square| *square ^= 56);
+            }
+
+            for i in 0..pairs.group_length[0] {
+                match off_diagonal(squares[i]) {
+                    0 => continue,
+                    offset if offset > 0 => squares[i..size]
+                        .iter_mut()
+                        .for_each(|square| *square = flip_diagonal(*square)),
+                    _ => {}
+                }
+                break;
+            }
+
+            index = self.encode_leading_pieces(&squares) as u64;
+        }
+
+        index *= pairs.group_index[0];
+
+        // The other groups, each by the squares of its pieces, skipping the
+        // squares taken by the groups before
+        let mut remaining_pawns = self.has_pawns && self.both_sides_pawns;
+        let mut start = pairs.group_length[0];
+        let mut group = 1;
+        while pairs.group_length[group] != 0 {
+            let length = pairs.group_length[group];
+            squares[start..start + length].sort_unstable();
+
+            let mut group_index = 0;
+            for i in 0..length {
+                let square = squares[start + i];
+                let taken = squares[..start].iter().filter(|&&s| square > s).count();
+                let pawn_offset = if remaining_pawns { 8 } else { 0 };
+
+                group_index += indices.binomial[i + 1][square - taken - pawn_offset];
+            }
+
+            remaining_pawns = false;
+            index += group_index * pairs.group_index[group];
+            start += length;
+            group += 1;
+        }
+
+        let value = self.decompress(pairs, index)?;
+
+        Some(Lookup::Value(match self.dtz {
+            true => self.map_dtz(pairs, value, wdl)?,
+            false => value - 2,
+        }))
+    }
+
+    /// The index of the leading group of a table without pawns.
+    fn encode_leading_pieces(&self, squares: &[usize; MAX_PIECES]) -> usize {
+        let indices = &*INDICES;
+
+        if !self.has_unique_pieces {
+            return indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]];
+        }
+
+        let adjust1 = usize::from(squares[1] > squares[0]);
+        let adjust2 = usize::from(squares[2] > squares[0]) + usize::from(squares[2] > squares[1]);
+        let rank = |square: usize| square / 8;
+
+        if off_diagonal(squares[0]) != 0 {
+            (indices.map_a1d1d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62 + squares[2]
+                - adjust2
+        } else if off_diagonal(squares[1]) != 0 {
+            (6 * 63 + rank(squares[0]) * 28 + indices.map_b1h1h7[squares[1]]) * 62 + squares[2]
+                - adjust2
+        } else if off_diagonal(squares[2]) != 0 {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + rank(squares[0]) * 7 * 28
+                + (rank(squares[1]) - adjust1) * 28
+                + indices.map_b1h1h7[squares[2]]
+        } else {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + 4 * 7 * 28
+                + rank(squares[0]) * 7 * 6
+                + (rank(squares[1]) - adjust1) * 6
+                + (rank(squares[2]) - adjust2)
+        }
+    }
+
+    /// The value stored at `index`, decoding the Huffman coded block that
+    /// holds it and expanding its symbol pairs.
+    fn decompress(&self, pairs: &PairsData, index: u64) -> Option<i32> {
+        let data = &self.data;
+
+        if pairs.flags & TableFlag::SingleValue != 0 {
+            return Some(pairs.min_symbol_length as i32);
+        }
+
+        // Every sparse index entry gives the block and offset in it of the
+        // value halfway through its span
+        let entry = (index / pairs.span as u64) as usize;
+        let entry_offset = pairs.sparse_index + 6 * entry;
+        let mut block = read_u32_le(data, entry_offset)? as usize;
+        let mut offset = read_u16_le(data, entry_offset + 4)? as i64;
+        offset += (index % pairs.span as u64) as i64 - (pairs.span / 2) as i64;
+
+        let block_length =
+            |block: usize| Some(read_u16_le(data, pairs.block_lengths + 2 * block)? as i64);
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        let mut position = pairs.data + block * pairs.block_size;
+        let mut buffer = read_u64_be(data.get(position..position + 8)?);
+        let mut buffer_bits = 64;
+        position += 8;
+
+        let min_length = pairs.min_symbol_length as usize;
+        let mut symbol;
+        loop {
+            let length = pairs.base.iter().position(|&base| buffer >= base)?;
+
+            symbol = ((buffer - pairs.base[length]) >> (64 - length - min_length)) as usize;
+            symbol += read_u16_le(data, pairs.lowest_symbol + 2 * length)? as usize;
+
+            let count = *pairs.symbol_lengths.get(symbol)? as i64 + 1;
+            if offset < count {
+                break;
+            }
+
+            offset -= count;
+            let bits = length + min_length;
+            buffer <<= bits;
+            buffer_bits -= bits;
+
+            if buffer_bits <= 32 {
+                buffer_bits += 32;
+                buffer |=
+                    (read_u32_be(data.get(position..position + 4)?) as u64) << (64 - buffer_bits);
+                position += 4;
+            }
+        }
+
+        // Pairs of symbols are expanded until the value's own symbol
+        while pairs.symbol_lengths[symbol] != 0 {
+            let (left, right) = self.children(pairs, symbol)?;
+
+            let left_count = pairs.symbol_lengths[left] as i64 + 1;
+            if offset < left_count {
+                symbol = left;
+            } else {
+                offset -= left_count;
+                symbol = right;
+            }
+        }
+
+        Some(self.children(pairs, symbol)?.0 as i32)
+    }
+
+    /// The two symbols `symbol` stands for, or its value for a leaf.
+    fn children(&self, pairs: &PairsData, symbol: usize) -> Option<(usize, usize)> {
+        children(&self.data, pairs.btree, symbol)
+    }
+
+    /// Converts a stored DTZ value to plies.
+    fn map_dtz(&self, pairs: &PairsData, mut value: i32, wdl: i32) -> Option<i32> {
+        // Map entries by result: win, loss, cursed win, blessed loss
+        let map_entry = match wdl {
+            Wdl::Win => 0,
+            Wdl::Loss => 1,
+            Wdl::CursedWin => 2,
+            _ => 3,
+        };
+
+        if pairs.flags & TableFlag::Mapped != 0 {
+            let index = pairs.map_index[map_entry] + value as usize;
+            value = match pairs.flags & TableFlag::Wide != 0 {
+                true => read_u16_le(&self.data, self.map + 2 * index)? as i32,
+                false => *self.data.get(self.map + index)? as i32,
+            };
+        }
+
+        let in_plies = match wdl {
+            Wdl::Win => pairs.flags & TableFlag::WinPlies != 0,
+            Wdl::Loss => pairs.flags & TableFlag::LossPlies != 0,
+            _ => false,
+        };
+
+        Some(if in_plies { value } else { 2 * value } + 1)
+    }
+}
+
+/// Reads the part of a table shared by its blocks: the Huffman code's
+/// lowest symbols and the symbol pairs. Returns the offset after them.
+fn set_sizes(pairs: &mut PairsData, data: &[u8], offset: usize) -> Option<usize> {
+    let header = data.get(offset..offset + 10)?;
+    pairs.flags = header[0];
+
+    if pairs.flags & TableFlag::SingleValue != 0 {
+        pairs.min_symbol_length = header[1];
+        return Some(offset + 2);
+    }
+
+    let table_size = pairs.group_index[pairs.group_length.iter().position(|&l| l == 0)?];
+
+    pairs.block_size = 1 << header[1];
+    pairs.span = 1 << header[2];
+    pairs.sparse_index_size = table_size.div_ceil(pairs.span as u64) as usize;
+    pairs.num_blocks = read_u32_le(header, 4)? as usize;
+    pairs.block_length_size = pairs.num_blocks + header[3] as usize;
+
+    let max_length = header[8] as usize;
+    let min_length = header[9] as usize;
+    pairs.min_symbol_length = header[9];
+    pairs.lowest_symbol = offset + 10;
+
+    // Canonical Huffman codes are ordered so that longer codes are smaller
+    // numbers, which gives the lowest code of each length from the lowest
+    // symbols
+    let lengths = max_length.checked_sub(min_length)? + 1;
+    let lowest = |length: usize| Some(read_u16_le(data, pairs.lowest_symbol + 2 * length)? as u64);
+
+    pairs.base = vec![0; lengths];
+    for length in (0..lengths - 1).rev() {
+        pairs.base[length] = (pairs.base[length + 1] + lowest(length)? - lowest(length + 1)?) / 2;
+    }
+    for (length, base) in pairs.base.iter_mut().enumerate() {
+        *base <<= 64 - length - min_length;
+    }
+
+    let symbols_offset = pairs.lowest_symbol + 2 * lengths;
+    let symbols = read_u16_le(data, symbols_offset)? as usize;
+    pairs.btree = symbols_offset + 2;
+    data.get(pairs.btree..pairs.btree + 3 * symbols)?;
+
+    // Every symbol stands for one value or a pair of symbols, so count the
+    // values each expands to
+    pairs.symbol_lengths = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for symbol in 0..symbols {
+        if !visited[symbol] {
+            pairs.symbol_lengths[symbol] = symbol_length(pairs, data, symbol, &mut visited)?;
+        }
+    }
+
+    Some(pairs.btree + 3 * symbols + (symbols & 1))
+}
+
+fn symbol_length(
+    pairs: &mut PairsData,
+    data: &[u8],
+    symbol: usize,
+    visited: &mut [bool],
+) -> Option<u8> {
+    visited[symbol] = true;
+
+    let (left, right) = children(data, pairs.btree, symbol)?;
+    if right == 0xFFF {
+        return Some(0);
+    }
+
+    for child in [left, right] {
+        if !*visited.get(child)? {
+            pairs.symbol_lengths[child] = symbol_length(pairs, data, child, visited)?;
+        }
+    }
+
+    Some(
+        pairs.symbol_lengths[left]
+            .wrapping_add(pairs.symbol_lengths[right])
+            .wrapping_add(1),
+    )
+}
+
+/// The two 12-bit symbols stored in three bytes for `symbol`.
+fn children(data: &[u8], btree: usize, symbol: usize) -> Option<(usize, usize)> {
+    let bytes = data.get(btree + 3 * symbol..btree + 3 * symbol + 3)?;
+    let left = ((bytes[1] as usize & 0xF) << 8) | bytes[0] as usize;
+    let right = ((bytes[2] as usize) << 4) | (bytes[1] as usize >> 4);
+
+    Some((left, right))
+}
+
+/// Tables number pieces like `Piece` does with 8 added for black.
+fn table_piece(piece: u8) -> u8 {
+    match Piece::colour_bool(piece) {
+        Colour::White => Piece::piece_type(piece),
+        Colour::Black => Piece::piece_type(piece) | 8,
+    }
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32_be(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().unwrap())
+}
+
+fn read_u64_be(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+
+    use super::*;
+
+    /// King and queen against king, white to move, mate in nine.
+    const KQK: &str = "8/8/8/4k3/8/8/8/KQ6 w - - 0 1";
+    /// `KQK` with the colours swapped, so black to move wins.
+    const KKQ: &str = "kq6/8/8/8/4K3/8/8/8 b - - 0 1";
+
+    fn init_tables() {
+        static INIT: Once = Once::new();
+
+        INIT.call_once(|| {
+            let found = init(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy"));
+            assert_eq!(found, 1);
+        });
+    }
+
+    fn wdl(fen: &str) -> Option<i32> {
+        init_tables();
+        probe_wdl(&mut Board::from_fen(fen).unwrap())
+    }
+
+    fn dtz(fen: &str) -> Option<i32> {
+        init_tables();
+        probe_dtz(&mut Board::from_fen(fen).unwrap())
+    }
+
+    fn black_to_move(fen: &str) -> String {
+        fen.replace(" w ", " b ")
+    }
+
+    /// A pawnless table holding only `data`, for testing its parts.
+    fn bare_table(data: Vec<u8>, piece_count: usize) -> Table {
+        Table {
+            data,
+            dtz: false,
+            symmetric: false,
+            has_pawns: false,
+            has_unique_pieces: true,
+            both_sides_pawns: false,
+            piece_count,
+            pairs: Vec::new(),
+            map: 0,
+        }
+    }
+
+    #[test]
+    fn indices_match_the_reference_implementation() {
+        let indices = &*INDICES;
+
+        // The kings take 462 placements, numbered from 0
+        assert_eq!(indices.map_kk.iter().flatten().max(), Some(&461));
+
+        assert_eq!(indices.map_a1d1d4[1], 0);
+        assert_eq!(indices.map_a1d1d4[19], 5);
+        assert_eq!(indices.map_a1d1d4[0], 6);
+        assert_eq!(indices.map_a1d1d4[27], 9);
+        assert_eq!(indices.map_b1h1h7[1], 0);
+        assert_eq!(indices.map_b1h1h7[55], 27);
+
+        assert_eq!(indices.map_pawns[8], 47);
+        assert_eq!(indices.map_pawns[15], 46);
+        assert_eq!(indices.map_pawns[51], 1);
+        assert_eq!(indices.map_pawns[52], 0);
+        assert_eq!(indices.lead_pawns_size[1], [6; 4]);
+        assert_eq!(indices.lead_pawns_size[2], [252, 180, 108, 36]);
+
+        assert_eq!(indices.binomial[3][48], 17_296);
+    }
+
+    #[test]
+    fn unique_leading_pieces_take_31332_indices() {
+        let table = bare_table(Vec::new(), 4);
+
+        let mut squares = [0; MAX_PIECES];
+        squares[..3].copy_from_slice(&[1, 0, 2]);
+        assert_eq!(table.encode_leading_pieces(&squares), 0);
+        // All three on the diagonal, as high as they go
+        squares[..3].copy_from_slice(&[27, 63, 54]);
+        assert_eq!(table.encode_leading_pieces(&squares), 31_331);
+
+        // King and queen against king and rook
+        let mut pairs = PairsData::default();
+        pairs.pieces[..4].copy_from_slice(&[6, 5, 14, 12]);
+        table.set_groups(&mut pairs, [0, 0xF], 0);
+        assert_eq!(pairs.group_length[..3], [3, 1, 0]);
+        assert_eq!(pairs.group_index[2], 31_332 * 61);
+    }
+
+    #[test]
+    fn decompress_expands_symbol_pairs() {
+        // A code of lengths one and two: 1 for the pair of values 1 and 3,
+        // 00 for the value 1 and 01 for the value 3
+        let mut data = vec![0, 5, 3, 0, 1, 0, 0, 0, 2, 1];
+        data.extend([2, 0, 0, 0, 3, 0]);
+        data.extend([0x01, 0xF0, 0xFF, 0x03, 0xF0, 0xFF, 0x00, 0x10, 0x00]);
+
+        let mut pairs = PairsData::default();
+        pairs.group_index[0] = 5;
+        let offset = set_sizes(&mut pairs, &data, 0).unwrap();
+        assert_eq!(pairs.symbol_lengths, [0, 0, 1]);
+
+        // One block of five values, 00 1 01 00
+        data.resize(offset, 0);
+        pairs.sparse_index = data.len();
+        data.extend([0, 0, 0, 0, 4, 0]);
+        pairs.block_lengths = data.len();
+        data.extend([4, 0]);
+        pairs.data = data.len();
+        data.extend([0x28, 0, 0, 0, 0, 0, 0, 0]);
+
+        let table = bare_table(data, 3);
+        let values = (0..5)
+            .map(|index| table.decompress(&pairs, index))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [Some(1), Some(1), Some(3), Some(3), Some(1)]);
+
+        // Past the end of the table
+        assert_eq!(table.decompress(&pairs, 5), None);
+        assert_eq!(table.decompress(&pairs, 1000), None);
+    }
+
+    #[test]
+    fn wdl_of_king_and_queen_against_king() {
+        assert_eq!(wdl(KQK), Some(Wdl::Win));
+        assert_eq!(wdl(&black_to_move(KQK)), Some(Wdl::Loss));
+
+        // Stalemate
+        assert_eq!(wdl("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(Wdl::Draw));
+        // The king takes the undefended queen
+        assert_eq!(wdl("8/8/8/8/8/8/6kQ/K7 b - - 0 1"), Some(Wdl::Draw));
+    }
+
+    #[test]
+    fn wdl_with_black_stronger() {
+        assert_eq!(wdl(KKQ), Some(Wdl::Win));
+        assert_eq!(wdl(&KKQ.replace(" b ", " w ")), Some(Wdl::Loss));
+    }
+
+    #[test]
+    fn dtz_of_king_and_queen_against_king() {
+        assert_eq!(dtz(KQK), Some(17));
+        assert_eq!(dtz(&black_to_move(KQK)), Some(-18));
+        assert_eq!(dtz(KKQ), Some(17));
+
+        // The longest wins, mate in ten
+        assert_eq!(dtz("7K/6Q1/8/8/8/3k4/8/8 w - - 0 1"), Some(19));
+        assert_eq!(dtz("7K/6Q1/8/8/8/8/2k5/8 b - - 0 1"), Some(-20));
+
+        assert_eq!(dtz("k7/8/1K6/8/8/8/7Q/8 w - - 0 1"), Some(1));
+        assert_eq!(dtz("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"), Some(-1));
+        assert_eq!(dtz("8/8/8/8/8/8/6kQ/K7 b - - 0 1"), Some(0));
+    }
+
+    #[test]
+    fn root_moves_keep_the_shortest_win() {
+        init_tables();
+        let mut board = Board::from_fen("k7/8/1K6/8/8/8/7Q/8 w - - 0 1").unwrap();
+
+        let moves = root_moves(&mut board, &[]).unwrap();
+        let moves = moves
+            .iter()
+            .map(|&v_move| Move::to_uci(&board, v_move))
+            .collect::<Vec<_>>();
+
+        assert_eq!(moves, ["h2h8"]);
+    }
+}
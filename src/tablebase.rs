@@ -0,0 +1,934 @@
+//! Distance to mate tablebases for endings of up to four pieces, kings
+//! included, generated by the engine itself.
+//!
+//! A table is generated by retrograde analysis. Every position is first
+//! looked at once: mates and stalemates are settled on the spot, captures
+//! and promotions are scored from the smaller tables they lead to, and the
+//! other moves are counted. Then, one ply at a time, the positions lost in
+//! `n` plies make every position one un-move before them won in `n + 1`,
+//! and the positions won in `n` plies take one off the count of the
+//! positions before them, which are lost once none is left. Whatever is
+//! still unresolved at the end is a draw.
+//!
+//! Tables are named by material like Syzygy tables, `KQvKR.rctb`, the
+//! stronger side first. Positions are indexed by the side to move, the
+//! square of the stronger side's king and those of the other pieces, the
+//! king being brought to the a1-d1-d4 triangle by symmetry, or only to the
+//! a to d files when there are pawns. The file holds a header with the
+//! material, followed by one byte per position: 0 for a draw and otherwise
+//! the number of plies to mate plus one, odd plies being wins for the side
+//! to move. Runs of equal bytes are written as a marker, the byte and the
+//! length, and positions that cannot occur take the value before them. Castling and en passant are left out.
+
+use std::{
+    collections::HashMap,
+    fs,
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::Instant,
+};
+
+use color_print::cprintln;
+use lazy_static::lazy_static;
+
+use crate::{
+    board::Board, colour::Colour, errors::TablebaseError, moves::Move, piece::Piece,
+    syzygy::PIECE_LETTERS, Result,
+};
+
+/// The most pieces a table covers, kings included.
+pub const MAX_PIECES: usize = 4;
+
+const MAGIC: &[u8; 4] = b"RCTB";
+const VERSION: u8 = 1;
+const EXTENSION: &str = "rctb";
+/// Marks a run of equal values in a file, no distance to mate reaching it.
+const RUN: u8 = 0xFF;
+/// Shorter runs are written out value by value.
+const MIN_RUN: usize = 4;
+
+/// The a1-d1-d4 triangle, where the stronger king stands in tables without
+/// pawns.
+const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+const INVALID: u8 = 0;
+const UNRESOLVED: u8 = 1;
+const RESOLVED: u8 = 2;
+const DRAWN: u8 = 3;
+
+/// Conversion rank of a position without captures or promotions.
+const NO_CONVERSION: i16 = i16::MIN;
+/// Above any distance to mate, to rank outcomes.
+const MAX_DTM: i16 = 1000;
+
+lazy_static! {
+    static ref TABLES: RwLock<HashMap<String, Arc<Table>>> = RwLock::new(HashMap::new());
+}
+
+/// The most pieces of any loaded table, 0 when none is.
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+/// The outcome of a position with best play, in plies to mate from the
+/// side to move's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dtm {
+    Win(u32),
+    Loss(u32),
+    Draw,
+}
+
+impl Dtm {
+    fn from_value(value: u8) -> Self {
+        match value {
+            0 => Dtm::Draw,
+            value if value % 2 == 0 => Dtm::Win(value as u32 - 1),
+            value => Dtm::Loss(value as u32 - 1),
+        }
+    }
+
+    fn value(self) -> u8 {
+        match self {
+            Dtm::Win(plies) | Dtm::Loss(plies) => plies as u8 + 1,
+            Dtm::Draw => 0,
+        }
+    }
+
+    /// The outcome for the side that played the move leading here.
+    fn before(self) -> Self {
+        match self {
+            Dtm::Win(plies) => Dtm::Loss(plies + 1),
+            Dtm::Loss(plies) => Dtm::Win(plies + 1),
+            Dtm::Draw => Dtm::Draw,
+        }
+    }
+
+    /// Higher for better outcomes: faster wins and slower losses.
+    fn rank(self) -> i16 {
+        match self {
+            Dtm::Win(plies) => MAX_DTM - plies as i16,
+            Dtm::Loss(plies) => plies as i16 - MAX_DTM,
+            Dtm::Draw => 0,
+        }
+    }
+
+    fn from_rank(rank: i16) -> Self {
+        match rank {
+            0 => Dtm::Draw,
+            rank if rank > 0 => Dtm::Win((MAX_DTM - rank) as u32),
+            rank => Dtm::Loss((rank + MAX_DTM) as u32),
+        }
+    }
+}
+
+/// The outcome of every position of one material.
+pub struct Table {
+    /// Coloured pieces in index order: the stronger side as white, its king
+    /// first, then the other pieces strongest first, then black's likewise.
+    pieces: Vec<u8>,
+    /// Index ranges of identical pieces, whose squares are kept sorted.
+    groups: Vec<Range<usize>>,
+    has_pawns: bool,
+    values: Vec<u8>,
+}
+
+impl Table {
+    fn new(pieces: Vec<u8>) -> Self {
+        let mut groups: Vec<Range<usize>> = Vec::new();
+        for (index, &piece) in pieces.iter().enumerate() {
+            match groups.last_mut() {
+                Some(group) if pieces[group.start] == piece => group.end = index + 1,
+                _ => groups.push(index..index + 1),
+            }
+        }
+
+        Self {
+            has_pawns: pieces
+                .iter()
+                .any(|&piece| Piece::is_type(piece, Piece::Pawn)),
+            pieces,
+            groups,
+            values: Vec::new(),
+        }
+    }
+
+    fn name(&self) -> String {
+        let sides = [Colour::White, Colour::Black].map(|colour| side_types(&self.pieces, colour));
+
+        material_name(&sides[0], &sides[1])
+    }
+
+    fn king_squares(&self) -> usize {
+        match self.has_pawns {
+            true => 32,
+            false => TRIANGLE.len(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    /// The index of a position whose stronger side is white.
+    fn index(&self, pieces: &[u8; 64], white_to_move: bool) -> usize {
+        let mut squares = Vec::with_capacity(self.pieces.len());
+        for group in &self.groups {
+            let piece = self.pieces[group.start];
+            squares.extend((0..64).filter(|&square| pieces[square] == piece));
+        }
+
+        // Bring the king to the a to d files, and without pawns to the
+        // a1-d1-d4 triangle
+        let king = squares[0];
+        let mut flip = if king % 8 > 3 { 7 } else { 0 };
+        if !self.has_pawns && king / 8 > 3 {
+            flip |= 56;
+        }
+        for square in squares.iter_mut() {
+            *square ^= flip;
+        }
+
+        let (file, rank) = (squares[0] % 8, squares[0] / 8);
+        if self.has_pawns || rank < file {
+            return self.encode(&mut squares, white_to_move);
+        }
+
+        let mut mirrored = squares
+            .iter()
+            .map(|&square| square % 8 * 8 + square / 8)
+            .collect::<Vec<_>>();
+        let mirrored_index = self.encode(&mut mirrored, white_to_move);
+
+        // With the king on the diagonal both halves are alike, so the
+        // smaller index is the one kept
+        match rank == file {
+            true => self.encode(&mut squares, white_to_move).min(mirrored_index),
+            false => mirrored_index,
+        }
+    }
+
+    fn encode(&self, squares: &mut [usize], white_to_move: bool) -> usize {
+        for group in &self.groups {
+            squares[group.clone()].sort_unstable();
+        }
+
+        let king = squares[0];
+        let king_index = match self.has_pawns {
+            true => king / 8 * 4 + king % 8,
+            false => TRIANGLE.iter().position(|&square| square == king).unwrap(),
+        };
+        let others = squares[1..]
+            .iter()
+            .rev()
+            .fold(0, |index, &square| index * 64 + square);
+
+        usize::from(!white_to_move) + 2 * (king_index + self.king_squares() * others)
+    }
+
+    /// The legal position at `index`, unless it is the index of no position
+    /// or not the one positions like it are stored at.
+    fn position(&self, index: usize) -> Option<Board> {
+        let white_to_move = index & 1 == 0;
+        let mut rest = index / 2;
+
+        let king_index = rest % self.king_squares();
+        rest /= self.king_squares();
+
+        let mut pieces = [Piece::None; 64];
+        for (n, &piece) in self.pieces.iter().enumerate() {
+            let square = match n {
+                0 if self.has_pawns => king_index / 4 * 8 + king_index % 4,
+                0 => TRIANGLE[king_index],
+                _ => {
+                    let square = rest % 64;
+                    rest /= 64;
+                    square
+                }
+            };
+
+            let back_rank = square / 8 == 0 || square / 8 == 7;
+            if pieces[square] != Piece::None || (Piece::is_type(piece, Piece::Pawn) && back_rank) {
+                return None;
+            }
+            pieces[square] = piece;
+        }
+
+        if self.index(&pieces, white_to_move) != index {
+            return None;
+        }
+
+        let board = Board::from_pieces(pieces, white_to_move);
+        match board.is_colour_in_check(!white_to_move) {
+            true => None,
+            false => Some(board),
+        }
+    }
+
+    fn probe(&self, pieces: &[u8; 64], white_to_move: bool) -> Dtm {
+        Dtm::from_value(self.values[self.index(pieces, white_to_move)])
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() < 6 || &bytes[..4] != MAGIC {
+            return Err(Box::new(TablebaseError::BadMagic));
+        }
+        if bytes[4] != VERSION {
+            return Err(Box::new(TablebaseError::UnsupportedVersion(bytes[4])));
+        }
+
+        let name_end = 6 + bytes[5] as usize;
+        let name = bytes
+            .get(6..name_end)
+            .ok_or(TablebaseError::Truncated)?
+            .iter()
+            .map(|&byte| byte as char)
+            .collect::<String>();
+        let mut table = Table::new(parse_name(&name)?);
+
+        let mut data = &bytes[name_end..];
+        while let [byte, rest @ ..] = data {
+            data = rest;
+            if *byte != RUN {
+                table.values.push(*byte);
+                continue;
+            }
+
+            let [value, rest @ ..] = data else {
+                return Err(Box::new(TablebaseError::Truncated));
+            };
+            let (length, rest) = read_varint(rest).ok_or(TablebaseError::Truncated)?;
+            table
+                .values
+                .extend(std::iter::repeat_n(*value, length as usize));
+            data = rest;
+        }
+
+        if table.values.len() != table.size() {
+            return Err(Box::new(TablebaseError::WrongLength(
+                table.values.len(),
+                table.size(),
+            )));
+        }
+
+        Ok(table)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let name = self.name();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([VERSION, name.len() as u8]);
+        bytes.extend(name.bytes());
+
+        for run in self.values.chunk_by(|a, b| a == b) {
+            if run.len() < MIN_RUN {
+                bytes.extend(run);
+            } else {
+                bytes.extend([RUN, run[0]]);
+                write_varint(&mut bytes, run.len() as u64);
+            }
+        }
+
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+}
+
+/// Loads every table in `directory`, returning how many were found.
+pub fn load(directory: &str) -> Result<usize> {
+    let mut count = 0;
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == EXTENSION)
+        {
+            insert(Arc::new(Table::load(&path)?));
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Forgets every loaded table.
+pub fn clear() {
+    TABLES.write().unwrap().clear();
+    LARGEST.store(0, Ordering::Relaxed);
+}
+
+fn insert(table: Arc<Table>) {
+    LARGEST.fetch_max(table.pieces.len(), Ordering::Relaxed);
+    TABLES.write().unwrap().insert(table.name(), table);
+}
+
+/// The outcome of `board` with best play, if a loaded table covers it.
+pub fn probe(board: &Board) -> Option<Dtm> {
+    let largest = LARGEST.load(Ordering::Relaxed);
+    if largest == 0 || board.get_castling_rights() != 0 {
+        return None;
+    }
+
+    let pieces = board.pieces().iter().filter(|&&piece| piece != Piece::None);
+    let kings = pieces
+        .clone()
+        .filter(|&&piece| Piece::is_type(piece, Piece::King))
+        .count();
+    let piece_count = pieces.count();
+    if piece_count > largest || piece_count <= 2 || kings != 2 {
+        return None;
+    }
+
+    let (name, pieces, white_to_move) = oriented(board);
+    let tables = TABLES.read().unwrap();
+
+    Some(tables.get(&name)?.probe(&pieces, white_to_move))
+}
+
+/// Generates the table named `name`, like `KRvKB`, along with the tables its
+/// captures and promotions lead to, and saves them to `directory`. Tables
+/// already loaded or found in `directory` are used as they are.
+pub fn generate(name: &str, directory: &str) -> Result<()> {
+    let pieces = parse_name(name)?;
+    fs::create_dir_all(directory)?;
+    ensure(Table::new(pieces), Path::new(directory))?;
+
+    Ok(())
+}
+
+fn ensure(table: Table, directory: &Path) -> Result<Arc<Table>> {
+    let name = table.name();
+    if let Some(table) = TABLES.read().unwrap().get(&name) {
+        return Ok(Arc::clone(table));
+    }
+
+    let path = directory.join(format!("{}.{}", name, EXTENSION));
+    if path.exists() {
+        let table = Arc::new(Table::load(&path)?);
+        insert(Arc::clone(&table));
+        return Ok(table);
+    }
+
+    let mut children = HashMap::new();
+    for child in child_materials(&table) {
+        let child = ensure(Table::new(child), directory)?;
+        children.insert(child.name(), child);
+    }
+
+    let start = Instant::now();
+    let table = Arc::new(generate_table(table, &children));
+    table.save(&path)?;
+
+    let longest = table
+        .values
+        .iter()
+        .map(|&value| value.saturating_sub(1))
+        .max()
+        .unwrap_or_default();
+    cprintln!(
+        "<green>generated</> {} in {:.1}s, longest mate {} plies",
+        name,
+        start.elapsed().as_secs_f64(),
+        longest
+    );
+
+    insert(Arc::clone(&table));
+    Ok(table)
+}
+
+/// The materials one capture or promotion away from `table`'s, bare kings
+/// aside.
+fn child_materials(table: &Table) -> Vec<Vec<u8>> {
+    let mut children: Vec<Vec<u8>> = Vec::new();
+
+    for (index, &piece) in table.pieces.iter().enumerate() {
+        if Piece::is_type(piece, Piece::King) {
+            continue;
+        }
+
+        let mut replacements = vec![Piece::None];
+        if Piece::is_type(piece, Piece::Pawn) {
+            replacements.extend([Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight]);
+        }
+
+        for replacement in replacements {
+            let mut pieces = table.pieces.clone();
+            match replacement {
+                Piece::None => {
+                    pieces.remove(index);
+                }
+                piece_type => pieces[index] = Piece::colour(piece) | piece_type,
+            }
+
+            if pieces.len() == 2 {
+                continue;
+            }
+
+            let sides = [Colour::White, Colour::Black].map(|colour| side_types(&pieces, colour));
+
+            let Ok(child) = parse_name(&material_name(&sides[0], &sides[1])) else {
+                continue;
+            };
+            if !children.contains(&child) {
+                children.push(child);
+            }
+        }
+    }
+
+    children
+}
+
+fn generate_table(mut table: Table, children: &HashMap<String, Arc<Table>>) -> Table {
+    let size = table.size();
+    let mut states = vec![INVALID; size];
+    let mut counts = vec![0u8; size];
+    let mut conversions = vec![NO_CONVERSION; size];
+    let mut values = vec![0u8; size];
+
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = size.div_ceil(threads);
+
+    thread::scope(|scope| {
+        let chunks = states
+            .chunks_mut(chunk_size)
+            .zip(counts.chunks_mut(chunk_size))
+            .zip(conversions.chunks_mut(chunk_size))
+            .zip(values.chunks_mut(chunk_size))
+            .enumerate();
+
+        for (chunk, (((states, counts), conversions), values)) in chunks {
+            let table = &table;
+            scope.spawn(move || {
+                for offset in 0..states.len() {
+                    let index = chunk * chunk_size + offset;
+                    let Some(mut board) = table.position(index) else {
+                        continue;
+                    };
+
+                    let (state, count, conversion, value) =
+                        initial_result(table, children, &mut board);
+                    states[offset] = state;
+                    counts[offset] = count;
+                    conversions[offset] = conversion;
+                    values[offset] = value;
+                }
+            });
+        }
+    });
+
+    let deepest_conversion = conversions
+        .iter()
+        .filter(|&&rank| rank > 0)
+        .map(|&rank| MAX_DTM - rank)
+        .max()
+        .unwrap_or_default() as u32;
+    let mut deepest = values
+        .iter()
+        .map(|&value| value.saturating_sub(1) as u32)
+        .max()
+        .unwrap_or_default()
+        .max(deepest_conversion);
+
+    let mut plies = 0;
+    while plies <= deepest {
+        let win = Dtm::Win(plies);
+        for index in 0..size {
+            if states[index] == UNRESOLVED && conversions[index] == win.rank() {
+                states[index] = RESOLVED;
+                values[index] = win.value();
+            }
+        }
+
+        for index in 0..size {
+            if states[index] != RESOLVED || values[index] as u32 != plies + 1 {
+                continue;
+            }
+
+            let board = table.position(index).unwrap();
+            let lost = Dtm::from_value(values[index]) == Dtm::Loss(plies);
+
+            for predecessor in predecessors(&table, &board) {
+                if states[predecessor] != UNRESOLVED {
+                    continue;
+                }
+
+                let result = if lost {
+                    Dtm::Win(plies + 1)
+                } else {
+                    counts[predecessor] -= 1;
+
+                    // Lost once every move loses, the slowest loss counting
+                    match (counts[predecessor], conversions[predecessor]) {
+                        (0, NO_CONVERSION) => Dtm::Loss(plies + 1),
+                        (0, rank) if rank < 0 => match Dtm::from_rank(rank) {
+                            Dtm::Loss(slowest) => Dtm::Loss(slowest.max(plies + 1)),
+                            _ => unreachable!(),
+                        },
+                        _ => continue,
+                    }
+                };
+
+                if let Dtm::Win(depth) | Dtm::Loss(depth) = result {
+                    deepest = deepest.max(depth);
+                }
+                states[predecessor] = RESOLVED;
+                values[predecessor] = result.value();
+            }
+        }
+
+        plies += 1;
+    }
+
+    // Invalid positions are never probed, so they repeat the value before
+    // them to lengthen the runs in the file
+    for index in 1..size {
+        if states[index] == INVALID {
+            values[index] = values[index - 1];
+        }
+    }
+
+    table.values = values;
+    table
+}
+
+/// The state, count of distinct positions reached without leaving the
+/// table, conversion rank and value of a legal position, before any
+/// retrograde step.
+fn initial_result(
+    table: &Table,
+    children: &HashMap<String, Arc<Table>>,
+    board: &mut Board,
+) -> (u8, u8, i16, u8) {
+    let moves = board.generate_legal_moves();
+    if moves.is_empty() {
+        return match board.is_in_check() {
+            true => (RESOLVED, 0, NO_CONVERSION, Dtm::Loss(0).value()),
+            false => (DRAWN, 0, NO_CONVERSION, 0),
+        };
+    }
+
+    let mut successors = Vec::with_capacity(moves.len());
+    let mut conversion = NO_CONVERSION;
+
+    for v_move in moves {
+        let converts = board.is_capture(v_move) || Move::is_promotion(v_move);
+        board.make_move(v_move);
+
+        if converts {
+            conversion = conversion.max(probe_child(children, board).before().rank());
+        } else {
+            successors.push(table.index(board.pieces(), board.get_colour_to_move()));
+        }
+
+        let _ = board.undo_move();
+    }
+
+    successors.sort_unstable();
+    successors.dedup();
+
+    if !successors.is_empty() {
+        return (UNRESOLVED, successors.len() as u8, conversion, 0);
+    }
+
+    match Dtm::from_rank(conversion) {
+        Dtm::Draw => (DRAWN, 0, conversion, 0),
+        dtm => (RESOLVED, 0, conversion, dtm.value()),
+    }
+}
+
+/// The distinct positions of the table one un-move before `board`.
+fn predecessors(table: &Table, board: &Board) -> Vec<usize> {
+    let mut predecessors = board
+        .generate_unmoves()
+        .into_iter()
+        .filter_map(|unmove| {
+            let mut board = board.clone();
+            board.make_unmove(unmove);
+
+            let white_to_move = board.get_colour_to_move();
+            match board.is_colour_in_check(!white_to_move) {
+                true => None,
+                false => Some(table.index(board.pieces(), white_to_move)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    predecessors.sort_unstable();
+    predecessors.dedup();
+    predecessors
+}
+
+/// The outcome of a position reached by a capture or promotion.
+fn probe_child(children: &HashMap<String, Arc<Table>>, board: &Board) -> Dtm {
+    let (name, pieces, white_to_move) = oriented(board);
+
+    match children.get(&name) {
+        Some(table) => table.probe(&pieces, white_to_move),
+        None => Dtm::Draw,
+    }
+}
+
+/// The material name of `board`, and its pieces and side to move with the
+/// colours swapped if black is the stronger side.
+fn oriented(board: &Board) -> (String, [u8; 64], bool) {
+    let sides = [Colour::White, Colour::Black].map(|colour| side_types(board.pieces(), colour));
+
+    let white_to_move = board.get_colour_to_move() == Colour::White;
+    if is_stronger(&sides[0], &sides[1]) {
+        return (
+            material_name(&sides[0], &sides[1]),
+            *board.pieces(),
+            white_to_move,
+        );
+    }
+
+    let mut pieces = [Piece::None; 64];
+    for (square, &piece) in board.pieces().iter().enumerate() {
+        if piece != Piece::None {
+            pieces[square ^ 56] = piece ^ (Piece::White | Piece::Black);
+        }
+    }
+
+    (material_name(&sides[1], &sides[0]), pieces, !white_to_move)
+}
+
+/// The types of `colour`'s pieces other than the king, strongest first.
+fn side_types(pieces: &[u8], colour: bool) -> Vec<u8> {
+    let mut types = pieces
+        .iter()
+        .filter(|&&piece| piece != Piece::None && Piece::is_colour_bool(piece, colour))
+        .map(|&piece| Piece::piece_type(piece))
+        .filter(|&piece_type| piece_type != Piece::King)
+        .collect::<Vec<_>>();
+    types.sort_unstable_by(|a, b| b.cmp(a));
+
+    types
+}
+
+/// Whether the side with the piece types `a`, strongest first, is at least
+/// as strong as the one with `b`: more pieces, or else stronger ones.
+fn is_stronger(a: &[u8], b: &[u8]) -> bool {
+    (a.len(), a) >= (b.len(), b)
+}
+
+/// The name of the material made of both sides' piece types besides the
+/// kings, strongest first.
+fn material_name(white: &[u8], black: &[u8]) -> String {
+    let letters = |types: &[u8]| {
+        types
+            .iter()
+            .filter_map(|&piece_type| {
+                PIECE_LETTERS
+                    .iter()
+                    .find(|&&(_, letter_type)| letter_type == piece_type)
+                    .map(|&(letter, _)| letter)
+            })
+            .collect::<String>()
+    };
+
+    format!("K{}vK{}", letters(white), letters(black))
+}
+
+/// The pieces of the table named `name` in index order, if it is a valid
+/// name with the stronger side first.
+fn parse_name(name: &str) -> Result<Vec<u8>> {
+    let bad_name = || TablebaseError::BadName(name.to_string());
+
+    let (white, black) = name.split_once('v').ok_or_else(bad_name)?;
+    let mut sides = [Vec::new(), Vec::new()];
+
+    for (types, letters) in sides.iter_mut().zip([white, black]) {
+        let mut letters = letters.chars();
+        if letters.next() != Some('K') {
+            return Err(Box::new(bad_name()));
+        }
+
+        for letter in letters {
+            let &(_, piece_type) = PIECE_LETTERS
+                .iter()
+                .find(|&&(piece_letter, _)| piece_letter == letter)
+                .ok_or_else(bad_name)?;
+            types.push(piece_type);
+        }
+    }
+
+    let piece_count = 2 + sides[0].len() + sides[1].len();
+    if piece_count > MAX_PIECES || piece_count == 2 {
+        return Err(Box::new(TablebaseError::TooManyPieces(name.to_string())));
+    }
+    // Pieces strongest first, and the stronger side first
+    if !is_stronger(&sides[0], &sides[1]) || material_name(&sides[0], &sides[1]) != name {
+        return Err(Box::new(bad_name()));
+    }
+
+    let mut pieces = vec![Piece::White | Piece::King];
+    pieces.extend(sides[0].iter().map(|&piece_type| Piece::White | piece_type));
+    pieces.push(Piece::Black | Piece::King);
+    pieces.extend(sides[1].iter().map(|&piece_type| Piece::Black | piece_type));
+
+    Ok(pieces)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0;
+
+    for (index, &byte) in bytes.iter().enumerate().take(10) {
+        value |= ((byte & 0x7F) as u64) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Some((value, &bytes[index + 1..]));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate_in_memory(name: &str) -> Table {
+        generate_table(Table::new(parse_name(name).unwrap()), &HashMap::new())
+    }
+
+    fn probe_board(table: &Table, board: &Board) -> Dtm {
+        let (_, pieces, white_to_move) = oriented(board);
+        table.probe(&pieces, white_to_move)
+    }
+
+    fn probe_fen(table: &Table, fen: &str) -> Dtm {
+        probe_board(table, &Board::from_fen(fen).unwrap())
+    }
+
+    /// The most plies to mate in any position of `table`.
+    fn longest(table: &Table) -> u32 {
+        table
+            .values
+            .iter()
+            .map(|&value| value.saturating_sub(1) as u32)
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn king_and_queen_against_king() {
+        let table = generate_in_memory("KQvK");
+
+        // Mate in ten at most, black to move being mated on the twentieth ply
+        assert_eq!(longest(&table), 20);
+        assert_eq!(
+            probe_fen(&table, "7K/6Q1/8/8/8/3k4/8/8 w - - 0 1"),
+            Dtm::Win(19)
+        );
+        assert_eq!(
+            probe_fen(&table, "7K/6Q1/8/8/8/8/2k5/8 b - - 0 1"),
+            Dtm::Loss(20)
+        );
+
+        assert_eq!(
+            probe_fen(&table, "k7/8/1K6/8/8/8/7Q/8 w - - 0 1"),
+            Dtm::Win(1)
+        );
+        assert_eq!(
+            probe_fen(&table, "k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"),
+            Dtm::Loss(0)
+        );
+        assert_eq!(
+            probe_fen(&table, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"),
+            Dtm::Draw
+        );
+        assert_eq!(probe_fen(&table, "8/8/8/8/8/8/6kQ/K7 b - - 0 1"), Dtm::Draw);
+
+        // Black is the stronger side
+        assert_eq!(
+            probe_fen(&table, "kq6/8/8/8/4K3/8/8/8 b - - 0 1"),
+            Dtm::Win(17)
+        );
+    }
+
+    #[test]
+    fn king_and_rook_against_king() {
+        let table = generate_in_memory("KRvK");
+
+        // Mate in sixteen at most
+        assert_eq!(longest(&table), 32);
+        assert_eq!(
+            probe_fen(&table, "k7/8/1K6/8/8/8/8/7R w - - 0 1"),
+            Dtm::Win(1)
+        );
+        assert_eq!(
+            probe_fen(&table, "R1k5/8/2K5/8/8/8/8/8 b - - 0 1"),
+            Dtm::Loss(0)
+        );
+        assert_eq!(probe_fen(&table, "8/8/8/8/8/8/6kR/K7 b - - 0 1"), Dtm::Draw);
+    }
+
+    /// With the stronger king on the a1-h8 diagonal a position and its
+    /// mirror image share an index, so every such position with black to
+    /// move must agree with the positions its moves lead to.
+    #[test]
+    fn probes_agree_with_generation_on_the_diagonal() {
+        let table = generate_in_memory("KQvK");
+
+        for king in (0..64).step_by(9) {
+            for queen in 0..64 {
+                for black_king in 0..64 {
+                    if queen == king || black_king == king || black_king == queen {
+                        continue;
+                    }
+
+                    let mut pieces = [Piece::None; 64];
+                    pieces[king] = Piece::White | Piece::King;
+                    pieces[queen] = Piece::White | Piece::Queen;
+                    pieces[black_king] = Piece::Black | Piece::King;
+
+                    let mut board = Board::from_pieces(pieces, Colour::Black);
+                    if board.is_colour_in_check(Colour::White) {
+                        continue;
+                    }
+
+                    let moves = board.generate_legal_moves();
+                    let expected = match moves.is_empty() {
+                        true if board.is_in_check() => Dtm::Loss(0),
+                        true => Dtm::Draw,
+                        false => {
+                            let best = moves
+                                .into_iter()
+                                .map(|v_move| {
+                                    // Taking the queen leaves bare kings
+                                    let dtm = match board.is_capture(v_move) {
+                                        true => Dtm::Draw,
+                                        false => {
+                                            board.make_move(v_move);
+                                            let dtm = probe_board(&table, &board);
+                                            let _ = board.undo_move();
+                                            dtm
+                                        }
+                                    };
+                                    dtm.before().rank()
+                                })
+                                .max()
+                                .unwrap();
+                            Dtm::from_rank(best)
+                        }
+                    };
+
+                    assert_eq!(probe_board(&table, &board), expected, "{}", board.to_fen());
+                }
+            }
+        }
+    }
+}
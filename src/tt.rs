@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// How a stored score relates to the true score of the position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    Exact,
+    /// The search failed high, the true score is at least this.
+    Lower,
+    /// The search failed low, the true score is at most this.
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+pub struct TtEntry {
+    pub best_move: u16,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+    generation: u8,
+}
+
+impl TtEntry {
+    /// Packs everything but the key into 64 bits:
+    /// 8 bits generation, 2 bits bound, 8 bits depth, 16 bits score, 16 bits move.
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+
+        self.best_move as u64
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.depth.clamp(0, u8::MAX as i32) as u64) << 32
+            | bound << 40
+            | (self.generation as u64) << 48
+    }
+
+    fn unpack(data: u64) -> Option<Self> {
+        let bound = match (data >> 40) & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+
+        Some(Self {
+            best_move: data as u16,
+            score: (data >> 16) as u16 as i16 as i32,
+            depth: ((data >> 32) & 0xFF) as i32,
+            bound,
+            generation: (data >> 48) as u8,
+        })
+    }
+}
+
+/// A transposition table shared between search threads without locking.
+///
+/// Each slot stores the key xored with the packed entry, so a slot torn by
+/// two threads writing at once fails the key check instead of returning a
+/// mix of both entries.
+pub struct TranspositionTable {
+    keys: Vec<AtomicU64>,
+    data: Vec<AtomicU64>,
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub const DEFAULT_SIZE_MB: usize = 16;
+    pub const MAX_SIZE_MB: usize = 4096;
+
+    pub fn new(size_mb: usize) -> Self {
+        let count = (size_mb.max(1) * 1024 * 1024 / 16).max(1);
+
+        Self {
+            keys: (0..count).map(|_| AtomicU64::new(0)).collect(),
+            data: (0..count).map(|_| AtomicU64::new(0)).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in self.keys.iter().chain(self.data.iter()) {
+            slot.store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the start of a new search, so entries from older searches are
+    /// replaced first while still being usable for move ordering.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let index = self.index(key);
+        let data = self.data[index].load(Ordering::Relaxed);
+
+        if self.keys[index].load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+
+        TtEntry::unpack(data)
+    }
+
+    pub fn store(&self, key: u64, best_move: u16, score: i32, depth: i32, bound: Bound) {
+        let index = self.index(key);
+        let generation = self.generation.load(Ordering::Relaxed);
+        let old = self.probe(key);
+
+        if let Some(old) = old {
+            // Keep deeper results from the current search
+            if old.generation == generation && old.depth > depth {
+                return;
+            }
+        }
+
+        let best_move = match old {
+            Some(old) if best_move == 0 => old.best_move,
+            _ => best_move,
+        };
+
+        let entry = TtEntry {
+            best_move,
+            score,
+            depth,
+            bound,
+            generation,
+        };
+        let data = entry.pack();
+
+        self.keys[index].store(key ^ data, Ordering::Relaxed);
+        self.data[index].store(data, Ordering::Relaxed);
+    }
+
+    /// Permille of a sample of slots holding entries from the current search.
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = self.data.len().min(1000);
+
+        let used = self.data[..sample]
+            .iter()
+            .filter_map(|data| TtEntry::unpack(data.load(Ordering::Relaxed)))
+            .filter(|entry| entry.generation == generation)
+            .count();
+
+        (used * 1000 / sample) as u32
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.keys.len() as u64) as usize
+    }
+}
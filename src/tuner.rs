@@ -0,0 +1,287 @@
+use std::{
+    fs,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, Scope},
+};
+
+use color_print::cprintln;
+
+use crate::{
+    board::Board,
+    errors::TuneError,
+    evaluation,
+    params::{set_eval_params, EvalParams, EVAL_PARAMS},
+    search::Searcher,
+    tt::TranspositionTable,
+    Result,
+};
+
+/// Results as they may appear on a position line, with white's score for each.
+const RESULTS: [(&str, f64); 6] = [
+    ("1-0", 1.0),
+    ("0-1", 0.0),
+    ("1/2-1/2", 0.5),
+    ("[1.0]", 1.0),
+    ("[0.0]", 0.0),
+    ("[0.5]", 0.5),
+];
+
+pub const DEFAULT_PASSES: usize = 100;
+
+/// Range searched for the sigmoid scaling constant.
+const MAX_SCALING: f64 = 3.0;
+const SCALING_PRECISION: f64 = 0.001;
+
+/// A quiet position and the result of the game it was taken from.
+struct TuningPosition {
+    board: Board,
+    /// 1 for a white win, 0.5 for a draw and 0 for a black win.
+    result: f64,
+}
+
+/// Tunes the evaluation parameters on the positions in `positions_path`
+/// with Texel's local search, writing them to `output_path` after every pass.
+///
+/// Each line of the positions file holds a FEN, possibly without its move
+/// counters, and the game result as `1-0`, `0-1` or `1/2-1/2`, or as
+/// `[1.0]`, `[0.0]` or `[0.5]`. Every weight is nudged one unit up or down
+/// in turn and the change kept when it lowers the mean squared error
+/// between the game results and the sigmoid of the evaluation, until a pass
+/// brings no improvement or `max_passes` is reached.
+pub fn tune(positions_path: &str, output_path: &str, max_passes: usize) -> Result<()> {
+    let positions = load_positions(positions_path)?;
+    if positions.is_empty() {
+        return Err(Box::new(TuneError::NoPositions));
+    }
+
+    cprintln!("<green>positions:</> {}", positions.len());
+
+    let mut params = EVAL_PARAMS.read().unwrap().clone();
+
+    thread::scope(|scope| -> Result<()> {
+        let pool = ErrorPool::new(scope, &positions);
+
+        let scaling = best_scaling(&pool, &params);
+        cprintln!("<green>scaling:</> {:.3}", scaling);
+
+        let mut values = params.values();
+        let mut best_error = pool.error(&params, scaling);
+        cprintln!("<green>initial error:</> {:.6}", best_error);
+
+        for pass in 1..=max_passes {
+            let mut improved = false;
+
+            for index in 0..values.len() {
+                for step in [1, -1] {
+                    values[index] += step;
+                    params.set_all(&values);
+
+                    let new_error = pool.error(&params, scaling);
+                    if new_error < best_error {
+                        best_error = new_error;
+                        improved = true;
+                        break;
+                    }
+
+                    values[index] -= step;
+                }
+            }
+
+            params.set_all(&values);
+            params.save(output_path)?;
+            cprintln!("<green>pass {}:</> error {:.6}", pass, best_error);
+
+            if !improved {
+                break;
+            }
+        }
+
+        Ok(())
+    })?;
+
+    set_eval_params(params);
+    cprintln!("<green>tuned parameters:</> {}", output_path);
+
+    Ok(())
+}
+
+/// Reads the positions and replaces each by the quiet position its
+/// quiescence search score comes from, so that the evaluation is never
+/// asked about a position in the middle of an exchange.
+fn load_positions(path: &str) -> Result<Vec<TuningPosition>> {
+    let contents = fs::read_to_string(path)?;
+    let searcher = Searcher::new(TranspositionTable::DEFAULT_SIZE_MB);
+
+    let mut positions = Vec::new();
+    for (line_number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (fen, result) = parse_line(line).ok_or(TuneError::NoResult(line_number + 1))?;
+        let mut board = Board::from_fen(&fen)
+            .map_err(|e| TuneError::BadPosition(line_number + 1, e.to_string()))?;
+
+        for v_move in searcher.quiescence_pv(&mut board.clone()) {
+            board.make_move(v_move);
+        }
+
+        positions.push(TuningPosition { board, result });
+    }
+
+    Ok(positions)
+}
+
+/// Splits a line into a full FEN and white's result.
+fn parse_line(line: &str) -> Option<(String, f64)> {
+    let (index, result) = RESULTS
+        .iter()
+        .filter_map(|&(marker, result)| line.find(marker).map(|index| (index, result)))
+        .min_by_key(|&(index, _)| index)?;
+
+    // The move counters are optional, and anything else before the result,
+    // like an EPD `c9` opcode, is dropped
+    let mut fields = line[..index].split_whitespace();
+    let mut fen = fields.by_ref().take(4).collect::<Vec<_>>();
+    let counters = fields
+        .take(2)
+        .take_while(|field| field.parse::<u16>().is_ok())
+        .collect::<Vec<_>>();
+
+    match counters.as_slice() {
+        [halfmove, fullmove] => fen.extend([*halfmove, *fullmove]),
+        _ => fen.extend(["0", "1"]),
+    }
+
+    Some((fen.join(" "), result))
+}
+
+/// The constant fitting the sigmoid of the evaluations with `params` best to the results.
+fn best_scaling(pool: &ErrorPool, params: &EvalParams) -> f64 {
+    let (mut low, mut high) = (0.0, MAX_SCALING);
+
+    // The error is convex in the scaling, so a ternary search finds its minimum
+    while high - low > SCALING_PRECISION {
+        let third = (high - low) / 3.0;
+        let (left, right) = (low + third, high - third);
+
+        if pool.error(params, left) < pool.error(params, right) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+/// One thread per core, each holding a share of the positions, kept for
+/// the whole tuning run and handed every set of parameters to try.
+struct ErrorPool {
+    jobs: Vec<Sender<(Arc<EvalParams>, f64)>>,
+    errors: Receiver<f64>,
+    positions: usize,
+}
+
+impl ErrorPool {
+    fn new<'scope>(scope: &'scope Scope<'scope, '_>, positions: &'scope [TuningPosition]) -> Self {
+        let threads = thread::available_parallelism().map_or(1, usize::from);
+        let chunk_size = positions.len().div_ceil(threads);
+        let (error_sender, errors) = mpsc::channel();
+
+        // The threads stop once the pool and with it their job senders are dropped
+        let jobs = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let (job_sender, jobs) = mpsc::channel::<(Arc<EvalParams>, f64)>();
+                let error_sender = error_sender.clone();
+
+                scope.spawn(move || {
+                    for (params, scaling) in jobs {
+                        let error = chunk
+                            .iter()
+                            .map(|position| {
+                                let evaluation =
+                                    evaluation::evaluate_with(&position.board, &params);
+                                (position.result - sigmoid(evaluation, scaling)).powi(2)
+                            })
+                            .sum::<f64>();
+
+                        if error_sender.send(error).is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                job_sender
+            })
+            .collect();
+
+        Self {
+            jobs,
+            errors,
+            positions: positions.len(),
+        }
+    }
+
+    /// The mean squared error of the results predicted with `params`.
+    fn error(&self, params: &EvalParams, scaling: f64) -> f64 {
+        let params = Arc::new(params.clone());
+        for jobs in &self.jobs {
+            jobs.send((Arc::clone(&params), scaling))
+                .expect("tuning thread panicked");
+        }
+
+        let total = self.errors.iter().take(self.jobs.len()).sum::<f64>();
+
+        total / self.positions as f64
+    }
+}
+
+/// The expected score for white of an evaluation in centipawns.
+fn sigmoid(evaluation: i32, scaling: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scaling * evaluation as f64 / 400.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(fen: &str, result: f64) -> TuningPosition {
+        TuningPosition {
+            board: Board::from_fen(fen).unwrap(),
+            result,
+        }
+    }
+
+    #[test]
+    fn pool_errors_follow_the_params_they_are_given() {
+        let positions = [
+            position("4k3/8/8/8/8/8/PPP5/4K3 w - - 0 1", 1.0),
+            position("4k3/pp6/8/8/8/8/8/4K3 b - - 0 1", 0.0),
+            position("4k3/8/8/3p4/3P4/8/8/4K3 w - - 0 1", 0.5),
+        ];
+        let mut params = EvalParams::default();
+        let serial_error = |params: &EvalParams| {
+            positions
+                .iter()
+                .map(|position| {
+                    let evaluation = evaluation::evaluate_with(&position.board, params);
+                    (position.result - sigmoid(evaluation, 1.0)).powi(2)
+                })
+                .sum::<f64>()
+                / positions.len() as f64
+        };
+
+        thread::scope(|scope| {
+            let pool = ErrorPool::new(scope, &positions);
+            assert!((pool.error(&params, 1.0) - serial_error(&params)).abs() < 1e-12);
+
+            params.material[0].eg += 100;
+            assert!((pool.error(&params, 1.0) - serial_error(&params)).abs() < 1e-12);
+        });
+    }
+}
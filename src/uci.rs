@@ -0,0 +1,560 @@
+use std::{
+    io::{stdin, BufRead},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    board::Board,
+    book::Book,
+    colour::Colour,
+    moves::Move,
+    nnue::{self, Network},
+    params::{set_eval_param, set_eval_params, EvalParams, EVAL_PARAMS},
+    search::{score_to_mate, SearchFeature, SearchInfo, SearchLimits, SearchResult, Searcher},
+    syzygy, tablebase,
+    tt::TranspositionTable,
+    utils::Rng,
+    Result,
+};
+
+pub const ENGINE_NAME: &str = "rust-chess-engine";
+const ENGINE_AUTHOR: &str = "mohamedimrane";
+
+/// Time kept in reserve so the engine never loses on time because of I/O.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+pub const DEFAULT_MOVES_TO_GO: u32 = 30;
+const MAX_MULTI_PV: usize = 256;
+/// Bound on the value of an evaluation weight set through an option.
+const EVAL_PARAM_LIMIT: i32 = 10_000;
+
+struct Uci {
+    board: Board,
+    /// `None` while a search is running on `search_thread`.
+    searcher: Option<Searcher>,
+    search_thread: Option<JoinHandle<(Searcher, SearchResult)>>,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    multi_pv: usize,
+    /// Whether moves are played from `book` before searching.
+    own_book: bool,
+    book: Option<Book>,
+    rng: Rng,
+}
+
+/// Runs the UCI protocol on stdin/stdout until `quit` or end of input.
+pub fn run() -> Result<()> {
+    let mut uci = Uci::new();
+
+    uci.command("uci");
+
+    for line in stdin().lock().lines() {
+        if !uci.command(&line?) {
+            break;
+        }
+    }
+
+    uci.stop_search();
+
+    Ok(())
+}
+
+impl Uci {
+    fn new() -> Self {
+        let mut searcher = Searcher::new(TranspositionTable::DEFAULT_SIZE_MB);
+        searcher.set_info_callback(Box::new(print_info));
+
+        Self {
+            board: Board::new(),
+            stop: searcher.stop_handle(),
+            ponder: searcher.ponder_handle(),
+            running: searcher.running_handle(),
+            searcher: Some(searcher),
+            search_thread: None,
+            multi_pv: 1,
+            own_book: false,
+            book: None,
+            rng: Rng::from_time(),
+        }
+    }
+
+    /// Handles one line of input, returning `false` once the engine should exit.
+    fn command(&mut self, line: &str) -> bool {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        let Some((&command, args)) = tokens.split_first() else {
+            return true;
+        };
+
+        match command {
+            "uci" => {
+                println!("id name {}", ENGINE_NAME);
+                println!("id author {}", ENGINE_AUTHOR);
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    TranspositionTable::DEFAULT_SIZE_MB,
+                    TranspositionTable::MAX_SIZE_MB
+                );
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    Searcher::MAX_THREADS
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                println!("option name Ponder type check default false");
+                println!("option name CheckExtensions type check default true");
+                println!("option name SingularExtensions type check default true");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name UseNNUE type check default false");
+                println!("option name NNUEFile type string default <empty>");
+                println!("option name SyzygyPath type string default <empty>");
+                println!(
+                    "option name SyzygyProbeLimit type spin default {} min 0 max {}",
+                    syzygy::MAX_PIECES,
+                    syzygy::MAX_PIECES
+                );
+                println!("option name TablebasePath type string default <empty>");
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                print_eval_param_options();
+                println!("uciok");
+            }
+
+            "isready" => println!("readyok"),
+
+            "setoption" => {
+                if let Err(e) = self.set_option(args) {
+                    println!("info string {}", e);
+                }
+            }
+
+            "ucinewgame" => {
+                self.searcher().clear();
+                self.board = Board::new();
+            }
+
+            "position" => match parse_position(args) {
+                Ok(board) => {
+                    self.wait_for_search();
+                    self.board = board;
+                }
+                Err(e) => println!("info string invalid position: {}", e),
+            },
+
+            "go" => match parse_go(args, self.board.get_colour_to_move()) {
+                Ok(limits) => {
+                    let book_move = match limits.ponder {
+                        true => None,
+                        false => self.book_move(),
+                    };
+
+                    match book_move {
+                        Some(v_move) => println!("bestmove {}", Move::to_uci(&self.board, v_move)),
+                        None => self.start_search(SearchLimits {
+                            multi_pv: self.multi_pv,
+                            ..limits
+                        }),
+                    }
+                }
+                Err(e) => println!("info string invalid go command: {}", e),
+            },
+
+            // The opponent played the move we were pondering on, so the
+            // running search carries on with its limits now in force
+            "ponderhit" => self.ponder.store(false, Ordering::Relaxed),
+
+            "stop" => self.stop_search(),
+
+            "quit" => return false,
+
+            _ => println!("info string unknown command {}", command),
+        }
+
+        true
+    }
+
+    fn set_option(&mut self, args: &[&str]) -> Result<()> {
+        let name_index = args.iter().position(|&arg| arg == "name");
+        let value_index = args.iter().position(|&arg| arg == "value");
+        let name = match (name_index, value_index) {
+            (Some(name_index), Some(value_index)) if value_index < name_index => {
+                return Err("expected the option name before its value".into())
+            }
+            (Some(name_index), _) => {
+                args[name_index + 1..value_index.unwrap_or(args.len())].join(" ")
+            }
+            (None, _) => String::new(),
+        };
+        if name.is_empty() {
+            return Err("missing option name".into());
+        }
+        let value = value_index.map_or(String::new(), |index| args[index + 1..].join(" "));
+
+        if name.eq_ignore_ascii_case("multipv") {
+            self.multi_pv = value.parse::<usize>()?.clamp(1, MAX_MULTI_PV);
+            return Ok(());
+        }
+
+        let searcher = self.searcher();
+
+        match name.to_ascii_lowercase().as_str() {
+            // Pondering is driven by the GUI, the option only announces support for it
+            "ponder" => {}
+            "hash" => searcher.resize_tt(value.parse()?),
+            "threads" => searcher.set_threads(value.parse()?),
+            "usennue" => {
+                let enabled = value.parse()?;
+                nnue::set_enabled(enabled);
+
+                if enabled && !nnue::is_loaded() {
+                    println!("info string no network loaded, using the hand-crafted evaluation");
+                }
+            }
+            "nnuefile" => match value.as_str() {
+                "" | "<empty>" => nnue::set_network(None),
+                path => nnue::set_network(Some(Network::load(path)?)),
+            },
+            "syzygypath" => {
+                let paths = match value.as_str() {
+                    "<empty>" => "",
+                    paths => paths,
+                };
+                let tables = syzygy::init(paths);
+                println!("info string found {} syzygy tables", tables);
+            }
+            "syzygyprobelimit" => {
+                syzygy::set_probe_limit(value.parse::<usize>()?.min(syzygy::MAX_PIECES))
+            }
+            "tablebasepath" => match value.as_str() {
+                "" | "<empty>" => tablebase::clear(),
+                path => {
+                    tablebase::clear();
+                    let tables = tablebase::load(path)?;
+                    println!("info string found {} tablebases", tables);
+                }
+            },
+            "ownbook" => self.own_book = value.parse()?,
+            "bookfile" => {
+                self.book = match value.as_str() {
+                    "" | "<empty>" => None,
+                    path => Some(Book::load(path)?),
+                }
+            }
+            "evalfile" => match value.as_str() {
+                "" | "<empty>" => set_eval_params(EvalParams::default()),
+                path => set_eval_params(EvalParams::load(path)?),
+            },
+            option_name => match SearchFeature::from_name(option_name) {
+                Some(feature) => searcher.set_feature(feature, value.parse()?),
+                None => set_eval_param(
+                    option_name,
+                    value
+                        .parse::<i32>()?
+                        .clamp(-EVAL_PARAM_LIMIT, EVAL_PARAM_LIMIT),
+                )?,
+            },
+        }
+
+        Ok(())
+    }
+
+    /// A move from the book for the current position, when `OwnBook` is on.
+    fn book_move(&mut self) -> Option<u16> {
+        if !self.own_book {
+            return None;
+        }
+
+        self.book.as_ref()?.pick(&mut self.board, &mut self.rng)
+    }
+
+    fn start_search(&mut self, limits: SearchLimits) {
+        let mut searcher = self.take_searcher();
+        let mut board = self.board.clone();
+
+        let search_thread = thread::spawn(move || {
+            let result = searcher.search(&mut board, limits);
+            let ponder_move = searcher.ponder_move(&board, &result);
+            print_result(&board, &result, ponder_move);
+
+            (searcher, result)
+        });
+
+        // A stop or ponder hit read before the search reset its flags would be lost
+        while !self.running.load(Ordering::Acquire) && !search_thread.is_finished() {
+            thread::yield_now();
+        }
+
+        self.search_thread = Some(search_thread);
+    }
+
+    /// Stops the running search, if it has not already sent its best move.
+    fn stop_search(&mut self) {
+        if let Some(search_thread) = &self.search_thread {
+            if !search_thread.is_finished() {
+                self.ponder.store(false, Ordering::Relaxed);
+                self.stop.store(true, Ordering::Relaxed);
+            }
+        }
+
+        self.wait_for_search();
+    }
+
+    /// Waits for the running search, returning its result.
+    fn wait_for_search(&mut self) -> Option<SearchResult> {
+        let search_thread = self.search_thread.take()?;
+        let (searcher, result) = search_thread.join().expect("search thread panicked");
+        self.searcher = Some(searcher);
+
+        Some(result)
+    }
+
+    /// The searcher, waiting for the running search to finish first.
+    fn searcher(&mut self) -> &mut Searcher {
+        self.wait_for_search();
+        self.searcher
+            .as_mut()
+            .expect("searcher is only taken while searching")
+    }
+
+    fn take_searcher(&mut self) -> Searcher {
+        self.wait_for_search();
+        self.searcher
+            .take()
+            .expect("searcher is only taken while searching")
+    }
+}
+
+/// Every evaluation term that is not a table as a spin option, so that it
+/// can be tuned from the GUI. The tables are set with `EvalFile`.
+fn print_eval_param_options() {
+    let params = EVAL_PARAMS.read().unwrap();
+
+    for (name, value) in params.scalar_values() {
+        println!(
+            "option name {} type spin default {} min {} max {}",
+            name, value, -EVAL_PARAM_LIMIT, EVAL_PARAM_LIMIT
+        );
+    }
+}
+
+fn parse_position(args: &[&str]) -> Result<Board> {
+    let moves_index = args.iter().position(|&arg| arg == "moves");
+    let (position, moves) = args.split_at(moves_index.unwrap_or(args.len()));
+
+    let mut board = match position {
+        ["startpos"] => Board::new(),
+        ["fen", fen @ ..] => Board::from_fen(&fen.join(" "))?,
+        _ => return Err("expected startpos or fen".into()),
+    };
+
+    for move_string in moves.iter().skip(1) {
+        let v_move = Move::from_uci(&mut board, move_string)
+            .ok_or(format!("illegal move {}", move_string))?;
+        board.make_move(v_move);
+    }
+
+    Ok(board)
+}
+
+fn parse_go(args: &[&str], colour: bool) -> Result<SearchLimits> {
+    let mut limits = SearchLimits::default();
+
+    let mut time = None;
+    let mut increment = 0;
+    let mut moves_to_go = DEFAULT_MOVES_TO_GO;
+
+    let (time_name, increment_name) = match colour {
+        Colour::White => ("wtime", "winc"),
+        Colour::Black => ("btime", "binc"),
+    };
+
+    let mut args = args.iter();
+    while let Some(&name) = args.next() {
+        if name == "infinite" {
+            continue;
+        }
+        if name == "ponder" {
+            limits.ponder = true;
+            continue;
+        }
+
+        let value = args.next().ok_or(format!("missing value for {}", name))?;
+
+        match name {
+            "depth" => limits.depth = Some(value.parse()?),
+            "nodes" => limits.nodes = Some(value.parse()?),
+            "movetime" => limits.movetime = Some(Duration::from_millis(value.parse()?)),
+            "movestogo" => moves_to_go = value.parse::<u32>()?.max(1),
+            name if name == time_name => time = Some(value.parse::<u64>()?),
+            name if name == increment_name => increment = value.parse::<u64>()?,
+            _ => {}
+        }
+    }
+
+    if let Some(time) = time {
+        limits.movetime = Some(time_budget(
+            Duration::from_millis(time),
+            Duration::from_millis(increment),
+            moves_to_go,
+        ));
+    }
+
+    Ok(limits)
+}
+
+/// How long to think on a move given the time left on the clock.
+pub fn time_budget(time: Duration, increment: Duration, moves_to_go: u32) -> Duration {
+    let time = time.saturating_sub(MOVE_OVERHEAD);
+    let budget = time / moves_to_go.max(1) + increment * 3 / 4;
+
+    budget.min(time / 2).max(Duration::from_millis(1))
+}
+
+fn print_info(board: &Board, info: &SearchInfo) {
+    match info {
+        SearchInfo::Iteration(info) => println!(
+            "info depth {} seldepth {} multipv {} score {} nodes {} nps {} hashfull {} tbhits {} time {} pv {}",
+            info.depth,
+            info.seldepth,
+            info.multi_pv,
+            format_score(info.score),
+            info.nodes,
+            info.nps,
+            info.hashfull,
+            info.tb_hits,
+            info.time.as_millis(),
+            Move::line_to_uci(board, &info.pv).join(" ")
+        ),
+
+        SearchInfo::CurrentMove {
+            depth,
+            v_move,
+            number,
+        } => println!(
+            "info depth {} currmove {} currmovenumber {}",
+            depth,
+            Move::to_uci(board, *v_move),
+            number
+        ),
+    }
+}
+
+fn print_result(board: &Board, result: &SearchResult, ponder_move: Option<u16>) {
+    let Some(best_move) = result.best_move else {
+        println!("bestmove 0000");
+        return;
+    };
+
+    match ponder_move {
+        Some(ponder_move) => {
+            let ponder_move = Move::line_to_uci(board, &[best_move, ponder_move])
+                .pop()
+                .unwrap();
+            println!(
+                "bestmove {} ponder {}",
+                Move::to_uci(board, best_move),
+                ponder_move
+            );
+        }
+        None => println!("bestmove {}", Move::to_uci(board, best_move)),
+    }
+}
+
+fn format_score(score: i32) -> String {
+    if let Some(moves_to_mate) = score_to_mate(score) {
+        return format!("mate {}", moves_to_mate);
+    }
+
+    format!("cp {}", score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// White mates with Qd8, and only with Qd8.
+    const MATE_IN_ONE: &str = "position fen 6k1/5ppp/8/8/8/8/8/3QK3 w - - 0 1";
+
+    fn wait_until_finished(uci: &Uci) {
+        while !uci.search_thread.as_ref().unwrap().is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn assert_searches_normally(uci: &mut Uci) {
+        uci.command(MATE_IN_ONE);
+        uci.command("go depth 6");
+        let result = uci.wait_for_search().unwrap();
+
+        assert_eq!(result.depth, 6);
+        assert_eq!(Move::to_uci(&uci.board, result.best_move.unwrap()), "d1d8");
+    }
+
+    #[test]
+    fn stop_after_bestmove_does_not_leak_into_next_search() {
+        let mut uci = Uci::new();
+
+        uci.command("position startpos");
+        uci.command("go depth 3");
+        wait_until_finished(&uci);
+        uci.command("stop");
+        // As left by a stop read just before the search finished
+        uci.stop.store(true, Ordering::Relaxed);
+
+        assert_searches_normally(&mut uci);
+    }
+
+    #[test]
+    fn late_ponderhit_and_stop_do_not_leak_into_next_search() {
+        let mut uci = Uci::new();
+
+        uci.command("position startpos");
+        uci.command("go ponder depth 3");
+        uci.command("ponderhit");
+        wait_until_finished(&uci);
+        uci.command("stop");
+        uci.command("ponderhit");
+        // As left by a late stop and a ponder search started but never hit
+        uci.stop.store(true, Ordering::Relaxed);
+        uci.ponder.store(true, Ordering::Relaxed);
+
+        assert_searches_normally(&mut uci);
+    }
+
+    #[test]
+    fn stop_right_after_go_ponder_ends_the_search() {
+        let mut uci = Uci::new();
+
+        uci.command("position startpos");
+        uci.command("go ponder");
+        uci.command("stop");
+
+        assert!(uci.search_thread.is_none());
+        assert!(uci.searcher.is_some());
+    }
+
+    #[test]
+    fn setoption_needs_a_name_before_the_value() {
+        let mut uci = Uci::new();
+
+        for args in [
+            "value 3",
+            "",
+            "name",
+            "name value 3",
+            "value 3 name MultiPV",
+        ] {
+            let args = args.split_whitespace().collect::<Vec<_>>();
+            assert!(uci.set_option(&args).is_err(), "{args:?}");
+        }
+
+        uci.set_option(&["name", "MultiPV", "value", "3"]).unwrap();
+        assert_eq!(uci.multi_pv, 3);
+    }
+}
@@ -1,7 +1,6 @@
-use crate::{
-    errors::{MoveError, SquareParsingError},
-    Result,
-};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{errors::SquareParsingError, Result};
 
 const FILE_LETTERS: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
 
@@ -19,13 +18,6 @@ pub fn string_to_square(string: &str) -> Result<u8> {
     Ok(square)
 }
 
-pub fn get_file_letter(file: u16) -> Result<char> {
-    FILE_LETTERS
-        .get(file as usize)
-        .copied()
-        .ok_or(Box::new(MoveError::InvalidFile))
-}
-
 pub fn get_file_number(file: char) -> Result<u16> {
     FILE_LETTERS
         .iter()
@@ -40,3 +32,42 @@ pub fn square_to_coods(square: u16) -> (u16, u16) {
 
     (file as u16, rank as u16)
 }
+
+pub fn square_to_string(square: u8) -> String {
+    let (file, rank) = square_to_coods(square as u16);
+
+    format!("{}{}", FILE_LETTERS[file as usize], rank + 1)
+}
+
+/// A small xorshift64* generator, fast and good enough for keys and openings.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// `seed` must not be zero.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A generator seeded from the clock, different on every run.
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |time| time.as_nanos() as u64);
+
+        Self::new(seed | 1)
+    }
+
+    /// A number in `0..bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
@@ -0,0 +1,386 @@
+use std::{
+    io::{stdin, BufRead},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    board::Board,
+    moves::Move,
+    search::{score_to_mate, SearchInfo, SearchLimits, Searcher},
+    tt::TranspositionTable,
+    uci::{time_budget, DEFAULT_MOVES_TO_GO, ENGINE_NAME},
+    Result,
+};
+
+/// XBoard reports mate scores as 100000 plus the number of moves to mate.
+const MATE_REPORT_BASE: i32 = 100_000;
+
+struct XBoard {
+    board: Board,
+    /// `None` while a search is running on `search_thread`.
+    searcher: Option<Searcher>,
+    /// Searches the engine's move, returning the move it sent, if any.
+    search_thread: Option<JoinHandle<(Searcher, Option<u16>)>>,
+    stop: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    /// Set when the running search should end without sending its move.
+    discard: Arc<AtomicBool>,
+    post: Arc<AtomicBool>,
+    /// In force mode the engine only tracks the moves played, without replying.
+    force: bool,
+    engine_colour: bool,
+    depth: Option<i32>,
+    /// Fixed time per move set by `st`, overriding the clock.
+    time_per_move: Option<Duration>,
+    clock: Option<Duration>,
+    increment: Duration,
+    moves_per_session: u32,
+}
+
+/// Runs the XBoard/CECP protocol on stdin/stdout until `quit` or end of input.
+pub fn run() -> Result<()> {
+    let mut xboard = XBoard::new();
+
+    for line in stdin().lock().lines() {
+        if !xboard.command(&line?) {
+            break;
+        }
+    }
+
+    xboard.abandon_search();
+
+    Ok(())
+}
+
+impl XBoard {
+    fn new() -> Self {
+        let post = Arc::new(AtomicBool::new(true));
+
+        let mut searcher = Searcher::new(TranspositionTable::DEFAULT_SIZE_MB);
+        let post_handle = Arc::clone(&post);
+        searcher.set_info_callback(Box::new(move |board, info| {
+            if post_handle.load(Ordering::Relaxed) {
+                print_thinking(board, info);
+            }
+        }));
+
+        let board = Board::new();
+        Self {
+            engine_colour: !board.get_colour_to_move(),
+            board,
+            stop: searcher.stop_handle(),
+            running: searcher.running_handle(),
+            searcher: Some(searcher),
+            search_thread: None,
+            discard: Arc::new(AtomicBool::new(false)),
+            post,
+            force: false,
+            depth: None,
+            time_per_move: None,
+            clock: None,
+            increment: Duration::ZERO,
+            moves_per_session: 0,
+        }
+    }
+
+    /// Handles one line of input, returning `false` once the engine should exit.
+    fn command(&mut self, line: &str) -> bool {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        let Some((&command, args)) = tokens.split_first() else {
+            return true;
+        };
+
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+            | "otim" => {}
+
+            "protover" => println!(
+                "feature myname=\"{}\" setboard=1 usermove=1 ping=1 playother=1 colors=0 \
+                 sigint=0 sigterm=0 done=1",
+                ENGINE_NAME
+            ),
+
+            "?" => self.move_now(),
+
+            "new" => {
+                self.abandon_search();
+                self.board = Board::new();
+                self.engine_colour = !self.board.get_colour_to_move();
+                self.force = false;
+                self.depth = None;
+                self.time_per_move = None;
+                self.searcher().clear();
+            }
+
+            "force" | "result" => {
+                self.abandon_search();
+                self.force = true;
+            }
+
+            "go" => {
+                self.wait_for_search();
+                self.force = false;
+                self.engine_colour = self.board.get_colour_to_move();
+                self.think();
+            }
+
+            "playother" => {
+                self.wait_for_search();
+                self.force = false;
+                self.engine_colour = !self.board.get_colour_to_move();
+            }
+
+            "usermove" => self.user_move(args.first().copied().unwrap_or_default()),
+
+            "setboard" => {
+                self.wait_for_search();
+                match Board::from_fen(&args.join(" ")) {
+                    Ok(board) => self.board = board,
+                    Err(e) => println!("tellusererror Illegal position: {}", e),
+                }
+            }
+
+            "undo" => {
+                self.wait_for_search();
+                let _ = self.board.undo_move();
+            }
+
+            "remove" => {
+                self.wait_for_search();
+                let _ = self.board.undo_move();
+                let _ = self.board.undo_move();
+            }
+
+            "level" => self.set_level(args),
+            "st" => self.time_per_move = parse_arg(args).map(Duration::from_secs),
+            "sd" => self.depth = parse_arg(args),
+            "time" => self.clock = parse_arg(args).map(|cs: u64| Duration::from_millis(cs * 10)),
+
+            "ping" => println!("pong {}", args.first().copied().unwrap_or_default()),
+
+            "post" => self.post.store(true, Ordering::Relaxed),
+            "nopost" => self.post.store(false, Ordering::Relaxed),
+
+            "quit" => return false,
+
+            _ => println!("Error (unknown command): {}", command),
+        }
+
+        true
+    }
+
+    fn user_move(&mut self, move_string: &str) {
+        self.wait_for_search();
+
+        let Some(v_move) = Move::from_uci(&mut self.board, move_string) else {
+            println!("Illegal move: {}", move_string);
+            return;
+        };
+
+        self.board.make_move(v_move);
+
+        if !self.force && self.board.get_colour_to_move() == self.engine_colour {
+            self.think();
+        }
+    }
+
+    /// Searches the current position on another thread, which sends the best
+    /// move found. The move is played on the board once the search is waited for.
+    fn think(&mut self) {
+        let limits = SearchLimits {
+            depth: self.depth,
+            movetime: self.time_per_move.or(self
+                .clock
+                .map(|clock| time_budget(clock, self.increment, self.moves_to_go()))),
+            ..Default::default()
+        };
+
+        let mut searcher = self.take_searcher();
+        let mut board = self.board.clone();
+        let discard = Arc::clone(&self.discard);
+        discard.store(false, Ordering::Relaxed);
+
+        let search_thread = thread::spawn(move || {
+            let result = searcher.search(&mut board, limits);
+
+            if discard.load(Ordering::Relaxed) {
+                return (searcher, None);
+            }
+
+            match result.best_move {
+                Some(best_move) => println!("move {}", Move::to_uci(&board, best_move)),
+                None if board.is_in_check() => println!("resign"),
+                None => println!("1/2-1/2 {{Stalemate}}"),
+            }
+
+            (searcher, result.best_move)
+        });
+
+        // A stop read before the search reset its flag would be lost
+        while !self.running.load(Ordering::Acquire) && !search_thread.is_finished() {
+            thread::yield_now();
+        }
+
+        self.search_thread = Some(search_thread);
+    }
+
+    /// Makes the running search send the best move found so far.
+    fn move_now(&mut self) {
+        if let Some(search_thread) = &self.search_thread {
+            if !search_thread.is_finished() {
+                self.stop.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Stops the running search without sending its move, unless it was
+    /// already sent, in which case it is still played.
+    fn abandon_search(&mut self) {
+        if let Some(search_thread) = &self.search_thread {
+            if !search_thread.is_finished() {
+                self.discard.store(true, Ordering::Relaxed);
+                self.stop.store(true, Ordering::Relaxed);
+            }
+        }
+
+        self.wait_for_search();
+    }
+
+    /// Waits for the running search and plays the move it sent.
+    fn wait_for_search(&mut self) {
+        let Some(search_thread) = self.search_thread.take() else {
+            return;
+        };
+
+        let (searcher, best_move) = search_thread.join().expect("search thread panicked");
+        self.searcher = Some(searcher);
+
+        if let Some(best_move) = best_move {
+            self.board.make_move(best_move);
+        }
+    }
+
+    /// The searcher, waiting for the running search to finish first.
+    fn searcher(&mut self) -> &mut Searcher {
+        self.wait_for_search();
+        self.searcher
+            .as_mut()
+            .expect("searcher is only taken while searching")
+    }
+
+    fn take_searcher(&mut self) -> Searcher {
+        self.wait_for_search();
+        self.searcher
+            .take()
+            .expect("searcher is only taken while searching")
+    }
+
+    /// `level MPS BASE INC`, where BASE is minutes or minutes:seconds.
+    fn set_level(&mut self, args: &[&str]) {
+        if let [moves_per_session, _, increment] = args {
+            self.moves_per_session = moves_per_session.parse().unwrap_or(0);
+            self.increment = increment
+                .parse()
+                .map_or(Duration::ZERO, Duration::from_secs_f64);
+        }
+    }
+
+    fn moves_to_go(&self) -> u32 {
+        if self.moves_per_session == 0 {
+            return DEFAULT_MOVES_TO_GO;
+        }
+
+        let moves_played = self.board.get_fullmove_number() as u32 - 1;
+        self.moves_per_session - moves_played % self.moves_per_session
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(args: &[&str]) -> Option<T> {
+    args.first().and_then(|arg| arg.parse().ok())
+}
+
+/// Thinking output: ply, score, time in centiseconds, nodes and the principal variation.
+fn print_thinking(board: &Board, info: &SearchInfo) {
+    let SearchInfo::Iteration(info) = info else {
+        return;
+    };
+
+    if info.multi_pv != 1 {
+        return;
+    }
+
+    let score = match score_to_mate(info.score) {
+        Some(moves_to_mate) => (MATE_REPORT_BASE + moves_to_mate.abs()) * moves_to_mate.signum(),
+        None => info.score,
+    };
+
+    println!(
+        "{} {} {} {} {}",
+        info.depth,
+        score,
+        info.time.as_millis() / 10,
+        info.nodes,
+        Move::line_to_uci(board, &info.pv).join(" ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thinking_for_a_minute() -> XBoard {
+        let mut xboard = XBoard::new();
+
+        xboard.command("nopost");
+        xboard.command("st 60");
+        xboard.command("go");
+
+        xboard
+    }
+
+    #[test]
+    fn move_now_plays_the_best_move_so_far() {
+        let mut xboard = thinking_for_a_minute();
+
+        xboard.command("?");
+        xboard.wait_for_search();
+
+        assert_eq!(xboard.board.played_moves().len(), 1);
+    }
+
+    #[test]
+    fn force_stops_thinking_without_moving() {
+        let mut xboard = thinking_for_a_minute();
+
+        xboard.command("force");
+
+        assert!(xboard.search_thread.is_none());
+        assert!(xboard.board.played_moves().is_empty());
+    }
+
+    #[test]
+    fn new_stops_thinking_and_resets_the_time_per_move() {
+        let mut xboard = thinking_for_a_minute();
+
+        xboard.command("new");
+
+        assert!(xboard.search_thread.is_none());
+        assert!(xboard.board.played_moves().is_empty());
+        assert_eq!(xboard.time_per_move, None);
+    }
+
+    #[test]
+    fn quit_stops_thinking() {
+        let mut xboard = thinking_for_a_minute();
+
+        assert!(!xboard.command("quit"));
+        xboard.abandon_search();
+
+        assert!(xboard.board.played_moves().is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use lazy_static::lazy_static;
+
+use crate::{castling_rights::CastlingRights, piece::Piece, utils::Rng};
+
+lazy_static! {
+    pub static ref ZOBRIST: Zobrist = Zobrist::new(0x9E37_79B9_7F4A_7C15);
+}
+
+/// Random keys used to hash a position, one per (piece, square) pair plus
+/// the side to move, each castling right and each en passant file.
+pub struct Zobrist {
+    pieces: [[u64; 64]; 12],
+    pub black_to_move: u64,
+    castling: [u64; 4],
+    pub en_passant: [u64; 8],
+}
+
+impl Zobrist {
+    fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut next = || rng.next_u64();
+
+        let mut pieces = [[0; 64]; 12];
+        for keys in pieces.iter_mut() {
+            for key in keys.iter_mut() {
+                *key = next();
+            }
+        }
+
+        let black_to_move = next();
+        let castling = [next(), next(), next(), next()];
+        let en_passant = [
+            next(),
+            next(),
+            next(),
+            next(),
+            next(),
+            next(),
+            next(),
+            next(),
+        ];
+
+        Self {
+            pieces,
+            black_to_move,
+            castling,
+            en_passant,
+        }
+    }
+
+    pub fn piece(&self, piece: u8, square: usize) -> u64 {
+        self.pieces[piece_index(piece)][square]
+    }
+
+    pub fn castling(&self, rights: u8) -> u64 {
+        use CastlingRights as CR;
+
+        [
+            CR::WhiteCanShortCastle,
+            CR::WhiteCanLongCastle,
+            CR::BlackCanShortCastle,
+            CR::BlackCanLongCastle,
+        ]
+        .iter()
+        .zip(self.castling)
+        .filter(|(&right, _)| rights & right == right)
+        .fold(0, |hash, (_, key)| hash ^ key)
+    }
+}
+
+/// Index of a piece in `0..12`: white pieces first, then black, each by type.
+pub fn piece_index(piece: u8) -> usize {
+    let colour_offset = if Piece::is_colour(piece, Piece::White) {
+        0
+    } else {
+        6
+    };
+
+    colour_offset + Piece::piece_type(piece) as usize - 1
+}
@@ -0,0 +1,316 @@
+#!/usr/bin/env python3
+"""Writes KQvK.rtbw and KQvK.rtbz next to this script for the syzygy tests.
+
+The results come from a retrograde analysis of king and queen against king
+done here, independently of the engine, and are stored in the Syzygy file
+layout: the WDL table for both sides to move, the DTZ table for white to
+move only, in full moves, as the original generator stores it. Every value
+is written with a fixed-length code, which is a valid canonical Huffman
+code with a single length and no symbol pairs.
+"""
+
+import os
+import struct
+from collections import deque
+
+WDL_MAGIC = 0x5D23E871
+DTZ_MAGIC = 0xA50C66D7
+
+KING, QUEEN = 6, 5
+BLACK = 8
+# Table pieces in index order: the white king, the white queen, the black king
+PIECES = [KING, QUEEN, KING | BLACK]
+
+BLOCK_SIZE_BITS = 10
+SPAN_BITS = 6
+SINGLE_VALUE = 128
+
+KING_STEPS = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)]
+
+
+def file_of(square):
+    return square % 8
+
+
+def rank_of(square):
+    return square // 8
+
+
+def king_moves(square):
+    for df, dr in KING_STEPS:
+        f, r = file_of(square) + df, rank_of(square) + dr
+        if 0 <= f < 8 and 0 <= r < 8:
+            yield r * 8 + f
+
+
+def queen_moves(square, blockers):
+    for df, dr in KING_STEPS:
+        f, r = file_of(square) + df, rank_of(square) + dr
+        while 0 <= f < 8 and 0 <= r < 8:
+            target = r * 8 + f
+            if target in blockers:
+                break
+            yield target
+            f, r = f + df, r + dr
+
+
+def adjacent(a, b):
+    return max(abs(file_of(a) - file_of(b)), abs(rank_of(a) - rank_of(b))) <= 1
+
+
+def attacked_by_white(square, white_king, queen, black_king):
+    """Whether white attacks `square`, the black king having left its square."""
+    if adjacent(square, white_king):
+        return True
+    if queen is None or square == queen:
+        return False
+    blockers = {white_king}
+    return square in set(queen_moves(queen, blockers))
+
+
+def legal(white_king, queen, black_king, white_to_move):
+    if len({white_king, queen, black_king}) != 3 or adjacent(white_king, black_king):
+        return False
+    # The side not to move may not be in check
+    return not (white_to_move and attacked_by_white(black_king, white_king, queen, black_king))
+
+
+def black_moves(white_king, queen, black_king):
+    """Black's legal moves, each as the new king square and whether it takes the queen."""
+    for target in king_moves(black_king):
+        if target == white_king or adjacent(target, white_king):
+            continue
+        if target == queen:
+            # Taking the queen is legal only when its king does not defend it
+            yield target, True
+            continue
+        if not attacked_by_white(target, white_king, queen, black_king):
+            yield target, False
+
+
+def analyse():
+    """Plies to mate of every won position, keyed by (wk, q, bk, white to move).
+
+    Positions missing from the result are draws, or illegal.
+    """
+    plies = {}
+    moves_left = {}
+    queue = deque()
+
+    for white_king in range(64):
+        for queen in range(64):
+            for black_king in range(64):
+                if not legal(white_king, queen, black_king, False):
+                    continue
+
+                moves = list(black_moves(white_king, queen, black_king))
+                if any(capture for _, capture in moves):
+                    continue
+
+                in_check = attacked_by_white(black_king, white_king, queen, black_king)
+                if not moves and in_check:
+                    plies[(white_king, queen, black_king, False)] = 0
+                    queue.append((white_king, queen, black_king))
+                elif moves:
+                    moves_left[(white_king, queen, black_king)] = len(moves)
+
+    # Black lost positions in order of distance, white wins found from them
+    while queue:
+        white_king, queen, black_king = queue.popleft()
+        distance = plies[(white_king, queen, black_king, False)]
+
+        # White positions moving into this one: any white piece stepping back
+        predecessors = [
+            (square, queen) for square in king_moves(white_king) if square != queen
+        ] + [(white_king, square) for square in queen_moves(queen, {white_king, black_king})]
+
+        for previous_king, previous_queen in predecessors:
+            key = (previous_king, previous_queen, black_king, True)
+            if key in plies or not legal(previous_king, previous_queen, black_king, True):
+                continue
+            plies[key] = distance + 1
+
+            # Black positions whose every move now loses
+            for square in king_moves(black_king):
+                black_key = (previous_king, previous_queen, square)
+                if black_key not in moves_left:
+                    continue
+                if (black_king, False) not in {
+                    (target, capture)
+                    for target, capture in black_moves(previous_king, previous_queen, square)
+                }:
+                    continue
+
+                moves_left[black_key] -= 1
+                if moves_left[black_key] == 0:
+                    del moves_left[black_key]
+                    plies[black_key + (False,)] = distance + 2
+                    queue.append(black_key)
+
+    return plies
+
+
+def off_diagonal(square):
+    return rank_of(square) - file_of(square)
+
+
+def flip_diagonal(square):
+    return ((square >> 3) | (square << 3)) & 63
+
+
+def triangle_codes():
+    triangle = [square for square in range(28) if file_of(square) <= 3]
+    below = [square for square in triangle if off_diagonal(square) < 0]
+    diagonal = [square for square in triangle if off_diagonal(square) == 0]
+    return {square: code for code, square in enumerate(below + diagonal)}
+
+
+MAP_A1D1D4 = triangle_codes()
+MAP_B1H1H7 = {
+    square: code
+    for code, square in enumerate(s for s in range(64) if off_diagonal(s) < 0)
+}
+TABLE_SIZE = 31332
+
+
+def index(squares):
+    """The index of three unique pieces, normalised by the board's symmetries."""
+    squares = list(squares)
+    if file_of(squares[0]) > 3:
+        squares = [square ^ 7 for square in squares]
+    if rank_of(squares[0]) > 3:
+        squares = [square ^ 56 for square in squares]
+    for i in range(3):
+        if off_diagonal(squares[i]) == 0:
+            continue
+        if off_diagonal(squares[i]) > 0:
+            squares[i:] = [flip_diagonal(square) for square in squares[i:]]
+        break
+
+    s0, s1, s2 = squares
+    adjust1 = int(s1 > s0)
+    adjust2 = int(s2 > s0) + int(s2 > s1)
+
+    if off_diagonal(s0) != 0:
+        return (MAP_A1D1D4[s0] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+    if off_diagonal(s1) != 0:
+        return (6 * 63 + rank_of(s0) * 28 + MAP_B1H1H7[s1]) * 62 + s2 - adjust2
+    if off_diagonal(s2) != 0:
+        return (
+            6 * 63 * 62
+            + 4 * 28 * 62
+            + rank_of(s0) * 7 * 28
+            + (rank_of(s1) - adjust1) * 28
+            + MAP_B1H1H7[s2]
+        )
+    return (
+        6 * 63 * 62
+        + 4 * 28 * 62
+        + 4 * 7 * 28
+        + rank_of(s0) * 7 * 6
+        + (rank_of(s1) - adjust1) * 6
+        + (rank_of(s2) - adjust2)
+    )
+
+
+def fill(value_of, white_to_move):
+    """Stored values by index, illegal positions taking the commonest value."""
+    values = [None] * TABLE_SIZE
+    for white_king in range(64):
+        for queen in range(64):
+            for black_king in range(64):
+                if not legal(white_king, queen, black_king, white_to_move):
+                    continue
+                value = value_of(white_king, queen, black_king)
+                position = index((white_king, queen, black_king))
+                assert values[position] in (None, value), "symmetric positions disagree"
+                values[position] = value
+
+    known = [value for value in values if value is not None]
+    common = max(set(known), key=known.count)
+    return [common if value is None else value for value in values]
+
+
+def pairs_header(values):
+    """The Huffman header of one table and its sparse index, block lengths and blocks."""
+    symbols = sorted(set(values))
+    if len(symbols) == 1:
+        return struct.pack("<BB", SINGLE_VALUE, symbols[0]), b"", b"", []
+
+    length = max(1, (len(symbols) - 1).bit_length())
+    code = {symbol: number for number, symbol in enumerate(symbols)}
+
+    # Room is kept at the end of each block for the decoder reading ahead
+    block_size = 1 << BLOCK_SIZE_BITS
+    per_block = (block_size * 8 - 64) // length
+    blocks = [values[start : start + per_block] for start in range(0, len(values), per_block)]
+
+    header = struct.pack("<BBBBIBB", 0, BLOCK_SIZE_BITS, SPAN_BITS, 0, len(blocks), length, length)
+    header += struct.pack("<H", 0)  # Lowest symbol of the only code length
+    header += struct.pack("<H", len(symbols))
+    for symbol in symbols:
+        # A leaf: the value, then 0xFFF in place of a second symbol
+        header += bytes([symbol & 0xFF, ((symbol >> 8) & 0xF) | 0xF0, 0xFF])
+    header += b"\0" * (len(symbols) & 1)
+
+    span = 1 << SPAN_BITS
+    sparse_index = b""
+    for entry in range((len(values) + span - 1) // span):
+        middle = entry * span + span // 2
+        block = min(middle // per_block, len(blocks) - 1)
+        sparse_index += struct.pack("<IH", block, middle - block * per_block)
+
+    block_lengths = b"".join(struct.pack("<H", len(block) - 1) for block in blocks)
+
+    data = []
+    for block in blocks:
+        bits = "".join(format(code[value], "0{}b".format(length)) for value in block)
+        bits += "0" * (block_size * 8 - len(bits))
+        data.append(int(bits, 2).to_bytes(block_size, "big"))
+
+    return header, sparse_index, block_lengths, data
+
+
+def write_table(path, magic, flags, tables, dtz):
+    contents = bytearray(struct.pack("<IB", magic, flags))
+    contents.append(0)  # Both sides start with the leading group
+    for piece in PIECES:
+        contents.append(piece | piece << 4)
+    if len(contents) & 1:
+        contents.append(0)
+
+    parts = [pairs_header(values) for values in tables]
+    for header, _, _, _ in parts:
+        contents += header
+    # The DTZ map, empty here, ends on an even offset
+    if dtz and len(contents) & 1:
+        contents.append(0)
+    for _, sparse_index, _, _ in parts:
+        contents += sparse_index
+    for _, _, block_lengths, _ in parts:
+        contents += block_lengths
+    for _, _, _, data in parts:
+        contents += b"\0" * (-len(contents) % 64)
+        for block in data:
+            contents += block
+
+    with open(path, "wb") as file:
+        file.write(contents)
+
+
+def main():
+    plies = analyse()
+    directory = os.path.dirname(os.path.abspath(__file__))
+
+    # WDL values are stored plus two: 4 for a win, 2 for a draw, 0 for a loss
+    white_wdl = fill(lambda wk, q, bk: 4 if (wk, q, bk, True) in plies else 2, True)
+    black_wdl = fill(lambda wk, q, bk: 0 if (wk, q, bk, False) in plies else 2, False)
+    write_table(os.path.join(directory, "KQvK.rtbw"), WDL_MAGIC, 1, [white_wdl, black_wdl], False)
+
+    # Wins take an odd number of plies, stored as whole moves before the last one
+    white_dtz = fill(lambda wk, q, bk: (plies.get((wk, q, bk, True), 1) - 1) // 2, True)
+    write_table(os.path.join(directory, "KQvK.rtbz"), DTZ_MAGIC, 0, [white_dtz], True)
+
+
+if __name__ == "__main__":
+    main()